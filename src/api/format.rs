@@ -0,0 +1,145 @@
+use crate::util::parse;
+use anyhow::Context as _;
+use axum::{
+    Json,
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{ACCEPT, CONTENT_TYPE},
+    },
+    response::{IntoResponse, Response},
+};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// Content type of CSV responses
+const TEXT_CSV: &str = "text/csv; charset=utf-8";
+
+/// Error answered to an API request
+#[derive(Debug)]
+pub(crate) enum ApiError {
+    /// The request is malformed
+    BadRequest(String),
+    /// The node is not part of the deployment
+    NotFound(String),
+    /// The stored telemetry is unavailable without the database
+    Unavailable,
+    /// The database query or the encoding failed
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Internal(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg).into_response(),
+            Self::NotFound(msg) => (StatusCode::NOT_FOUND, msg).into_response(),
+            Self::Unavailable => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "stored telemetry is unavailable without PostgreSQL",
+            )
+                .into_response(),
+            Self::Internal(e) => {
+                tracing::error!(e = format!("{e:#}"), "API request failed");
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal error").into_response()
+            }
+        }
+    }
+}
+
+/// Response body format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// `application/json`, the default
+    Json,
+    /// `text/csv` with a header row
+    Csv,
+}
+
+impl Format {
+    /// Picks the format from a `format` query parameter, then from the `Accept` header
+    pub(crate) fn negotiate(param: Option<&str>, headers: &HeaderMap) -> Result<Self, ApiError> {
+        match param {
+            Some("json") => Ok(Self::Json),
+            Some("csv") => Ok(Self::Csv),
+            Some(other) => Err(ApiError::BadRequest(format!(
+                "unknown format {other}, expected json or csv"
+            ))),
+            None => Ok(
+                if headers
+                    .get(ACCEPT)
+                    .and_then(|v| v.to_str().ok())
+                    .is_some_and(|v| v.contains("text/csv"))
+                {
+                    Self::Csv
+                } else {
+                    Self::Json
+                },
+            ),
+        }
+    }
+
+    /// Renders rows as a JSON array or as CSV
+    pub(crate) fn render<T: Serialize>(self, rows: &[T]) -> Result<Response, ApiError> {
+        match self {
+            Self::Json => Ok(Json(rows).into_response()),
+            Self::Csv => Ok(csv(rows)?),
+        }
+    }
+}
+
+/// Encodes rows as CSV, with a header row taken from the field names
+pub(crate) fn csv<T: Serialize>(rows: &[T]) -> anyhow::Result<Response> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row).context("Failed to encode CSV row")?;
+    }
+    let body = writer.into_inner().context("Failed to finish CSV body")?;
+    Ok(([(CONTENT_TYPE, HeaderValue::from_static(TEXT_CSV))], body).into_response())
+}
+
+/// Parses a node number given as decimal or as a `!` prefixed hex node id
+pub(crate) fn node_id(s: &str) -> Result<u32, ApiError> {
+    parse::node_id(s).map_err(bad_request)
+}
+
+/// Parses a time given as Unix seconds, RFC 3339 or a UTC date
+pub(crate) fn time(s: &str) -> Result<NaiveDateTime, ApiError> {
+    parse::time(s).map_err(bad_request)
+}
+
+/// Answers a parse error as a bad request
+#[expect(clippy::needless_pass_by_value, reason = "used with map_err")]
+fn bad_request(e: anyhow::Error) -> ApiError {
+    ApiError::BadRequest(format!("{e:#}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bad_request<T>(r: &Result<T, ApiError>) -> bool {
+        matches!(r, Err(ApiError::BadRequest(_)))
+    }
+
+    #[test]
+    fn parse_errors_are_bad_requests() {
+        assert!(bad_request(&node_id("!xyz")));
+        assert!(bad_request(&time("yesterday")));
+    }
+
+    #[test]
+    fn format_prefers_the_query_parameter() -> Result<(), ApiError> {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Format::negotiate(None, &headers)?, Format::Json);
+        headers.insert(ACCEPT, HeaderValue::from_static("text/csv"));
+        assert_eq!(Format::negotiate(None, &headers)?, Format::Csv);
+        assert_eq!(Format::negotiate(Some("json"), &headers)?, Format::Json);
+        assert!(bad_request(&Format::negotiate(Some("xml"), &headers)));
+        Ok(())
+    }
+}
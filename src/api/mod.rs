@@ -0,0 +1,399 @@
+use crate::{
+    api::{
+        format::{ApiError, Format, csv, node_id, time},
+        queries::{AirQualityRow, DeviceRow, EnvironmentRow, NodeRow, PaxcountRow, PowerRow},
+    },
+    sinks::{Sinks, live::Live},
+    util::{
+        config::{ApiSettings, DEPLOYMENT_LOCATION},
+        state::GatewayState,
+    },
+};
+use anyhow::{Context as _, Result};
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse as _, Response},
+    routing::get,
+};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Pool, Postgres};
+use std::{sync::Arc, time::Duration};
+use tokio::{net::TcpListener, sync::watch, task::JoinHandle, time::timeout};
+
+/// Response formats, input parsing and errors
+pub(crate) mod format;
+/// Read-only queries over the stored telemetry
+pub(crate) mod queries;
+/// Server-sent event stream of live decoded packets
+pub(crate) mod stream;
+
+/// Time range of environment queries without a `from` parameter
+const DEFAULT_RANGE: TimeDelta = TimeDelta::hours(24);
+
+/// Time allowed for in-flight requests on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// State shared by the request handlers
+#[derive(Debug)]
+struct Shared {
+    /// Gateway state holding packet counts
+    state: Arc<GatewayState>,
+    /// Database pool, `None` when another output replaces the database
+    pool: Option<Pool<Postgres>>,
+    /// Deployment location every query is scoped to
+    location: String,
+    /// Live decoded packets for `/stream`
+    live: Option<Live>,
+    /// Set once the API shuts down, ending the open streams
+    closing: watch::Receiver<bool>,
+}
+
+/// `format` query parameter
+#[derive(Debug, Deserialize)]
+struct FormatQuery {
+    /// `json` or `csv`
+    format: Option<String>,
+}
+
+/// Query parameters of time ranged queries
+#[derive(Debug, Deserialize)]
+struct RangeQuery {
+    /// Start of the range, 24 hours before `to` if left out
+    from: Option<String>,
+    /// End of the range, now if left out
+    to: Option<String>,
+    /// `json` or `csv`
+    format: Option<String>,
+}
+
+/// A node as listed by `/nodes`
+#[derive(Debug, Serialize)]
+struct Node {
+    /// Node number
+    num: i64,
+    /// Node id, e.g. `!1234abcd`
+    id: String,
+    /// Long name of the node
+    long_name: String,
+    /// Short name of the node
+    short_name: String,
+    /// Hardware model number
+    hw_model: i32,
+    /// Packets received since the daemon started
+    packets: Option<usize>,
+    /// Time of the last received packet, or of the daemon start if none was received yet
+    last_heard: Option<NaiveDateTime>,
+}
+
+/// Latest readings of a node by telemetry type
+#[derive(Debug, Serialize)]
+struct Latest {
+    /// Node number
+    node_id: u32,
+    /// Latest `DeviceMetrics` telemetry
+    device: Option<DeviceRow>,
+    /// Latest `EnvironmentMetrics`
+    environment: Option<EnvironmentRow>,
+    /// Latest `AirQualityMetrics`
+    air_quality: Option<AirQualityRow>,
+    /// Latest `PowerMetrics`
+    power: Option<PowerRow>,
+    /// Latest `Paxcount` of a Paxcounter node
+    paxcount: Option<PaxcountRow>,
+}
+
+/// One reading of `Latest` in CSV output
+#[derive(Debug, Serialize)]
+struct LatestField {
+    /// Telemetry type
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// Time of the reading
+    time: NaiveDateTime,
+    /// Field name
+    field: String,
+    /// Field value
+    value: Value,
+}
+
+impl Latest {
+    /// Flattens the readings into one row per present field
+    fn fields(&self) -> Result<Vec<LatestField>> {
+        let mut rows = Vec::new();
+        for (kind, reading) in [
+            ("device", serde_json::to_value(&self.device)?),
+            ("environment", serde_json::to_value(&self.environment)?),
+            ("air_quality", serde_json::to_value(&self.air_quality)?),
+            ("power", serde_json::to_value(&self.power)?),
+            ("paxcount", serde_json::to_value(&self.paxcount)?),
+        ] {
+            let Value::Object(mut fields) = reading else {
+                continue;
+            };
+            let time = serde_json::from_value(fields.remove("time").unwrap_or_default())
+                .context("Reading without a time")?;
+            rows.extend(
+                fields
+                    .into_iter()
+                    .filter(|(_, value)| !value.is_null())
+                    .map(|(field, value)| LatestField {
+                        kind,
+                        time,
+                        field,
+                        value,
+                    }),
+            );
+        }
+        Ok(rows)
+    }
+}
+
+/// Embedded read-only HTTP API over the stored telemetry
+#[derive(Debug)]
+pub(crate) struct Api {
+    /// Signals the server to stop accepting connections and the streams to end
+    stop: watch::Sender<bool>,
+    /// The server task
+    task: JoinHandle<()>,
+}
+
+impl Api {
+    /// Binds the listener and spawns the server if the API is configured
+    pub(crate) async fn start(
+        cfg: Option<&ApiSettings>,
+        state: &Arc<GatewayState>,
+        pool: Option<&Pool<Postgres>>,
+        sinks: &Sinks,
+    ) -> Result<Option<Self>> {
+        let Some(cfg) = cfg else {
+            return Ok(None);
+        };
+        if pool.is_none() {
+            tracing::warn!("Serving only /stream, the stored telemetry needs PostgreSQL");
+        }
+        let location = DEPLOYMENT_LOCATION
+            .get()
+            .context("Unable to get DEPLOYMENT_LOCATION for the API")?
+            .clone();
+        let listener = TcpListener::bind(&cfg.bind)
+            .await
+            .with_context(|| format!("Failed to bind the API to {}", cfg.bind))?;
+        tracing::info!(bind = cfg.bind, "Serving the API");
+
+        let (stop, closing) = watch::channel(false);
+        let app = router(Arc::new(Shared {
+            state: Arc::clone(state),
+            pool: pool.cloned(),
+            location,
+            live: sinks.live().cloned(),
+            closing: closing.clone(),
+        }));
+        let mut stopped = closing;
+        let task = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app)
+                .with_graceful_shutdown(async move {
+                    drop(stopped.wait_for(|closing| *closing).await);
+                })
+                .await
+            {
+                tracing::error!(%e, "API server failed");
+            }
+        });
+        Ok(Some(Self { stop, task }))
+    }
+
+    /// Stops accepting connections and waits briefly for in-flight requests
+    pub(crate) async fn shutdown(self) {
+        let _ = self.stop.send(true);
+        let mut task = self.task;
+        if timeout(SHUTDOWN_TIMEOUT, &mut task).await.is_err() {
+            task.abort();
+        }
+    }
+}
+
+/// Routes of the API
+fn router(shared: Arc<Shared>) -> Router {
+    Router::new()
+        .route("/nodes", get(nodes))
+        .route("/nodes/{id}/latest", get(latest))
+        .route("/nodes/{id}/environment", get(environment))
+        .route("/stream", get(stream::stream))
+        .with_state(shared)
+}
+
+impl Shared {
+    /// The database pool, answering 503 when the API runs without one
+    fn pool(&self) -> Result<&Pool<Postgres>, ApiError> {
+        self.pool.as_ref().ok_or(ApiError::Unavailable)
+    }
+}
+
+/// Looks up a node of the deployment, answering 404 for other nodes
+async fn find_node(api: &Shared, id: &str) -> Result<u32, ApiError> {
+    let node_id = node_id(id)?;
+    queries::node(node_id, &api.location, api.pool()?)
+        .await?
+        .map(|_| node_id)
+        .ok_or_else(|| ApiError::NotFound(format!("node {id} is not part of this deployment")))
+}
+
+/// `GET /nodes`, the nodes of the deployment with their packet counts
+async fn nodes(
+    State(api): State<Arc<Shared>>,
+    Query(q): Query<FormatQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let format = Format::negotiate(q.format.as_deref(), &headers)?;
+    let nodes = queries::nodes(&api.location, api.pool()?)
+        .await?
+        .into_iter()
+        .map(|row: NodeRow| {
+            let activity = u32::try_from(row.node_id)
+                .ok()
+                .and_then(|id| api.state.activity(id));
+            Node {
+                num: row.node_id,
+                id: format!("!{:08x}", row.node_id),
+                long_name: row.longname,
+                short_name: row.shortname,
+                hw_model: row.hwmodel,
+                packets: activity.map(|(packets, _)| packets),
+                last_heard: activity
+                    .and_then(|(_, t)| DateTime::from_timestamp(t, 0))
+                    .map(|dt| dt.naive_utc()),
+            }
+        })
+        .collect::<Vec<_>>();
+    format.render(&nodes)
+}
+
+/// `GET /nodes/{id}/latest`, the latest reading of every telemetry type of a node
+async fn latest(
+    State(api): State<Arc<Shared>>,
+    Path(id): Path<String>,
+    Query(q): Query<FormatQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let format = Format::negotiate(q.format.as_deref(), &headers)?;
+    let node_id = find_node(&api, &id).await?;
+    let pool = api.pool()?;
+    let (device, environment, air_quality, power, paxcount) = tokio::try_join!(
+        queries::latest_device(node_id, pool),
+        queries::latest_environment(node_id, pool),
+        queries::latest_air_quality(node_id, pool),
+        queries::latest_power(node_id, pool),
+        queries::latest_paxcount(node_id, pool),
+    )?;
+    let latest = Latest {
+        node_id,
+        device,
+        environment,
+        air_quality,
+        power,
+        paxcount,
+    };
+    match format {
+        Format::Json => Ok(Json(latest).into_response()),
+        Format::Csv => Ok(csv(&latest.fields()?)?),
+    }
+}
+
+/// `GET /nodes/{id}/environment?from=&to=`, the environment readings of a node in a time range
+async fn environment(
+    State(api): State<Arc<Shared>>,
+    Path(id): Path<String>,
+    Query(q): Query<RangeQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let format = Format::negotiate(q.format.as_deref(), &headers)?;
+    let to =
+        q.to.as_deref()
+            .map_or_else(|| Ok(Utc::now().naive_utc()), time)?;
+    let from = q.from.as_deref().map_or(Ok(to - DEFAULT_RANGE), time)?;
+    if from >= to {
+        return Err(ApiError::BadRequest(String::from("from must be before to")));
+    }
+    let node_id = find_node(&api, &id).await?;
+    format.render(&queries::environment(node_id, from, to, api.pool()?).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    #[test]
+    fn latest_flattens_present_fields() -> Result<()> {
+        let time = DateTime::from_timestamp(1_760_000_000, 0)
+            .context("out of range")?
+            .naive_utc();
+        let latest = Latest {
+            node_id: 1,
+            device: Some(DeviceRow {
+                time,
+                battery_level: Some(87),
+                voltage: None,
+                channel_utilization: None,
+                air_util_tx: None,
+            }),
+            environment: None,
+            air_quality: None,
+            power: Some(PowerRow {
+                time,
+                ch1_voltage: Some(12.5),
+                ch1_current: None,
+                ch2_voltage: None,
+                ch2_current: None,
+                ch3_voltage: None,
+                ch3_current: None,
+            }),
+            paxcount: Some(PaxcountRow {
+                time,
+                wifi: 12,
+                ble: 30,
+                uptime: 600,
+            }),
+        };
+        let fields = latest
+            .fields()?
+            .into_iter()
+            .map(|f| (f.kind, f.field, f.value.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            [
+                ("device", String::from("battery_level"), String::from("87")),
+                ("power", String::from("ch1_voltage"), String::from("12.5")),
+                ("paxcount", String::from("ble"), String::from("30")),
+                ("paxcount", String::from("uptime"), String::from("600")),
+                ("paxcount", String::from("wifi"), String::from("12")),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stored_telemetry_is_unavailable_without_a_database() {
+        let (_stop, closing) = watch::channel(false);
+        let api = Arc::new(Shared {
+            state: Arc::new(GatewayState::new()),
+            pool: None,
+            location: String::from("test"),
+            live: None,
+            closing,
+        });
+        let response = nodes(
+            State(api),
+            Query(FormatQuery { format: None }),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,351 @@
+use anyhow::{Context as _, Error, Result};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{Pool, Postgres, postgres::types::Oid, query_as};
+
+/// Most rows a single range query returns
+const MAX_ROWS: i64 = 10_000;
+
+/// A node from the `NodeInfo` table
+#[derive(Debug, Serialize)]
+pub(crate) struct NodeRow {
+    /// Node number
+    pub(crate) node_id: i64,
+    /// Long name of the node
+    pub(crate) longname: String,
+    /// Short name of the node
+    pub(crate) shortname: String,
+    /// Hardware model number
+    pub(crate) hwmodel: i32,
+}
+
+/// Latest telemetry of a `DeviceMetrics` row
+#[derive(Debug, Serialize)]
+pub(crate) struct DeviceRow {
+    /// Time of the reading
+    pub(crate) time: NaiveDateTime,
+    /// Battery level in percent, above 100 when powered
+    pub(crate) battery_level: Option<i64>,
+    /// Battery voltage
+    pub(crate) voltage: Option<f32>,
+    /// Channel utilization in percent
+    pub(crate) channel_utilization: Option<f32>,
+    /// Transmit air utilization in percent
+    pub(crate) air_util_tx: Option<f32>,
+}
+
+/// An `EnvironmentMetrics` row
+#[derive(Debug, Serialize)]
+pub(crate) struct EnvironmentRow {
+    /// Time of the reading
+    pub(crate) time: NaiveDateTime,
+    /// Temperature in degrees Celsius
+    pub(crate) temperature: Option<f32>,
+    /// Relative humidity in percent
+    pub(crate) relative_humidity: Option<f32>,
+    /// Barometric pressure in hPa
+    pub(crate) barometric_pressure: Option<f32>,
+    /// Gas resistance in `MOhm`
+    pub(crate) gas_resistance: Option<f32>,
+    /// Indoor air quality index
+    pub(crate) iaq: Option<i64>,
+    /// Wind direction in degrees
+    pub(crate) wind_direction: Option<i64>,
+    /// Wind speed in m/s
+    pub(crate) wind_speed: Option<f32>,
+    /// Wind gust in m/s
+    pub(crate) wind_gust: Option<f32>,
+    /// Wind lull in m/s
+    pub(crate) wind_lull: Option<f32>,
+    /// Rainfall over the last hour in mm
+    pub(crate) rainfall_1h: Option<f32>,
+    /// Rainfall over the last day in mm
+    pub(crate) rainfall_24h: Option<f32>,
+    /// Sensor voltage
+    pub(crate) voltage: Option<f32>,
+    /// Sensor current
+    pub(crate) current: Option<f32>,
+}
+
+/// An `AirQualityMetrics` row
+#[derive(Debug, Serialize)]
+pub(crate) struct AirQualityRow {
+    /// Time of the reading
+    pub(crate) time: NaiveDateTime,
+    /// PM1.0 standard concentration
+    pub(crate) pm10_standard: Option<i64>,
+    /// PM2.5 standard concentration
+    pub(crate) pm25_standard: Option<i64>,
+    /// PM10.0 standard concentration
+    pub(crate) pm100_standard: Option<i64>,
+    /// CO2 in ppm
+    pub(crate) co2: Option<i64>,
+}
+
+/// A `PowerMetrics` row
+#[derive(Debug, Serialize)]
+pub(crate) struct PowerRow {
+    /// Time of the reading
+    pub(crate) time: NaiveDateTime,
+    /// Channel 1 voltage
+    pub(crate) ch1_voltage: Option<f32>,
+    /// Channel 1 current
+    pub(crate) ch1_current: Option<f32>,
+    /// Channel 2 voltage
+    pub(crate) ch2_voltage: Option<f32>,
+    /// Channel 2 current
+    pub(crate) ch2_current: Option<f32>,
+    /// Channel 3 voltage
+    pub(crate) ch3_voltage: Option<f32>,
+    /// Channel 3 current
+    pub(crate) ch3_current: Option<f32>,
+}
+
+/// A `Paxcount` row
+#[derive(Debug, Serialize)]
+pub(crate) struct PaxcountRow {
+    /// Time the counts were received
+    pub(crate) time: NaiveDateTime,
+    /// Wi-Fi devices seen
+    pub(crate) wifi: i64,
+    /// Bluetooth devices seen
+    pub(crate) ble: i64,
+    /// Uptime of the counter in seconds
+    pub(crate) uptime: i64,
+}
+
+/// Nodes of a deployment
+pub(crate) async fn nodes(location: &str, pool: &Pool<Postgres>) -> Result<Vec<NodeRow>, Error> {
+    query_as!(
+        NodeRow,
+        r#"
+SELECT
+    node_id::bigint AS "node_id!",
+    longname,
+    shortname,
+    hwmodel
+FROM NodeInfo
+WHERE deployment_location = $1
+ORDER BY node_id
+        "#,
+        location,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select rows from NodeInfo table")
+}
+
+/// A node of a deployment, `None` if the node is not part of it
+pub(crate) async fn node(
+    node_id: u32,
+    location: &str,
+    pool: &Pool<Postgres>,
+) -> Result<Option<NodeRow>, Error> {
+    query_as!(
+        NodeRow,
+        r#"
+SELECT
+    node_id::bigint AS "node_id!",
+    longname,
+    shortname,
+    hwmodel
+FROM NodeInfo
+WHERE node_id = $1 AND deployment_location = $2
+        "#,
+        Oid(node_id),
+        location,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select row from NodeInfo table")
+}
+
+/// Latest `DeviceMetrics` telemetry of a node, skipping rows only holding position or names
+pub(crate) async fn latest_device(
+    node_id: u32,
+    pool: &Pool<Postgres>,
+) -> Result<Option<DeviceRow>, Error> {
+    query_as!(
+        DeviceRow,
+        r#"
+SELECT
+    time,
+    battery_levels::bigint AS battery_level,
+    voltage,
+    channelutil AS channel_utilization,
+    airutil AS air_util_tx
+FROM DeviceMetrics
+WHERE
+    node_id = $1
+    AND (battery_levels IS NOT NULL OR voltage IS NOT NULL OR channelutil IS NOT NULL)
+ORDER BY time DESC
+LIMIT 1
+        "#,
+        Oid(node_id),
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select row from DeviceMetrics table")
+}
+
+/// Latest `EnvironmentMetrics` row of a node
+pub(crate) async fn latest_environment(
+    node_id: u32,
+    pool: &Pool<Postgres>,
+) -> Result<Option<EnvironmentRow>, Error> {
+    query_as!(
+        EnvironmentRow,
+        r#"
+SELECT
+    time,
+    temperature,
+    relative_humidity,
+    barometric_pressure,
+    gas_resistance,
+    iaq::bigint AS iaq,
+    wind_direction::bigint AS wind_direction,
+    wind_speed,
+    wind_gust,
+    wind_lull,
+    rainfall_1h,
+    rainfall_24h,
+    voltage,
+    current
+FROM EnvironmentMetrics
+WHERE node_id = $1
+ORDER BY time DESC
+LIMIT 1
+        "#,
+        Oid(node_id),
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select row from EnvironmentMetrics table")
+}
+
+/// Latest `AirQualityMetrics` row of a node
+pub(crate) async fn latest_air_quality(
+    node_id: u32,
+    pool: &Pool<Postgres>,
+) -> Result<Option<AirQualityRow>, Error> {
+    query_as!(
+        AirQualityRow,
+        r#"
+SELECT
+    time,
+    pm10standard::bigint AS pm10_standard,
+    pm25standard::bigint AS pm25_standard,
+    pm100standard::bigint AS pm100_standard,
+    co2::bigint AS co2
+FROM AirQualityMetrics
+WHERE node_id = $1
+ORDER BY time DESC
+LIMIT 1
+        "#,
+        Oid(node_id),
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select row from AirQualityMetrics table")
+}
+
+/// Latest `PowerMetrics` row of a node
+pub(crate) async fn latest_power(
+    node_id: u32,
+    pool: &Pool<Postgres>,
+) -> Result<Option<PowerRow>, Error> {
+    query_as!(
+        PowerRow,
+        r#"
+SELECT
+    time,
+    ch1_voltage,
+    ch1_current,
+    ch2_voltage,
+    ch2_current,
+    ch3_voltage,
+    ch3_current
+FROM PowerMetrics
+WHERE node_id = $1
+ORDER BY time DESC
+LIMIT 1
+        "#,
+        Oid(node_id),
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select row from PowerMetrics table")
+}
+
+/// Latest `Paxcount` row of a node
+pub(crate) async fn latest_paxcount(
+    node_id: u32,
+    pool: &Pool<Postgres>,
+) -> Result<Option<PaxcountRow>, Error> {
+    query_as!(
+        PaxcountRow,
+        r#"
+SELECT
+    time,
+    wifi::bigint AS "wifi!",
+    ble::bigint AS "ble!",
+    uptime::bigint AS "uptime!"
+FROM Paxcount
+WHERE node_id = $1
+ORDER BY time DESC
+LIMIT 1
+        "#,
+        Oid(node_id),
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select row from Paxcount table")
+}
+
+/// `EnvironmentMetrics` rows of a node in `[from, to)`, oldest first
+pub(crate) async fn environment(
+    node_id: u32,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    pool: &Pool<Postgres>,
+) -> Result<Vec<EnvironmentRow>, Error> {
+    query_as!(
+        EnvironmentRow,
+        r#"
+SELECT
+    time,
+    temperature,
+    relative_humidity,
+    barometric_pressure,
+    gas_resistance,
+    iaq::bigint AS iaq,
+    wind_direction::bigint AS wind_direction,
+    wind_speed,
+    wind_gust,
+    wind_lull,
+    rainfall_1h,
+    rainfall_24h,
+    voltage,
+    current
+FROM EnvironmentMetrics
+WHERE node_id = $1 AND time >= $2 AND time < $3
+ORDER BY time
+LIMIT $4
+        "#,
+        Oid(node_id),
+        from,
+        to,
+        MAX_ROWS,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select rows from EnvironmentMetrics table")
+}
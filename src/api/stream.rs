@@ -0,0 +1,230 @@
+use crate::{
+    api::{
+        Shared,
+        format::{ApiError, node_id},
+    },
+    dto::telemetry::{empty, kind},
+    sinks::live::LivePacket,
+    util::parse::list,
+};
+use axum::{
+    extract::{Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::{Stream, stream};
+use meshtastic::protobufs::PortNum;
+use serde::Deserialize;
+use std::{convert::Infallible, sync::Arc};
+use tokio::sync::{
+    broadcast::{Receiver, error::RecvError},
+    watch,
+};
+
+/// Filters of `/stream`, each a comma separated list matching any of its entries
+#[derive(Debug, Deserialize)]
+pub(super) struct StreamQuery {
+    /// Sending nodes, decimal or `!` prefixed hex
+    node: Option<String>,
+    /// Port names like `TELEMETRY_APP` or port numbers
+    portnum: Option<String>,
+    /// Telemetry types like `environment`, only passing telemetry packets
+    variant: Option<String>,
+}
+
+/// Parsed `/stream` filters, an empty list lets everything through
+#[derive(Debug)]
+struct Filter {
+    /// Sending nodes
+    nodes: Vec<u32>,
+    /// Port names
+    ports: Vec<&'static str>,
+    /// Telemetry types
+    variants: Vec<&'static str>,
+}
+
+impl Filter {
+    /// Parses the query parameters, rejecting unknown nodes, ports and telemetry types
+    fn parse(q: &StreamQuery) -> Result<Self, ApiError> {
+        Ok(Self {
+            nodes: list(q.node.as_deref())
+                .map(node_id)
+                .collect::<Result<_, _>>()?,
+            ports: list(q.portnum.as_deref())
+                .map(port)
+                .collect::<Result<_, _>>()?,
+            variants: list(q.variant.as_deref())
+                .map(|v| {
+                    empty(v)
+                        .map(|variant| kind(&variant))
+                        .ok_or_else(|| ApiError::BadRequest(format!("unknown telemetry type {v}")))
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Whether a packet passes every filter
+    fn matches(&self, p: &LivePacket) -> bool {
+        (self.nodes.is_empty() || self.nodes.contains(&p.from))
+            && (self.ports.is_empty() || self.ports.contains(&p.portnum))
+            && (self.variants.is_empty() || p.variant.is_some_and(|v| self.variants.contains(&v)))
+    }
+}
+
+/// Parses a port given by name, case insensitive, or by number
+fn port(s: &str) -> Result<&'static str, ApiError> {
+    s.parse::<i32>()
+        .ok()
+        .and_then(|n| PortNum::try_from(n).ok())
+        .or_else(|| PortNum::from_str_name(&s.to_ascii_uppercase()))
+        .map(|p| p.as_str_name())
+        .ok_or_else(|| ApiError::BadRequest(format!("unknown portnum {s}")))
+}
+
+/// A client's subscription to the live packets
+#[derive(Debug)]
+struct Subscription {
+    /// Packets from the packet handler
+    rx: Receiver<Arc<LivePacket>>,
+    /// Filters of the client
+    filter: Filter,
+    /// Set once the API shuts down
+    closing: watch::Receiver<bool>,
+}
+
+impl Subscription {
+    /// Waits for the next packet passing the filters, or a notice of skipped packets
+    async fn next(mut self) -> Option<(Result<Event, Infallible>, Self)> {
+        loop {
+            let event = tokio::select! {
+                _ = self.closing.wait_for(|closing| *closing) => return None,
+                msg = self.rx.recv() => match msg {
+                    Ok(p) if self.filter.matches(&p) => {
+                        Event::default().event("packet").data(p.json.as_str())
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(n)) => {
+                        Event::default().event("lagged").data(n.to_string())
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+            };
+            return Some((Ok(event), self));
+        }
+    }
+}
+
+/// `GET /stream?node=&portnum=&variant=`, every decoded packet as a server-sent `packet`
+/// event, with `lagged` events counting packets skipped by a slow client
+pub(super) async fn stream(
+    State(api): State<Arc<Shared>>,
+    Query(q): Query<StreamQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let filter = Filter::parse(&q)?;
+    let Some(live) = &api.live else {
+        return Err(ApiError::NotFound(String::from("live stream disabled")));
+    };
+    let subscription = Subscription {
+        rx: live.subscribe(),
+        filter,
+        closing: api.closing.clone(),
+    };
+    Ok(Sse::new(stream::unfold(subscription, Subscription::next)).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dto::packet::Payload, sinks::live::Live};
+    use anyhow::Context as _;
+    use meshtastic::protobufs::MeshPacket;
+
+    fn packet(from: u32, portnum: &'static str, variant: Option<&'static str>) -> LivePacket {
+        LivePacket {
+            from,
+            portnum,
+            variant,
+            json: String::new(),
+        }
+    }
+
+    fn filter(
+        node: Option<&str>,
+        portnum: Option<&str>,
+        variant: Option<&str>,
+    ) -> anyhow::Result<Filter> {
+        Filter::parse(&StreamQuery {
+            node: node.map(String::from),
+            portnum: portnum.map(String::from),
+            variant: variant.map(String::from),
+        })
+        .map_err(|e| anyhow::anyhow!("invalid filter: {e:?}"))
+    }
+
+    fn rejected(node: Option<&str>, portnum: Option<&str>, variant: Option<&str>) -> bool {
+        Filter::parse(&StreamQuery {
+            node: node.map(String::from),
+            portnum: portnum.map(String::from),
+            variant: variant.map(String::from),
+        })
+        .is_err()
+    }
+
+    #[test]
+    fn empty_filter_passes_everything() -> anyhow::Result<()> {
+        let f = filter(None, None, None)?;
+        assert!(f.matches(&packet(1, "TEXT_MESSAGE_APP", None)));
+        assert!(f.matches(&packet(2, "TELEMETRY_APP", Some("device"))));
+        Ok(())
+    }
+
+    #[test]
+    fn filters_combine_lists() -> anyhow::Result<()> {
+        let f = filter(
+            Some("!00000001, 2"),
+            Some("telemetry_app,3"),
+            Some("environment,power"),
+        )?;
+        assert_eq!(f.ports, ["TELEMETRY_APP", "POSITION_APP"]);
+        assert!(f.matches(&packet(1, "TELEMETRY_APP", Some("environment"))));
+        assert!(f.matches(&packet(2, "TELEMETRY_APP", Some("power"))));
+        assert!(!f.matches(&packet(3, "TELEMETRY_APP", Some("power"))));
+        assert!(!f.matches(&packet(1, "TELEMETRY_APP", Some("device"))));
+        // A variant filter only lets telemetry through
+        assert!(!f.matches(&packet(1, "POSITION_APP", None)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn subscription_skips_filtered_packets_and_ends_on_close() -> anyhow::Result<()> {
+        let live = Live::new();
+        let (close, closing) = watch::channel(false);
+        let subscription = Subscription {
+            rx: live.subscribe(),
+            filter: filter(Some("1"), None, None)?,
+            closing,
+        };
+        for from in [2, 1] {
+            let pkt = MeshPacket {
+                from,
+                ..Default::default()
+            };
+            live.send(&pkt, PortNum::TextMessageApp, Payload::Raw(b"hi"));
+        }
+        let (_, subscription) = subscription
+            .next()
+            .await
+            .context("stream ended before the matching packet")?;
+        assert!(subscription.rx.is_empty());
+        close.send(true)?;
+        assert!(subscription.next().await.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_filters_are_rejected() {
+        assert!(rejected(Some("!zz"), None, None));
+        assert!(rejected(None, Some("NOT_AN_APP"), None));
+        assert!(rejected(None, Some("1000"), None));
+        assert!(rejected(None, None, Some("weather")));
+    }
+}
@@ -0,0 +1,55 @@
+use crate::{
+    sinks::rules::Alert,
+    util::{config::DEPLOYMENT_LOCATION, timestamp},
+};
+use anyhow::{Context as _, Error, Result};
+use meshtastic::protobufs::{MeshPacket, Telemetry};
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// Insert a row into the `Alerts` table for a threshold rule transition
+pub(crate) async fn insert(
+    pkt: &MeshPacket,
+    tm: &Telemetry,
+    alert: &Alert,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in insert() for Alerts table")?;
+
+    query!(
+        "
+INSERT INTO
+  Alerts (
+    msg_id,
+    node_id,
+    time,
+    rule,
+    field,
+    value,
+    threshold,
+    cleared,
+    deployment_location
+)
+VALUES
+  ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ",
+        Oid(pkt.id),
+        Oid(alert.node_id),
+        timestamp(tm.time),
+        alert.rule,
+        alert.field,
+        alert.value,
+        alert.threshold,
+        alert.cleared,
+        loc,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into Alerts table")
+}
@@ -1,5 +1,9 @@
-use crate::util::timestamp;
+use crate::{
+    rangetest::Point,
+    util::{first_timestamp, timestamp},
+};
 use anyhow::{Context as _, Error, Result};
+use chrono::NaiveDateTime;
 use meshtastic::protobufs::{DeviceMetrics, FromRadio, MeshPacket, NodeInfo, Position, Telemetry};
 use sqlx::{
     Pool, Postgres,
@@ -78,7 +82,7 @@ VALUES
             ",
         Oid(pkt.id),
         Oid(pkt.from),
-        timestamp(pos.timestamp), //TODO: do I need ms adjustments?
+        first_timestamp(&[pos.timestamp, pos.time, pkt.rx_time]), //TODO: do I need ms adjustments?
         pos.latitude_i,
         pos.longitude_i,
     )
@@ -207,3 +211,34 @@ ON CONFLICT (msg_id) DO UPDATE SET
     .map_err(Error::from)
     .context("Failed to upsert row in DeviceMetrics table from serial")
 }
+
+/// Last position a node reported, with the time it was taken
+pub(crate) async fn latest_position(
+    node_id: u32,
+    pool: &Pool<Postgres>,
+) -> Result<Option<(Point, NaiveDateTime)>, Error> {
+    let row = query!(
+        r#"
+SELECT
+    latitude AS "latitude!",
+    longitude AS "longitude!",
+    time
+FROM DeviceMetrics
+WHERE node_id = $1 AND latitude IS NOT NULL AND longitude IS NOT NULL
+ORDER BY time DESC
+LIMIT 1
+        "#,
+        Oid(node_id),
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select position from DeviceMetrics table")?;
+    Ok(row.map(|r| {
+        let point = Point {
+            latitude_i: r.latitude,
+            longitude_i: r.longitude,
+        };
+        (point, r.time)
+    }))
+}
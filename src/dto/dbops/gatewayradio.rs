@@ -0,0 +1,99 @@
+use crate::{dto::radio::RadioSnapshot, util::config::DEPLOYMENT_LOCATION};
+use anyhow::{Context as _, Error, Result};
+use chrono::Utc;
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// Insert a row into the `GatewayRadio` table if the serial node's configuration changed
+///
+/// An unchanged configuration only moves `last_seen` of the deployment's latest row, so one
+/// row covers the time the radio was configured that way. Affects no row in that case.
+pub(crate) async fn record(
+    node_id: u32,
+    snapshot: &RadioSnapshot,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in record() for GatewayRadio table")?;
+
+    query!(
+        "
+WITH
+  latest AS (
+    SELECT
+      first_seen
+    FROM
+      GatewayRadio
+    WHERE
+      deployment_location = $2
+      AND node_id = $1
+      AND snapshot = $7
+      AND first_seen = (
+        SELECT
+          MAX(first_seen)
+        FROM
+          GatewayRadio
+        WHERE
+          deployment_location = $2
+      )
+  ),
+  unchanged AS (
+    UPDATE GatewayRadio
+    SET
+      last_seen = $3
+    WHERE
+      deployment_location = $2
+      AND first_seen = (
+        SELECT
+          first_seen
+        FROM
+          latest
+      )
+    RETURNING
+      1
+  )
+INSERT INTO
+  GatewayRadio (
+    node_id,
+    deployment_location,
+    first_seen,
+    last_seen,
+    firmware_version,
+    role,
+    hw_model,
+    snapshot
+  )
+SELECT
+  $1,
+  $2,
+  $3,
+  $3,
+  $4,
+  $5,
+  $6,
+  $7
+WHERE
+  NOT EXISTS (
+    SELECT
+      1
+    FROM
+      unchanged
+  )
+            ",
+        Oid(node_id),
+        loc,
+        Utc::now().naive_utc(),
+        snapshot.firmware_version(),
+        snapshot.role(),
+        snapshot.hw_model(),
+        snapshot.to_json(),
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into GatewayRadio table")
+}
@@ -0,0 +1,74 @@
+use crate::{
+    VERSION,
+    util::{config::DEPLOYMENT_LOCATION, host::HostStats, state::GatewayState},
+};
+use anyhow::{Context as _, Error, Result};
+use chrono::Utc;
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+use std::time::Duration;
+
+/// Insert a row into the `GatewayStats` table reporting on the gateway itself
+///
+/// The serial node is left NULL until it has reported its number.
+pub(crate) async fn insert(
+    state: &GatewayState,
+    host: &HostStats,
+    daemon_uptime: Duration,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in insert() for GatewayStats table")?;
+    let serial_node = Some(state.serial_number()).filter(|n| *n != 0).map(Oid);
+    let load = host.load.map(|[one, five, fifteen]| (one, five, fifteen));
+
+    query!(
+        "
+INSERT INTO
+  GatewayStats (
+    time,
+    deployment_location,
+    serial_node,
+    version,
+    uptime_secs,
+    daemon_uptime_secs,
+    cpu_temp,
+    load_1,
+    load_5,
+    load_15,
+    mem_total_kb,
+    mem_available_kb,
+    disk_free_bytes,
+    disk_total_bytes,
+    packets_processed,
+    db_errors
+)
+VALUES
+  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+            ",
+        Utc::now().naive_utc(),
+        loc,
+        serial_node,
+        VERSION,
+        host.uptime_secs,
+        i64::try_from(daemon_uptime.as_secs()).unwrap_or(i64::MAX),
+        host.cpu_temp,
+        load.map(|l| l.0),
+        load.map(|l| l.1),
+        load.map(|l| l.2),
+        host.mem_total_kb,
+        host.mem_available_kb,
+        host.disk_free_bytes,
+        host.disk_total_bytes,
+        i64::try_from(state.packets_processed()).unwrap_or(i64::MAX),
+        i64::try_from(state.db_errors()).unwrap_or(i64::MAX),
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into GatewayStats table")
+}
@@ -0,0 +1,49 @@
+use crate::util::timestamp;
+use anyhow::{Context as _, Error, Result};
+use meshtastic::protobufs::{HealthMetrics, MeshPacket, Telemetry};
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// Insert a row into the `HealthMetrics` table from a `MeshPacket`
+pub(crate) async fn insert(
+    pkt: &MeshPacket,
+    tm: &Telemetry,
+    hm: &HealthMetrics,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    query!(
+        "
+INSERT INTO
+  HealthMetrics (
+    msg_id,
+    node_id,
+    time,
+    heart_bpm,
+    spo2,
+    temperature
+  )
+VALUES
+  (
+    $1,
+    $2,
+    $3,
+    $4,
+    $5,
+    $6
+  )
+            ",
+        Oid(pkt.id),
+        Oid(pkt.from),
+        timestamp(tm.time),
+        hm.heart_bpm.map(Oid),
+        hm.sp_o2.map(Oid),
+        hm.temperature,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into HealthMetrics table")
+}
@@ -1,16 +1,49 @@
 /// `AirQualityMetrics` database table operations
 pub(crate) mod airqualitymetrics;
+/// `Alerts` database table operations
+pub(crate) mod alerts;
 /// `DeviceMetrics` database table operations
 pub(crate) mod devicemetrics;
 /// `EnvironmentMetrics` database table operations
 pub(crate) mod environmentmetrics;
 /// `ErrorMetrics` database table operations
 pub(crate) mod errormetrics;
+/// `GatewayRadio` database table operations
+pub(crate) mod gatewayradio;
+/// `GatewayStats` database table operations
+pub(crate) mod gatewaystats;
+/// `HealthMetrics` database table operations
+pub(crate) mod healthmetrics;
 /// `LocalStats` database table operations
 pub(crate) mod localstats;
-/// `NeighborInfo` database table operations
+/// `NeighborInfo` and `NeighborLinks` database table operations
 pub(crate) mod neighborinfo;
 /// `NodeInfo` database table operations
 pub(crate) mod nodeinfo;
+/// `NodeInventory` database table operations
+pub(crate) mod nodeinventory;
+/// `PacketSources` database table operations
+#[cfg(feature = "mqtt-ingest")]
+pub(crate) mod packetsources;
+/// `Paxcount` database table operations
+pub(crate) mod paxcount;
 /// `PowerMetrics` database table operations
 pub(crate) mod powermetrics;
+/// `RangeTest` database table operations
+pub(crate) mod rangetest;
+/// `RawPackets` database table operations
+pub(crate) mod rawpackets;
+/// `Routing` and `RoutingPairs` database table operations
+pub(crate) mod routing;
+/// `SensorEvents` database table operations
+pub(crate) mod sensorevents;
+/// `StoreForwardRouters` and `StoreForwardStats` database table operations
+pub(crate) mod storeforward;
+/// `TelemetryFallback` database table operations
+pub(crate) mod telemetryfallback;
+/// `TopologySnapshots` database table operations
+pub(crate) mod topologysnapshots;
+/// `Traceroutes` database table operations
+pub(crate) mod traceroutes;
+/// `Waypoints` database table operations
+pub(crate) mod waypoints;
@@ -1,12 +1,16 @@
-use crate::util::timestamp;
+use crate::{
+    topology::Link,
+    util::{config::DEPLOYMENT_LOCATION, timestamp},
+};
 use anyhow::{Context as _, Error, Result};
+use chrono::NaiveDateTime;
 use meshtastic::protobufs::{MeshPacket, NeighborInfo};
 use serde::Serialize;
 use serde_json::{Value, json};
 use sqlx::{
     Pool, Postgres,
     postgres::{PgQueryResult, types::Oid},
-    query,
+    query, query_as,
 };
 
 #[derive(Serialize)]
@@ -73,3 +77,162 @@ VALUES
     .map_err(Error::from)
     .context("Failed to insert row into NeighborInfo table")
 }
+
+/// Insert a row per neighbor into the `NeighborLinks` table from a `MeshPacket`
+///
+/// The report itself is kept as a `NeighborReports` row, a report without neighbors replaces
+/// the older links of the node when they are loaded back.
+pub(crate) async fn insert_links(
+    pkt: &MeshPacket,
+    nbi: &NeighborInfo,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in insert_links() for NeighborLinks table")?;
+
+    let neighbors: Vec<Oid> = nbi.neighbors.iter().map(|n| Oid(n.node_id)).collect();
+    let snrs: Vec<f32> = nbi.neighbors.iter().map(|n| n.snr).collect();
+    query!(
+        "
+WITH report AS (
+    INSERT INTO NeighborReports (msg_id, reporter, time, deployment_location)
+    VALUES ($1, $2, $3, $6)
+    ON CONFLICT (msg_id) DO NOTHING
+)
+INSERT INTO NeighborLinks (msg_id, reporter, time, neighbor, snr, deployment_location)
+SELECT $1, $2, $3, n.neighbor, n.snr, $6
+FROM UNNEST($4::oid[], $5::real[]) AS n (neighbor, snr)
+ON CONFLICT (msg_id, neighbor) DO NOTHING
+        ",
+        Oid(pkt.id),
+        Oid(pkt.from),
+        timestamp(pkt.rx_time),
+        &neighbors,
+        &snrs,
+        loc,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert rows into NeighborLinks table")
+}
+
+/// A neighbor of the latest report of a node, `None` for a report without neighbors
+#[derive(Debug)]
+struct LinkRow {
+    /// Node that reported the neighbor
+    reporter: Oid,
+    /// Node it heard
+    neighbor: Option<Oid>,
+    /// SNR the reporter heard the neighbor with
+    snr: Option<f32>,
+    /// Time of the report
+    time: NaiveDateTime,
+}
+
+/// Reporter, Unix time and links of the latest report of every node since `since`
+pub(crate) async fn latest_reports(
+    since: NaiveDateTime,
+    pool: &Pool<Postgres>,
+) -> Result<Vec<(u32, i64, Vec<Link>)>, Error> {
+    let loc = DEPLOYMENT_LOCATION.get().context(
+        "Unable to get DEPLOYMENT_LOCATION in latest_reports() for NeighborReports table",
+    )?;
+
+    let rows = query_as!(
+        LinkRow,
+        r#"
+WITH latest AS (
+    SELECT DISTINCT ON (reporter) reporter, msg_id, time
+    FROM NeighborReports
+    WHERE deployment_location = $1 AND time >= $2
+    ORDER BY reporter, time DESC
+)
+SELECT latest.reporter AS "reporter!", l.neighbor AS "neighbor?", l.snr AS "snr?",
+    latest.time AS "time!"
+FROM latest
+LEFT JOIN NeighborLinks l
+    ON l.msg_id = latest.msg_id AND l.reporter = latest.reporter
+    AND l.deployment_location = $1
+ORDER BY latest.reporter, l.neighbor
+        "#,
+        loc,
+        since,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select rows from NeighborReports table")?;
+
+    Ok(reports(&rows))
+}
+
+/// Groups the rows of each reporter into its report
+fn reports(rows: &[LinkRow]) -> Vec<(u32, i64, Vec<Link>)> {
+    rows.chunk_by(|a, b| a.reporter == b.reporter)
+        .map(|report| {
+            let reporter = report[0].reporter.0;
+            let time = report[0].time.and_utc().timestamp();
+            let links = report
+                .iter()
+                .filter_map(|r| {
+                    Some(Link {
+                        reporter,
+                        neighbor: r.neighbor?.0,
+                        snr: r.snr?,
+                        time,
+                    })
+                })
+                .collect();
+            (reporter, time, links)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::Topology;
+    use chrono::DateTime;
+
+    fn row(reporter: u32, neighbor: Option<u32>, time: i64) -> LinkRow {
+        LinkRow {
+            reporter: Oid(reporter),
+            neighbor: neighbor.map(Oid),
+            snr: neighbor.map(|_| 6.25),
+            time: DateTime::from_timestamp(time, 0)
+                .unwrap_or_default()
+                .naive_utc(),
+        }
+    }
+
+    #[test]
+    fn empty_report_is_reloaded_without_links() {
+        let mut topology = Topology::default();
+        topology.report(
+            1,
+            100,
+            vec![Link {
+                reporter: 1,
+                neighbor: 2,
+                snr: 6.25,
+                time: 100,
+            }],
+        );
+        let reloaded = reports(&[row(1, None, 200), row(3, Some(4), 150)]);
+        assert_eq!(reloaded[0], (1, 200, Vec::new()));
+        for (reporter, time, links) in reloaded {
+            topology.report(reporter, time, links);
+        }
+        assert_eq!(
+            topology.graph(0).links,
+            [Link {
+                reporter: 3,
+                neighbor: 4,
+                snr: 6.25,
+                time: 150,
+            }]
+        );
+    }
+}
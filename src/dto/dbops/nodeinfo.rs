@@ -1,19 +1,48 @@
-use crate::util::config::DEPLOYMENT_LOCATION;
+use crate::util::{config::DEPLOYMENT_LOCATION, enum_name, timestamp};
 use anyhow::{Context as _, Error, Result};
-use meshtastic::protobufs::NodeInfo;
+use chrono::Utc;
+use meshtastic::protobufs::{NodeInfo, config::device_config::Role};
 use sqlx::{
     Pool, Postgres,
     postgres::{PgQueryResult, types::Oid},
     query,
 };
 
+/// Where a `NodeInfo` was received from, kept with its changes
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Source {
+    /// Node database of the serial node
+    Serial,
+    /// `NodeinfoApp` packet from the mesh
+    Mesh,
+}
+
+impl Source {
+    /// Value of the `source` column
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Serial => "serial",
+            Self::Mesh => "mesh",
+        }
+    }
+}
+
 /// Upsert (insert or update) a row in the `NodeInfo` table
-pub(crate) async fn upsert(ni: &NodeInfo, pool: &Pool<Postgres>) -> Result<PgQueryResult, Error> {
-    if ni.user.is_none() {
+///
+/// Changes of the names, hardware model, role, public key, licence or MAC address of a known
+/// node are kept as rows of the `NodeInfoHistory` table, the affected rows are those history
+/// rows. A field's first known value is not a change, and a missing key or MAC address keeps
+/// the stored one. The hardware model is recorded by its number.
+pub(crate) async fn upsert(
+    ni: &NodeInfo,
+    source: Source,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let Some(user) = &ni.user else {
         return Result::Err(Error::msg(
             "NodeInfo packet does not contain User information",
         ));
-    }
+    };
 
     let loc = DEPLOYMENT_LOCATION
         .get()
@@ -21,19 +50,61 @@ pub(crate) async fn upsert(ni: &NodeInfo, pool: &Pool<Postgres>) -> Result<PgQue
 
     query!(
         "
-INSERT INTO NodeInfo (node_id, longname, shortname, hwmodel, deployment_location)
-VALUES ($1, $2, $3, $4, $5)
-ON CONFLICT (node_id) DO UPDATE SET
-    longname            = EXCLUDED.longname,
-    shortname           = EXCLUDED.shortname,
-    hwmodel             = EXCLUDED.hwmodel,
-    deployment_location = EXCLUDED.deployment_location
+WITH
+old AS (
+    SELECT longname, shortname, hwmodel, role, public_key, is_licensed, macaddr
+    FROM NodeInfo WHERE node_id = $1
+),
+new AS (
+    INSERT INTO NodeInfo (node_id, longname, shortname, hwmodel, deployment_location, role,
+        public_key, is_licensed, macaddr, snr, last_heard, hops_away, via_mqtt, is_favorite)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+    ON CONFLICT (node_id) DO UPDATE SET
+        longname            = EXCLUDED.longname,
+        shortname           = EXCLUDED.shortname,
+        hwmodel             = EXCLUDED.hwmodel,
+        deployment_location = EXCLUDED.deployment_location,
+        role                = EXCLUDED.role,
+        public_key          = COALESCE(EXCLUDED.public_key, NodeInfo.public_key),
+        is_licensed         = EXCLUDED.is_licensed,
+        macaddr             = COALESCE(EXCLUDED.macaddr, NodeInfo.macaddr),
+        snr                 = EXCLUDED.snr,
+        last_heard          = COALESCE(EXCLUDED.last_heard, NodeInfo.last_heard),
+        hops_away           = EXCLUDED.hops_away,
+        via_mqtt            = EXCLUDED.via_mqtt,
+        is_favorite         = EXCLUDED.is_favorite
+    RETURNING longname, shortname, hwmodel, role, public_key, is_licensed, macaddr
+)
+INSERT INTO NodeInfoHistory (node_id, time, field, old_value, new_value, source,
+    deployment_location)
+SELECT $1, $15, c.field, c.old_value, c.new_value, $16, $5
+FROM old, new, LATERAL (VALUES
+    ('longname', old.longname, new.longname),
+    ('shortname', old.shortname, new.shortname),
+    ('hwmodel', old.hwmodel::text, new.hwmodel::text),
+    ('role', old.role, new.role),
+    ('public_key', encode(old.public_key, 'hex'), encode(new.public_key, 'hex')),
+    ('is_licensed', old.is_licensed::text, new.is_licensed::text),
+    ('macaddr', encode(old.macaddr, 'hex'), encode(new.macaddr, 'hex'))
+) AS c (field, old_value, new_value)
+WHERE c.old_value IS NOT NULL AND c.old_value IS DISTINCT FROM c.new_value
         ",
         Oid(ni.num),
-        ni.user.as_ref().map(|u| u.long_name.as_str()),
-        ni.user.as_ref().map(|u| u.short_name.as_str()),
-        ni.user.as_ref().map(|u| u.hw_model),
+        user.long_name,
+        user.short_name,
+        user.hw_model,
         loc,
+        enum_name(user.role, Role::as_str_name),
+        (!user.public_key.is_empty()).then_some(user.public_key.as_slice()),
+        user.is_licensed,
+        (!user.macaddr.is_empty()).then_some(user.macaddr.as_slice()),
+        ni.snr,
+        (ni.last_heard != 0).then(|| timestamp(ni.last_heard)),
+        ni.hops_away.and_then(|h| i32::try_from(h).ok()),
+        ni.via_mqtt,
+        ni.is_favorite,
+        Utc::now().naive_utc(),
+        source.as_str(),
     )
     .execute(pool)
     .await
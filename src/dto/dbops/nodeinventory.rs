@@ -0,0 +1,113 @@
+use crate::util::{config::DEPLOYMENT_LOCATION, enum_name, timestamp};
+use anyhow::{Context as _, Error, Result};
+use meshtastic::protobufs::{
+    MapReport, MeshPacket,
+    config::{
+        device_config::Role,
+        lo_ra_config::{ModemPreset, RegionCode},
+    },
+};
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// Upsert a row in the `NodeInventory` table from a node's `MapReport`
+///
+/// `config_since` is when the firmware, role, region or modem preset last changed, so drift
+/// across the fleet shows up next to the latest report.
+pub(crate) async fn upsert(
+    pkt: &MeshPacket,
+    report: &MapReport,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in upsert() for NodeInventory table")?;
+
+    query!(
+        "
+INSERT INTO
+  NodeInventory (
+    node_id,
+    deployment_location,
+    last_report,
+    config_since,
+    long_name,
+    short_name,
+    role,
+    hw_model,
+    firmware_version,
+    region,
+    modem_preset,
+    has_default_channel,
+    latitude,
+    longitude,
+    altitude,
+    position_precision,
+    num_online_local_nodes,
+    has_opted_report_location
+)
+VALUES
+  ($1, $2, $3, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
+ON CONFLICT (node_id, deployment_location) DO UPDATE
+SET
+    last_report = EXCLUDED.last_report,
+    config_since = CASE
+        WHEN (
+            NodeInventory.firmware_version,
+            NodeInventory.role,
+            NodeInventory.region,
+            NodeInventory.modem_preset
+        ) IS DISTINCT FROM (
+            EXCLUDED.firmware_version,
+            EXCLUDED.role,
+            EXCLUDED.region,
+            EXCLUDED.modem_preset
+        ) THEN EXCLUDED.last_report
+        ELSE NodeInventory.config_since
+    END,
+    long_name = EXCLUDED.long_name,
+    short_name = EXCLUDED.short_name,
+    role = EXCLUDED.role,
+    hw_model = EXCLUDED.hw_model,
+    firmware_version = EXCLUDED.firmware_version,
+    region = EXCLUDED.region,
+    modem_preset = EXCLUDED.modem_preset,
+    has_default_channel = EXCLUDED.has_default_channel,
+    latitude = EXCLUDED.latitude,
+    longitude = EXCLUDED.longitude,
+    altitude = EXCLUDED.altitude,
+    position_precision = EXCLUDED.position_precision,
+    num_online_local_nodes = EXCLUDED.num_online_local_nodes,
+    has_opted_report_location = EXCLUDED.has_opted_report_location
+            ",
+        Oid(pkt.from),
+        loc,
+        timestamp(pkt.rx_time),
+        report.long_name,
+        report.short_name,
+        enum_name(report.role, Role::as_str_name),
+        report.hw_model,
+        report.firmware_version,
+        enum_name(report.region, RegionCode::as_str_name),
+        enum_name(report.modem_preset, ModemPreset::as_str_name),
+        report.has_default_channel,
+        // Left out unless the node opted to report its location
+        report
+            .has_opted_report_location
+            .then_some(report.latitude_i),
+        report
+            .has_opted_report_location
+            .then_some(report.longitude_i),
+        report.has_opted_report_location.then_some(report.altitude),
+        Oid(report.position_precision),
+        Oid(report.num_online_local_nodes),
+        report.has_opted_report_location,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to upsert row in NodeInventory table")
+}
@@ -0,0 +1,47 @@
+use crate::util::{config::DEPLOYMENT_LOCATION, timestamp};
+use anyhow::{Context as _, Error, Result};
+use meshtastic::protobufs::MeshPacket;
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// Insert a row into the `PacketSources` table recording the MQTT gateway a packet arrived through
+pub(crate) async fn insert(
+    pkt: &MeshPacket,
+    gateway_id: &str,
+    channel_id: &str,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in insert() for PacketSources table")?;
+
+    query!(
+        "
+INSERT INTO
+  PacketSources (
+    msg_id,
+    node_id,
+    time,
+    gateway_id,
+    channel_id,
+    deployment_location
+)
+VALUES
+  ($1, $2, $3, $4, $5, $6)
+ON CONFLICT (msg_id) DO NOTHING
+            ",
+        Oid(pkt.id),
+        Oid(pkt.from),
+        timestamp(pkt.rx_time),
+        gateway_id,
+        channel_id,
+        loc,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into PacketSources table")
+}
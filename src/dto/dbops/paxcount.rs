@@ -0,0 +1,51 @@
+use crate::util::timestamp;
+use anyhow::{Context as _, Error, Result};
+use meshtastic::protobufs::{MeshPacket, Paxcount};
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// Insert a row into the `Paxcount` table from a `MeshPacket`
+///
+/// Counts carry no time of their own, the packet's receive time is used.
+pub(crate) async fn insert(
+    pkt: &MeshPacket,
+    pax: &Paxcount,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    query!(
+        "
+INSERT INTO
+  Paxcount (
+    msg_id,
+    node_id,
+    time,
+    wifi,
+    ble,
+    uptime
+  )
+VALUES
+  (
+    $1,
+    $2,
+    $3,
+    $4,
+    $5,
+    $6
+  )
+ON CONFLICT (msg_id) DO NOTHING
+            ",
+        Oid(pkt.id),
+        Oid(pkt.from),
+        timestamp(pkt.rx_time),
+        Oid(pax.wifi),
+        Oid(pax.ble),
+        Oid(pax.uptime),
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into Paxcount table")
+}
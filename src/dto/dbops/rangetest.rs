@@ -0,0 +1,70 @@
+use crate::{
+    rangetest::Sample,
+    util::{config::DEPLOYMENT_LOCATION, timestamp},
+};
+use anyhow::{Context as _, Error, Result};
+use meshtastic::protobufs::MeshPacket;
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// Insert a row into the `RangeTest` table for a received range-test message
+pub(crate) async fn insert(
+    pkt: &MeshPacket,
+    sample: &Sample<'_>,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in insert() for RangeTest table")?;
+    let sender = sample.sender.map(|(point, _)| point);
+
+    query!(
+        "
+INSERT INTO
+  RangeTest (
+    msg_id,
+    node_id,
+    time,
+    seq,
+    text,
+    latitude,
+    longitude,
+    position_time,
+    rx_snr,
+    rx_rssi,
+    hop_start,
+    hop_limit,
+    gateway_latitude,
+    gateway_longitude,
+    distance_m,
+    deployment_location
+)
+VALUES
+  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+ON CONFLICT (msg_id) DO NOTHING
+            ",
+        Oid(pkt.id),
+        Oid(pkt.from),
+        timestamp(pkt.rx_time),
+        sample.seq,
+        sample.text,
+        sender.map(|p| p.latitude_i),
+        sender.map(|p| p.longitude_i),
+        sample.sender.map(|(_, time)| time),
+        pkt.rx_snr,
+        pkt.rx_rssi,
+        i32::try_from(pkt.hop_start).ok(),
+        i32::try_from(pkt.hop_limit).ok(),
+        sample.gateway.map(|p| p.latitude_i),
+        sample.gateway.map(|p| p.longitude_i),
+        sample.distance_m,
+        loc,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into RangeTest table")
+}
@@ -0,0 +1,120 @@
+use crate::util::{config::DEPLOYMENT_LOCATION, timestamp};
+use anyhow::{Context as _, Error, Result};
+use meshtastic::{
+    Message,
+    protobufs::{
+        AdminMessage, Compressed, Data, HardwareMessage, MeshPacket, PortNum, PowerStressMessage,
+        TakPacket,
+    },
+};
+use serde_json::{Value, json};
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+use std::fmt::Debug;
+
+/// Debug form of a protobuf message, `None` if the bytes are not one
+fn debug_json<M: Message + Default + Debug>(bytes: &[u8]) -> Option<Value> {
+    M::decode(bytes)
+        .ok()
+        .map(|m| json!({ "debug": format!("{m:?}") }))
+}
+
+/// Best-effort JSON form of a payload the handler does not process, `None` if it does not decode
+///
+/// The protobufs have no serde support, decoded messages are kept in their debug form.
+fn decoded(port: PortNum, bytes: &[u8]) -> Option<Value> {
+    match port {
+        PortNum::TextMessageApp | PortNum::RangeTestApp | PortNum::ReplyApp => {
+            str::from_utf8(bytes).ok().map(|t| json!({ "text": t }))
+        }
+        PortNum::RemoteHardwareApp => debug_json::<HardwareMessage>(bytes),
+        PortNum::AdminApp => debug_json::<AdminMessage>(bytes),
+        PortNum::TextMessageCompressedApp => debug_json::<Compressed>(bytes),
+        PortNum::AtakPlugin | PortNum::AtakForwarder => debug_json::<TakPacket>(bytes),
+        PortNum::PowerstressApp => debug_json::<PowerStressMessage>(bytes),
+        _ => None,
+    }
+}
+
+/// Insert a row into the `RawPackets` table for a payload no other table takes
+///
+/// The raw bytes are kept so the history can be processed once a port gets a table.
+pub(crate) async fn insert(
+    pkt: &MeshPacket,
+    data: &Data,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in insert() for RawPackets table")?;
+    // Unset fixed32 ids are zero on the wire
+    let id = |n: u32| (n != 0).then_some(Oid(n));
+
+    query!(
+        "
+INSERT INTO
+  RawPackets (
+    msg_id,
+    node_id,
+    time,
+    portnum,
+    payload,
+    request_id,
+    reply_id,
+    dest,
+    source,
+    bitfield,
+    decoded,
+    deployment_location
+)
+VALUES
+  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+ON CONFLICT (msg_id) DO NOTHING
+            ",
+        Oid(pkt.id),
+        Oid(pkt.from),
+        timestamp(pkt.rx_time),
+        data.portnum,
+        data.payload,
+        id(data.request_id),
+        id(data.reply_id),
+        id(data.dest),
+        id(data.source),
+        data.bitfield.map(Oid),
+        decoded(data.portnum(), &data.payload),
+        loc,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into RawPackets table")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payloads_decode_as_text_messages_or_nothing() -> Result<()> {
+        assert_eq!(
+            decoded(PortNum::RangeTestApp, b"seq 12"),
+            Some(json!({ "text": "seq 12" }))
+        );
+        let hw = HardwareMessage {
+            gpio_mask: 7,
+            ..Default::default()
+        };
+        let json =
+            decoded(PortNum::RemoteHardwareApp, &hw.encode_to_vec()).context("not decoded")?;
+        let Some(debug) = json["debug"].as_str() else {
+            anyhow::bail!("expected a debug string, got {json}");
+        };
+        assert!(debug.contains("gpio_mask: 7"));
+        assert_eq!(decoded(PortNum::SerialApp, b"\x01\x02"), None);
+        assert_eq!(decoded(PortNum::TextMessageApp, b"\xff"), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use crate::util::{config::DEPLOYMENT_LOCATION, enum_name, timestamp};
+use anyhow::{Context as _, Error, Result};
+use meshtastic::protobufs::{Data, MeshPacket, routing};
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// Name of a routing error code, e.g. `NO_ROUTE`, `NONE` for an ACK
+///
+/// Codes newer than the protobufs are kept as their number.
+pub(crate) fn reason_name(code: i32) -> String {
+    enum_name(code, routing::Error::as_str_name)
+}
+
+/// Insert a row into the `Routing` table from an ACK or NAK
+///
+/// The packet comes from the node reporting the outcome and goes to the sender of the packet
+/// it answers, `request_id` names that packet.
+pub(crate) async fn insert(
+    pkt: &MeshPacket,
+    data: &Data,
+    reason: &str,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in insert() for Routing table")?;
+
+    query!(
+        "
+INSERT INTO
+  Routing (
+    msg_id,
+    node_id,
+    dest,
+    time,
+    request_id,
+    error_reason,
+    deployment_location
+  )
+VALUES
+  (
+    $1,
+    $2,
+    $3,
+    $4,
+    $5,
+    $6,
+    $7
+  )
+ON CONFLICT (msg_id) DO NOTHING
+            ",
+        Oid(pkt.id),
+        Oid(pkt.from),
+        Oid(pkt.to),
+        timestamp(pkt.rx_time),
+        Oid(data.request_id),
+        reason,
+        loc,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into Routing table")
+}
+
+/// Count an ACK or NAK in the `RoutingPairs` table for its node pair and reason
+pub(crate) async fn count(
+    pkt: &MeshPacket,
+    reason: &str,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in count() for RoutingPairs table")?;
+    let time = timestamp(pkt.rx_time);
+
+    query!(
+        "
+INSERT INTO
+  RoutingPairs (
+    node_id,
+    dest,
+    error_reason,
+    deployment_location,
+    count,
+    first_seen,
+    last_seen
+  )
+VALUES
+  ($1, $2, $3, $4, 1, $5, $5)
+ON CONFLICT (node_id, dest, error_reason, deployment_location) DO UPDATE
+SET
+    count = RoutingPairs.count + 1,
+    first_seen = LEAST(RoutingPairs.first_seen, EXCLUDED.first_seen),
+    last_seen = GREATEST(RoutingPairs.last_seen, EXCLUDED.last_seen)
+            ",
+        Oid(pkt.from),
+        Oid(pkt.to),
+        reason,
+        loc,
+        time,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to count row in RoutingPairs table")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reasons_are_named() {
+        assert_eq!(reason_name(0), "NONE");
+        assert_eq!(reason_name(5), "MAX_RETRANSMIT");
+        assert_eq!(reason_name(99), "99");
+    }
+}
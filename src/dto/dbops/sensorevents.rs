@@ -0,0 +1,61 @@
+use crate::util::{config::DEPLOYMENT_LOCATION, timestamp};
+use anyhow::{Context as _, Error, Result};
+use meshtastic::protobufs::{MeshPacket, PortNum};
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// Insert a row into the `SensorEvents` table from a detection sensor or alert message
+pub(crate) async fn insert(
+    pkt: &MeshPacket,
+    port: PortNum,
+    text: &str,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in insert() for SensorEvents table")?;
+
+    query!(
+        "
+INSERT INTO
+  SensorEvents (
+    msg_id,
+    node_id,
+    dest,
+    time,
+    portnum,
+    text,
+    channel,
+    rx_snr,
+    rx_rssi,
+    hop_start,
+    hop_limit,
+    via_mqtt,
+    deployment_location
+)
+VALUES
+  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+ON CONFLICT (msg_id) DO NOTHING
+            ",
+        Oid(pkt.id),
+        Oid(pkt.from),
+        Oid(pkt.to),
+        timestamp(pkt.rx_time),
+        port.as_str_name(),
+        text,
+        i32::try_from(pkt.channel).ok(),
+        pkt.rx_snr,
+        pkt.rx_rssi,
+        i32::try_from(pkt.hop_start).ok(),
+        i32::try_from(pkt.hop_limit).ok(),
+        pkt.via_mqtt,
+        loc,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into SensorEvents table")
+}
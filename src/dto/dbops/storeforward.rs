@@ -0,0 +1,160 @@
+use crate::util::{config::DEPLOYMENT_LOCATION, timestamp};
+use anyhow::{Context as _, Error, Result};
+use meshtastic::protobufs::{
+    MeshPacket,
+    store_and_forward::{Heartbeat, History, Statistics},
+};
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// Upsert a store-and-forward router into the `StoreForwardRouters` table from its heartbeat
+pub(crate) async fn heartbeat(
+    pkt: &MeshPacket,
+    hb: &Heartbeat,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION.get().context(
+        "Unable to get DEPLOYMENT_LOCATION in heartbeat() for StoreForwardRouters table",
+    )?;
+
+    query!(
+        "
+INSERT INTO
+  StoreForwardRouters (
+    node_id,
+    deployment_location,
+    heartbeat_period,
+    secondary,
+    last_heartbeat
+)
+VALUES
+  ($1, $2, $3, $4, $5)
+ON CONFLICT (node_id, deployment_location) DO UPDATE
+SET
+    heartbeat_period = EXCLUDED.heartbeat_period,
+    secondary = EXCLUDED.secondary,
+    last_heartbeat = EXCLUDED.last_heartbeat
+            ",
+        Oid(pkt.from),
+        loc,
+        Oid(hb.period),
+        hb.secondary != 0,
+        timestamp(pkt.rx_time),
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to upsert row in StoreForwardRouters table")
+}
+
+/// Upsert a store-and-forward router into the `StoreForwardRouters` table from the start of a
+/// history replay
+///
+/// `window` is the number of minutes of history the router replays.
+pub(crate) async fn history(
+    pkt: &MeshPacket,
+    h: &History,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in history() for StoreForwardRouters table")?;
+
+    query!(
+        "
+INSERT INTO
+  StoreForwardRouters (
+    node_id,
+    deployment_location,
+    last_replay,
+    replay_messages,
+    replay_window
+)
+VALUES
+  ($1, $2, $3, $4, $5)
+ON CONFLICT (node_id, deployment_location) DO UPDATE
+SET
+    last_replay = EXCLUDED.last_replay,
+    replay_messages = EXCLUDED.replay_messages,
+    replay_window = EXCLUDED.replay_window
+            ",
+        Oid(pkt.from),
+        loc,
+        timestamp(pkt.rx_time),
+        Oid(h.history_messages),
+        Oid(h.window),
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to upsert row in StoreForwardRouters table")
+}
+
+/// Insert a row into the `StoreForwardStats` table from a router's statistics
+pub(crate) async fn insert_stats(
+    pkt: &MeshPacket,
+    st: &Statistics,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION.get().context(
+        "Unable to get DEPLOYMENT_LOCATION in insert_stats() for StoreForwardStats table",
+    )?;
+
+    query!(
+        "
+INSERT INTO
+  StoreForwardStats (
+    msg_id,
+    node_id,
+    time,
+    messages_total,
+    messages_saved,
+    messages_max,
+    up_time,
+    requests,
+    requests_history,
+    heartbeat,
+    return_max,
+    return_window,
+    deployment_location
+  )
+VALUES
+  (
+    $1,
+    $2,
+    $3,
+    $4,
+    $5,
+    $6,
+    $7,
+    $8,
+    $9,
+    $10,
+    $11,
+    $12,
+    $13
+  )
+ON CONFLICT (msg_id) DO NOTHING
+            ",
+        Oid(pkt.id),
+        Oid(pkt.from),
+        timestamp(pkt.rx_time),
+        Oid(st.messages_total),
+        Oid(st.messages_saved),
+        Oid(st.messages_max),
+        Oid(st.up_time),
+        Oid(st.requests),
+        Oid(st.requests_history),
+        st.heartbeat,
+        Oid(st.return_max),
+        Oid(st.return_window),
+        loc,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into StoreForwardStats table")
+}
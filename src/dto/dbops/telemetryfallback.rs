@@ -0,0 +1,51 @@
+use crate::util::timestamp;
+use anyhow::{Context as _, Error, Result};
+use meshtastic::protobufs::{MeshPacket, Telemetry};
+use serde_json::Value;
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// Insert a row into the `TelemetryFallback` table for telemetry without a table of its own
+///
+/// `variant` is `None` for variants newer than the protobufs of this build, `data` then
+/// holds the undecoded payload.
+pub(crate) async fn insert(
+    pkt: &MeshPacket,
+    tm: &Telemetry,
+    variant: Option<&str>,
+    data: Value,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    query!(
+        "
+INSERT INTO
+  TelemetryFallback (
+    msg_id,
+    node_id,
+    time,
+    variant,
+    data
+  )
+VALUES
+  (
+    $1,
+    $2,
+    $3,
+    $4,
+    $5
+  )
+            ",
+        Oid(pkt.id),
+        Oid(pkt.from),
+        timestamp(tm.time),
+        variant,
+        data,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into TelemetryFallback table")
+}
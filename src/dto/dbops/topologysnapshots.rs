@@ -0,0 +1,33 @@
+use crate::{topology::Graph, util::config::DEPLOYMENT_LOCATION};
+use anyhow::{Context as _, Error, Result};
+use chrono::NaiveDateTime;
+use serde_json::to_value;
+use sqlx::{Pool, Postgres, postgres::PgQueryResult, query};
+
+/// Insert a row into the `TopologySnapshots` table
+pub(crate) async fn insert(
+    time: NaiveDateTime,
+    graph: &Graph,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in insert() for TopologySnapshots table")?;
+
+    query!(
+        "
+INSERT INTO TopologySnapshots (deployment_location, time, nodes, links, graph)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (deployment_location, time) DO NOTHING
+        ",
+        loc,
+        time,
+        i32::try_from(graph.nodes().len()).unwrap_or(i32::MAX),
+        i32::try_from(graph.links.len()).unwrap_or(i32::MAX),
+        to_value(graph).context("Failed to serialize topology graph")?,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into TopologySnapshots table")
+}
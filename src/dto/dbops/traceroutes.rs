@@ -0,0 +1,94 @@
+use crate::util::{config::DEPLOYMENT_LOCATION, state::Probe, timestamp};
+use anyhow::{Context as _, Error, Result};
+use chrono::{DateTime, Utc};
+use meshtastic::protobufs::{MeshPacket, RouteDiscovery};
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// SNR the firmware reports for hops it could not measure, before scaling
+const UNKNOWN_SNR: i32 = i8::MIN as i32;
+
+/// SNR values of a route in dB, `None` where the hop was not measured
+#[expect(
+    clippy::cast_precision_loss,
+    reason = "SNR values are quarter dB steps within an i8"
+)]
+fn snr_db(snr: &[i32]) -> Vec<Option<f32>> {
+    snr.iter()
+        .map(|s| (*s != UNKNOWN_SNR).then(|| *s as f32 / 4.0))
+        .collect()
+}
+
+/// Insert a row into the `Traceroutes` table for the reply to a traceroute the gateway sent
+pub(crate) async fn insert(
+    pkt: &MeshPacket,
+    request_id: u32,
+    probe: Probe,
+    rd: &RouteDiscovery,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in insert() for Traceroutes table")?;
+    let rtt_ms = i32::try_from(Utc::now().timestamp_millis() - probe.sent_ms).unwrap_or(i32::MAX);
+    let sent = DateTime::from_timestamp_millis(probe.sent_ms)
+        .context("Traceroute send time out of range")?
+        .naive_utc();
+    let route = rd.route.iter().copied().map(Oid).collect::<Vec<_>>();
+    let route_back = rd.route_back.iter().copied().map(Oid).collect::<Vec<_>>();
+    let snr_towards = snr_db(&rd.snr_towards);
+    let snr_back = snr_db(&rd.snr_back);
+
+    query!(
+        "
+INSERT INTO
+  Traceroutes (
+    msg_id,
+    node_id,
+    time,
+    request_id,
+    sent,
+    rtt_ms,
+    route,
+    snr_towards,
+    route_back,
+    snr_back,
+    deployment_location
+)
+VALUES
+  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+ON CONFLICT (msg_id) DO NOTHING
+            ",
+        Oid(pkt.id),
+        Oid(probe.node_id),
+        timestamp(pkt.rx_time),
+        Oid(request_id),
+        sent,
+        rtt_ms,
+        route.as_slice(),
+        snr_towards.as_slice() as &[Option<f32>],
+        route_back.as_slice(),
+        snr_back.as_slice() as &[Option<f32>],
+        loc,
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to insert row into Traceroutes table")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snr_is_scaled_and_unknown_hops_are_null() {
+        assert_eq!(
+            snr_db(&[40, -10, UNKNOWN_SNR]),
+            [Some(10.0), Some(-2.5), None]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use crate::util::{config::DEPLOYMENT_LOCATION, timestamp};
+use anyhow::{Context as _, Error, Result};
+use chrono::DateTime;
+use meshtastic::protobufs::{MeshPacket, Waypoint};
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgQueryResult, types::Oid},
+    query,
+};
+
+/// Whether a waypoint update deletes the waypoint
+///
+/// An `expire` of 0 means the waypoint never expires, apps delete one by sending it with an
+/// expiry in the past.
+pub(crate) fn is_deletion(wp: &Waypoint, now: i64) -> bool {
+    wp.expire != 0 && i64::from(wp.expire) <= now
+}
+
+/// Upsert a row in the `Waypoints` table by waypoint id within the deployment
+///
+/// Waypoints locked to a node are only updated by that node.
+pub(crate) async fn upsert(
+    pkt: &MeshPacket,
+    wp: &Waypoint,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in upsert() for Waypoints table")?;
+    let icon = char::from_u32(wp.icon)
+        .filter(|_| wp.icon != 0)
+        .map(String::from);
+    let expire = (wp.expire != 0)
+        .then(|| DateTime::from_timestamp(i64::from(wp.expire), 0))
+        .flatten()
+        .map(|dt| dt.naive_utc());
+
+    query!(
+        "
+INSERT INTO
+  Waypoints (
+    waypoint_id,
+    deployment_location,
+    node_id,
+    msg_id,
+    time,
+    name,
+    description,
+    icon,
+    latitude,
+    longitude,
+    expire,
+    locked_to
+)
+VALUES
+  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+ON CONFLICT (waypoint_id, deployment_location) DO UPDATE
+SET
+    node_id = EXCLUDED.node_id,
+    msg_id = EXCLUDED.msg_id,
+    time = EXCLUDED.time,
+    name = EXCLUDED.name,
+    description = EXCLUDED.description,
+    icon = EXCLUDED.icon,
+    latitude = EXCLUDED.latitude,
+    longitude = EXCLUDED.longitude,
+    expire = EXCLUDED.expire,
+    locked_to = EXCLUDED.locked_to
+WHERE Waypoints.locked_to IS NULL OR Waypoints.locked_to = EXCLUDED.node_id
+            ",
+        Oid(wp.id),
+        loc,
+        Oid(pkt.from),
+        Oid(pkt.id),
+        timestamp(pkt.rx_time),
+        wp.name,
+        wp.description,
+        icon,
+        wp.latitude_i,
+        wp.longitude_i,
+        expire,
+        (wp.locked_to != 0).then_some(Oid(wp.locked_to)),
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to upsert row in Waypoints table")
+}
+
+/// Delete a waypoint of the deployment from the `Waypoints` table
+///
+/// Waypoints locked to a node are only deleted by that node.
+pub(crate) async fn delete(
+    pkt: &MeshPacket,
+    wp: &Waypoint,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let loc = DEPLOYMENT_LOCATION
+        .get()
+        .context("Unable to get DEPLOYMENT_LOCATION in delete() for Waypoints table")?;
+
+    query!(
+        "
+DELETE FROM Waypoints
+WHERE
+    waypoint_id = $1
+    AND deployment_location = $2
+    AND (locked_to IS NULL OR locked_to = $3)
+            ",
+        Oid(wp.id),
+        loc,
+        Oid(pkt.from),
+    )
+    .execute(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to delete row from Waypoints table")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn past_expiry_deletes_and_zero_never_expires() {
+        let now = 1_760_000_000;
+        let wp = |expire| Waypoint {
+            id: 1,
+            expire,
+            ..Default::default()
+        };
+        assert!(!is_deletion(&wp(0), now));
+        assert!(is_deletion(&wp(1), now));
+        assert!(!is_deletion(&wp(1_760_003_600), now));
+    }
+}
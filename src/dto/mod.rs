@@ -1,4 +1,10 @@
 /// Database operations for inserts and updates
 pub(crate) mod dbops;
+/// JSON view of decoded packets shared by the packet sinks
+pub(crate) mod packet;
 /// Packet handling functions for packets received over a serial connection to a Meshtastic node
 pub(crate) mod packet_handler;
+/// Configuration snapshot of the serial node
+pub(crate) mod radio;
+/// Flat numeric view of telemetry variants shared by the rules and sinks
+pub(crate) mod telemetry;
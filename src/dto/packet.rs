@@ -0,0 +1,306 @@
+#![cfg_attr(
+    not(any(feature = "api", feature = "ndjson", test)),
+    expect(dead_code, reason = "only serialized by the packet sinks")
+)]
+
+use crate::dto::{
+    dbops::routing::reason_name,
+    telemetry::{fields, kind},
+};
+use meshtastic::protobufs::{
+    MapReport, MeshPacket, NeighborInfo, NodeInfo, Paxcount, PortNum, Position, RouteDiscovery,
+    Routing, StoreAndForward, Telemetry, Waypoint, routing, store_and_forward,
+};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::fmt::Write as _;
+
+/// A payload as decoded by the packet handler
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Payload<'a> {
+    /// `POSITION_APP`
+    Position(&'a Position),
+    /// `NODEINFO_APP`
+    NodeInfo(&'a NodeInfo),
+    /// `TELEMETRY_APP`
+    Telemetry(&'a Telemetry),
+    /// `NEIGHBORINFO_APP`
+    NeighborInfo(&'a NeighborInfo),
+    /// `TRACEROUTE_APP`
+    RouteDiscovery(&'a RouteDiscovery),
+    /// `PAXCOUNTER_APP`
+    Paxcount(&'a Paxcount),
+    /// `WAYPOINT_APP`
+    Waypoint(&'a Waypoint),
+    /// `ROUTING_APP`
+    Routing(&'a Routing),
+    /// `STORE_FORWARD_APP`
+    StoreAndForward(&'a StoreAndForward),
+    /// `MAP_REPORT_APP`
+    MapReport(&'a MapReport),
+    /// Ports the handler does not decode, kept as bytes
+    Raw(&'a [u8]),
+}
+
+/// A decoded `MeshPacket` with its metadata, as streamed and archived
+#[derive(Debug, Serialize)]
+pub(crate) struct Decoded {
+    /// Sending node number
+    pub(crate) from: u32,
+    /// Destination node number
+    pub(crate) to: u32,
+    /// Packet id
+    pub(crate) id: u32,
+    /// Channel index
+    pub(crate) channel: u32,
+    /// Unix time the packet was received
+    pub(crate) rx_time: u32,
+    /// Receive SNR in dB
+    pub(crate) rx_snr: f32,
+    /// Receive RSSI in dBm
+    pub(crate) rx_rssi: i32,
+    /// Hops left
+    pub(crate) hop_limit: u32,
+    /// Hop limit the packet was sent with
+    pub(crate) hop_start: u32,
+    /// Whether the packet went through MQTT on its way
+    pub(crate) via_mqtt: bool,
+    /// Port name, e.g. `TELEMETRY_APP`
+    pub(crate) portnum: &'static str,
+    /// Telemetry type of `TELEMETRY_APP` packets, see [`kind`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) variant: Option<&'static str>,
+    /// Decoded payload, its shape depends on `portnum`
+    pub(crate) payload: Value,
+}
+
+impl Decoded {
+    /// Pairs the packet metadata with its decoded payload
+    pub(crate) fn new(pkt: &MeshPacket, port: PortNum, payload: Payload<'_>) -> Self {
+        Self {
+            from: pkt.from,
+            to: pkt.to,
+            id: pkt.id,
+            channel: pkt.channel,
+            rx_time: pkt.rx_time,
+            rx_snr: pkt.rx_snr,
+            rx_rssi: pkt.rx_rssi,
+            hop_limit: pkt.hop_limit,
+            hop_start: pkt.hop_start,
+            via_mqtt: pkt.via_mqtt,
+            portnum: port.as_str_name(),
+            variant: match payload {
+                Payload::Telemetry(tm) => tm.variant.as_ref().map(kind),
+                _ => None,
+            },
+            payload: payload_json(port, payload),
+        }
+    }
+}
+
+/// JSON form of a payload, text ports are kept as text when valid UTF-8
+fn payload_json(port: PortNum, payload: Payload<'_>) -> Value {
+    match payload {
+        Payload::Position(pos) => json!({
+            "latitude_i": pos.latitude_i,
+            "longitude_i": pos.longitude_i,
+            "altitude": pos.altitude,
+            "time": pos.time,
+            "ground_speed": pos.ground_speed,
+            "ground_track": pos.ground_track,
+            "sats_in_view": pos.sats_in_view,
+            "precision_bits": pos.precision_bits,
+        }),
+        Payload::NodeInfo(ni) => {
+            let user = ni.user.as_ref();
+            json!({
+                "num": ni.num,
+                "id": user.map(|u| u.id.as_str()),
+                "long_name": user.map(|u| u.long_name.as_str()),
+                "short_name": user.map(|u| u.short_name.as_str()),
+                "hw_model": user.map(|u| u.hw_model),
+                "snr": ni.snr,
+                "last_heard": ni.last_heard,
+                "hops_away": ni.hops_away,
+            })
+        }
+        Payload::Telemetry(tm) => {
+            let mut fields = tm
+                .variant
+                .as_ref()
+                .map_or_else(|| json!({}), |v| json!(fields(v)));
+            if let Value::Object(map) = &mut fields {
+                map.insert(String::from("time"), json!(tm.time));
+            }
+            fields
+        }
+        Payload::NeighborInfo(ni) => json!({
+            "node_id": ni.node_id,
+            "last_sent_by_id": ni.last_sent_by_id,
+            "node_broadcast_interval_secs": ni.node_broadcast_interval_secs,
+            "neighbors": ni
+                .neighbors
+                .iter()
+                .map(|n| json!({ "node_id": n.node_id, "snr": n.snr }))
+                .collect::<Vec<_>>(),
+        }),
+        Payload::RouteDiscovery(rd) => json!({
+            "route": rd.route,
+            "snr_towards": rd.snr_towards,
+            "route_back": rd.route_back,
+            "snr_back": rd.snr_back,
+        }),
+        Payload::Paxcount(pax) => json!({
+            "wifi": pax.wifi,
+            "ble": pax.ble,
+            "uptime": pax.uptime,
+        }),
+        Payload::Waypoint(wp) => json!({
+            "id": wp.id,
+            "latitude_i": wp.latitude_i,
+            "longitude_i": wp.longitude_i,
+            "expire": wp.expire,
+            "locked_to": wp.locked_to,
+            "name": wp.name,
+            "description": wp.description,
+            "icon": wp.icon,
+        }),
+        Payload::Routing(r) => match &r.variant {
+            Some(routing::Variant::ErrorReason(code)) => json!({
+                "error_reason": reason_name(*code),
+            }),
+            Some(routing::Variant::RouteRequest(rd)) => json!({ "route_request": rd.route }),
+            Some(routing::Variant::RouteReply(rd)) => json!({ "route_reply": rd.route }),
+            None => json!({}),
+        },
+        Payload::StoreAndForward(sf) => store_forward_json(sf),
+        Payload::MapReport(report) => map_report_json(report),
+        Payload::Raw(bytes) => match (port, str::from_utf8(bytes)) {
+            (
+                PortNum::TextMessageApp
+                | PortNum::DetectionSensorApp
+                | PortNum::AlertApp
+                | PortNum::RangeTestApp
+                | PortNum::ReplyApp,
+                Ok(text),
+            ) => json!({ "text": text }),
+            _ => json!({ "hex": hex(bytes) }),
+        },
+    }
+}
+
+/// JSON form of a `MapReport`, enums as their numbers like the other payloads
+fn map_report_json(report: &MapReport) -> Value {
+    json!({
+        "long_name": report.long_name,
+        "short_name": report.short_name,
+        "role": report.role,
+        "hw_model": report.hw_model,
+        "firmware_version": report.firmware_version,
+        "region": report.region,
+        "modem_preset": report.modem_preset,
+        "has_default_channel": report.has_default_channel,
+        "latitude_i": report.latitude_i,
+        "longitude_i": report.longitude_i,
+        "altitude": report.altitude,
+        "position_precision": report.position_precision,
+        "num_online_local_nodes": report.num_online_local_nodes,
+        "has_opted_report_location": report.has_opted_report_location,
+    })
+}
+
+/// JSON form of a store-and-forward message, tagged with its request or response type
+fn store_forward_json(sf: &StoreAndForward) -> Value {
+    let rr =
+        store_and_forward::RequestResponse::try_from(sf.rr).map_or("UNSET", |rr| rr.as_str_name());
+    match &sf.variant {
+        Some(store_and_forward::Variant::Stats(st)) => json!({
+            "rr": rr,
+            "messages_total": st.messages_total,
+            "messages_saved": st.messages_saved,
+            "messages_max": st.messages_max,
+            "up_time": st.up_time,
+            "requests": st.requests,
+            "requests_history": st.requests_history,
+            "heartbeat": st.heartbeat,
+            "return_max": st.return_max,
+            "return_window": st.return_window,
+        }),
+        Some(store_and_forward::Variant::History(h)) => json!({
+            "rr": rr,
+            "history_messages": h.history_messages,
+            "window": h.window,
+            "last_request": h.last_request,
+        }),
+        Some(store_and_forward::Variant::Heartbeat(hb)) => json!({
+            "rr": rr,
+            "period": hb.period,
+            "secondary": hb.secondary,
+        }),
+        Some(store_and_forward::Variant::Text(text)) => json!({
+            "rr": rr,
+            "text": String::from_utf8_lossy(text),
+        }),
+        None => json!({ "rr": rr }),
+    }
+}
+
+/// Lowercase hex form of raw payload bytes
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+            let _ = write!(s, "{b:02x}");
+            s
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use meshtastic::protobufs::{EnvironmentMetrics, telemetry::Variant};
+
+    fn packet() -> MeshPacket {
+        MeshPacket {
+            from: 0x1234_5678,
+            to: u32::MAX,
+            id: 42,
+            rx_time: 1_760_000_000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn telemetry_carries_its_variant_and_time() -> serde_json::Result<()> {
+        let tm = Telemetry {
+            time: 1_759_999_990,
+            variant: Some(Variant::EnvironmentMetrics(EnvironmentMetrics {
+                temperature: Some(21.5),
+                ..Default::default()
+            })),
+        };
+        let decoded = Decoded::new(&packet(), PortNum::TelemetryApp, Payload::Telemetry(&tm));
+        let v = serde_json::to_value(&decoded)?;
+        assert_eq!(v["portnum"], "TELEMETRY_APP");
+        assert_eq!(v["variant"], "environment");
+        assert_eq!(
+            v["payload"],
+            json!({ "temperature": 21.5, "time": 1_759_999_990 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn raw_payloads_are_text_or_hex() -> serde_json::Result<()> {
+        let text = Decoded::new(&packet(), PortNum::TextMessageApp, Payload::Raw(b"hi"));
+        assert_eq!(text.payload, json!({ "text": "hi" }));
+        assert!(text.variant.is_none());
+        assert!(!serde_json::to_string(&text)?.contains("variant"));
+
+        let binary = Decoded::new(&packet(), PortNum::TextMessageApp, Payload::Raw(&[0xff, 0]));
+        assert_eq!(binary.payload, json!({ "hex": "ff00" }));
+        let serial = Decoded::new(&packet(), PortNum::SerialApp, Payload::Raw(b"hi"));
+        assert_eq!(serial.payload, json!({ "hex": "6869" }));
+        Ok(())
+    }
+}
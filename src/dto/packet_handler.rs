@@ -1,57 +1,90 @@
 use crate::{
-    dto::dbops::{
-        airqualitymetrics, devicemetrics, environmentmetrics, errormetrics, localstats,
-        neighborinfo, nodeinfo, powermetrics,
+    dto::{
+        dbops::{
+            airqualitymetrics, alerts, devicemetrics, environmentmetrics, errormetrics,
+            gatewayradio, healthmetrics, localstats, neighborinfo, nodeinfo, nodeinfo::Source,
+            nodeinventory, paxcount, powermetrics, rawpackets, routing, sensorevents, storeforward,
+            telemetryfallback, traceroutes, waypoints,
+        },
+        packet::{Payload, hex},
     },
-    util::state::GatewayState,
+    rangetest,
+    sinks::Sinks,
+    topology::Link,
+    util::{config::RANGE_TEST, state::GatewayState, timestamp},
 };
+use chrono::Utc;
 #[cfg(feature = "trace")]
 use meshtastic::protobufs::{
-    AdminMessage, Compressed, Data, HardwareMessage, MapReport, Paxcount, PowerStressMessage,
-    RouteDiscovery, Routing, StoreAndForward, TakPacket, Waypoint,
+    AdminMessage, Compressed, HardwareMessage, PowerStressMessage, TakPacket,
 };
 use meshtastic::{
     Message as _,
     protobufs::{
-        FromRadio, MeshPacket, NeighborInfo, NodeInfo, PortNum, Position, Telemetry, from_radio,
-        mesh_packet, telemetry::Variant,
+        Data, FromRadio, MapReport, MeshPacket, NeighborInfo, NodeInfo, Paxcount, PortNum,
+        Position, RouteDiscovery, Routing, StoreAndForward, Telemetry, Waypoint, from_radio,
+        mesh_packet, routing::Variant as RoutingVariant, store_and_forward::Variant as SfVariant,
+        telemetry::Variant,
     },
 };
+use serde_json::json;
 use sqlx::{Pool, Postgres};
 #[cfg(feature = "trace")]
 use std::fmt::Debug;
 
 /// Dispatches a `FromRadio` packet to the appropriate database insert or upsert.
-pub(crate) async fn process_packet(pkt: &FromRadio, state: &GatewayState, pool: &Pool<Postgres>) {
+///
+/// Without a database the packets are only handed to the sinks.
+pub(crate) async fn process_packet(
+    pkt: &FromRadio,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
     if let Some(pv) = &pkt.payload_variant {
         match pv {
             from_radio::PayloadVariant::Packet(mesh_packet) => {
-                decode_payload(mesh_packet, state, pool).await;
+                decode_payload(mesh_packet, state, pool, sinks).await;
             }
             from_radio::PayloadVariant::NodeInfo(node_info) => {
-                let (dm_result, ni_result) = tokio::join!(
-                    devicemetrics::upsert_fr(pkt, node_info, pool),
-                    nodeinfo::upsert(node_info, pool),
-                );
-                match dm_result {
-                    Ok(_) => {
-                        tracing::info!(table = "DeviceMetrics", "upserted 1 row");
+                if let Some(pool) = pool {
+                    let (dm_result, ni_result) = tokio::join!(
+                        devicemetrics::upsert_fr(pkt, node_info, pool),
+                        nodeinfo::upsert(node_info, Source::Serial, pool),
+                    );
+                    match dm_result {
+                        Ok(_) => {
+                            tracing::info!(table = "DeviceMetrics", "upserted 1 row");
+                        }
+                        Err(e) => {
+                            state.db_error();
+                            tracing::error!(%e, table = "DeviceMetrics", "upsert failed");
+                        }
                     }
-                    Err(e) => tracing::error!(%e, table = "DeviceMetrics", "upsert failed"),
-                }
 
-                match ni_result {
-                    Ok(_) => tracing::info!(table = "NodeInfo", "upserted 1 row"),
-                    Err(e) => tracing::error!(%e, table = "NodeInfo", "upsert failed"),
-                }
-                // insert into GatewayState
-                #[cfg(feature = "debug")]
-                if let Some(user) = &node_info.user {
-                    match state.insert(node_info.num, user) {
-                        Ok(()) => tracing::trace!("Added {} to GatewayState", node_info.num),
-                        Err(e) => tracing::warn!(%e),
+                    match ni_result {
+                        Ok(r) => {
+                            tracing::info!(
+                                table = "NodeInfo",
+                                history_rows = r.rows_affected(),
+                                "upserted 1 row"
+                            );
+                        }
+                        Err(e) => {
+                            state.db_error();
+                            tracing::error!(%e, table = "NodeInfo", "upsert failed");
+                        }
                     }
                 }
+                track_node(node_info, state);
+            }
+            from_radio::PayloadVariant::Metadata(_)
+            | from_radio::PayloadVariant::Config(_)
+            | from_radio::PayloadVariant::ModuleConfig(_)
+            | from_radio::PayloadVariant::Channel(_) => state.radio_frame(pv),
+            from_radio::PayloadVariant::ConfigCompleteId(id) => {
+                tracing::debug!(config_id = id, "serial node configuration complete");
+                store_radio(state, pool).await;
             }
             from_radio::PayloadVariant::MyInfo(my_node_info) => {
                 #[cfg(feature = "trace")]
@@ -71,36 +104,51 @@ pub(crate) async fn process_packet(pkt: &FromRadio, state: &GatewayState, pool:
     }
 }
 
+/// Stores the configuration the serial node sent, unless it is unchanged since the last time
+async fn store_radio(state: &GatewayState, pool: Option<&Pool<Postgres>>) {
+    let snapshot = state.take_radio();
+    let Some(pool) = pool.filter(|_| !snapshot.is_empty()) else {
+        return;
+    };
+    match gatewayradio::record(state.serial_number(), &snapshot, pool).await {
+        Ok(r) if r.rows_affected() == 0 => {
+            tracing::info!(
+                table = "GatewayRadio",
+                "serial node configuration unchanged"
+            );
+        }
+        Ok(_) => tracing::warn!(table = "GatewayRadio", "serial node configuration changed"),
+        Err(e) => {
+            state.db_error();
+            tracing::error!(%e, table = "GatewayRadio", "insert failed");
+        }
+    }
+}
+
+/// Updates the node in `GatewayState`, the `NodeInfo` upsert records what changed
+fn track_node(ni: &NodeInfo, state: &GatewayState) {
+    if let Some(user) = &ni.user
+        && !state.update(ni.num, user)
+    {
+        tracing::trace!("Node {} unchanged in GatewayState", ni.num);
+    }
+}
+
 #[cfg(feature = "trace")]
 fn trace_fromradio(payload: &from_radio::PayloadVariant) {
     match payload {
-        from_radio::PayloadVariant::Config(config) => {
-            tracing::trace!("Received config packet: {config:?}");
-        }
         from_radio::PayloadVariant::LogRecord(log_record) => {
             tracing::trace!("Received log_record packet: {log_record:?}");
         }
-        from_radio::PayloadVariant::ConfigCompleteId(id) => {
-            tracing::trace!("Received config {id} complete packet over serial");
-        }
         from_radio::PayloadVariant::Rebooted(rbt) => {
             tracing::trace!("Received rebooted packet: {rbt}");
         }
-        from_radio::PayloadVariant::ModuleConfig(module_config) => {
-            tracing::trace!("Received module_config packet: {module_config:?}");
-        }
-        from_radio::PayloadVariant::Channel(channel) => {
-            tracing::trace!("Received channel packet: {channel:?}");
-        }
         from_radio::PayloadVariant::QueueStatus(queue_status) => {
             tracing::trace!("Received queue_status packet: {queue_status:?}");
         }
         from_radio::PayloadVariant::XmodemPacket(xmodem) => {
             tracing::trace!("Received xmodem packet: {xmodem:?}");
         }
-        from_radio::PayloadVariant::Metadata(device_metadata) => {
-            tracing::trace!("Received device_metadata packet: {device_metadata:?}");
-        }
         from_radio::PayloadVariant::MqttClientProxyMessage(mqtt_client_proxy_message) => {
             tracing::trace!(
                 "Received mqtt_client_proxy_message packet: {mqtt_client_proxy_message:?}"
@@ -126,14 +174,43 @@ fn decode_and_trace<P: Debug>(ptype: &str, payload: P) {
     tracing::trace!("Received {ptype} packet: {payload:?}");
 }
 
+/// Returns whether a `MeshPacket` is new, remembering it as handled
+///
+/// Copies of packets already handled, e.g. replayed by a store-and-forward router, are dropped.
+pub(crate) fn is_new(pkt: &MeshPacket, state: &GatewayState) -> bool {
+    let new = state.first_sighting(pkt.from, pkt.id);
+    if !new {
+        tracing::debug!(
+            node_id = pkt.from,
+            msg_id = pkt.id,
+            "dropped copy of a handled packet"
+        );
+    }
+    new
+}
+
 /// Decodes a `MeshPacket` payload and inserts the result into the database.
-async fn decode_payload(pkt: &MeshPacket, state: &GatewayState, pool: &Pool<Postgres>) {
-    // Count received packets in debug builds for period reporting in logs
-    #[cfg(feature = "debug")]
-    {
-        if !state.increment_count(pkt.from) {
-            tracing::debug!("rx count missed for unregistered node {:08x}", pkt.from);
-        }
+pub(crate) async fn decode_payload(
+    pkt: &MeshPacket,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    if is_new(pkt, state) {
+        decode_new_payload(pkt, state, pool, sinks).await;
+    }
+}
+
+/// Decodes the payload of a `MeshPacket` that [`is_new`], inserting the result into the database.
+pub(crate) async fn decode_new_payload(
+    pkt: &MeshPacket,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    // Count received packets for periodic reporting in logs and node liveness
+    if !state.increment_count(pkt.from) {
+        tracing::debug!("rx count missed for unregistered node {:08x}", pkt.from);
     }
     // Check if the packet is on the telemetry channel before decoding a payload
     if pkt.channel != 0 {
@@ -150,69 +227,379 @@ async fn decode_payload(pkt: &MeshPacket, state: &GatewayState, pool: &Pool<Post
 
     match data.portnum() {
         // We care about these four payload types for sure!
-        PortNum::PositionApp => match Position::decode(data.payload.as_ref()) {
-            Ok(pos) => match devicemetrics::insert_pos(pkt, &pos, pool).await {
-                Ok(_) => tracing::info!(table = "DeviceMetrics", "inserted 1 row of position data"),
-                Err(e) => {
-                    tracing::error!(%e, table = "DeviceMetrics", "inserting position data failed");
-                }
-            },
-            Err(e) => {
-                tracing::warn!(%e, node_id = pkt.from, portnum = ?PortNum::PositionApp, "decode failed");
-            }
-        },
+        PortNum::PositionApp => decode_position(pkt, data, state, pool, sinks).await,
         PortNum::NodeinfoApp => match NodeInfo::decode(data.payload.as_ref()) {
             Ok(ni) => {
-                let (dm_result, ni_result) = tokio::join!(
-                    devicemetrics::upsert_mp(pkt, &ni, pool),
-                    nodeinfo::upsert(&ni, pool),
-                );
-
-                match dm_result {
-                    Ok(_) => tracing::info!(table = "DeviceMetrics", "upserted 1 row"),
-                    Err(e) => tracing::error!(%e, table = "DeviceMetrics", "upsert failed"),
-                }
+                sinks.publish_packet(pkt, PortNum::NodeinfoApp, Payload::NodeInfo(&ni));
+                if let Some(pool) = pool {
+                    let (dm_result, ni_result) = tokio::join!(
+                        devicemetrics::upsert_mp(pkt, &ni, pool),
+                        nodeinfo::upsert(&ni, Source::Mesh, pool),
+                    );
 
-                match ni_result {
-                    Ok(_) => tracing::info!(table = "NodeInfo", "upserted 1 row"),
-                    Err(e) => tracing::error!(%e, table = "NodeInfo", "upsert failed"),
-                }
+                    match dm_result {
+                        Ok(_) => tracing::info!(table = "DeviceMetrics", "upserted 1 row"),
+                        Err(e) => {
+                            state.db_error();
+                            tracing::error!(%e, table = "DeviceMetrics", "upsert failed");
+                        }
+                    }
 
-                // insert into GatewayState
-                #[cfg(feature = "debug")]
-                if let Some(user) = &ni.user {
-                    match state.insert(ni.num, user) {
-                        Ok(()) => tracing::trace!("Added {} to GatewayState", ni.num),
-                        Err(e) => tracing::warn!(%e),
+                    match ni_result {
+                        Ok(r) => {
+                            tracing::info!(
+                                table = "NodeInfo",
+                                history_rows = r.rows_affected(),
+                                "upserted 1 row"
+                            );
+                        }
+                        Err(e) => {
+                            state.db_error();
+                            tracing::error!(%e, table = "NodeInfo", "upsert failed");
+                        }
                     }
                 }
+
+                track_node(&ni, state);
             }
             Err(e) => {
                 tracing::error!(%e, node_id = pkt.from, portnum = ?PortNum::NodeinfoApp, "decode failed");
             }
         },
         PortNum::TelemetryApp => match Telemetry::decode(data.payload.as_ref()) {
-            Ok(telemetry) => decode_telemetry(pkt, &telemetry, pool).await,
+            Ok(telemetry) => {
+                sinks.publish_packet(pkt, PortNum::TelemetryApp, Payload::Telemetry(&telemetry));
+                decode_telemetry(pkt, &telemetry, &data.payload, state, pool, sinks).await;
+            }
             Err(e) => {
                 tracing::warn!(%e, node_id = pkt.from, portnum = ?PortNum::TelemetryApp, "decode failed");
             }
         },
-        PortNum::NeighborinfoApp => match NeighborInfo::decode(data.payload.as_ref()) {
-            Ok(ni) => match neighborinfo::insert(pkt, &ni, pool).await {
-                Ok(_) => tracing::info!(table = "NeighborInfo", "inserted 1 row"),
-                Err(e) => tracing::error!(%e, table = "NeighborInfo", "insert failed"),
-            },
+        PortNum::NeighborinfoApp => decode_neighbor_info(pkt, data, state, pool, sinks).await,
+        PortNum::TracerouteApp => decode_traceroute(pkt, data, state, pool, sinks).await,
+        PortNum::PaxcounterApp => decode_paxcount(pkt, data, state, pool, sinks).await,
+        PortNum::RangeTestApp => decode_range_test(pkt, data, state, pool, sinks).await,
+        PortNum::WaypointApp => decode_waypoint(pkt, data, state, pool, sinks).await,
+        PortNum::RoutingApp => decode_routing(pkt, data, state, pool, sinks).await,
+        PortNum::StoreForwardApp => decode_store_forward(pkt, data, state, pool, sinks).await,
+        PortNum::MapReportApp => decode_map_report(pkt, data, state, pool, sinks).await,
+        PortNum::DetectionSensorApp | PortNum::AlertApp => {
+            record_sensor_event(pkt, data.portnum(), data, state, pool, sinks).await;
+        }
+        other => archive_raw(pkt, other, data, state, pool, sinks).await,
+    }
+}
+
+/// Stores a position a node reports with its device metrics
+async fn decode_position(
+    pkt: &MeshPacket,
+    data: &Data,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    let pos = match Position::decode(data.payload.as_ref()) {
+        Ok(pos) => pos,
+        Err(e) => {
+            tracing::warn!(%e, node_id = pkt.from, portnum = ?PortNum::PositionApp, "decode failed");
+            return;
+        }
+    };
+    sinks.publish_packet(pkt, PortNum::PositionApp, Payload::Position(&pos));
+    let Some(pool) = pool else {
+        return;
+    };
+    match devicemetrics::insert_pos(pkt, &pos, pool).await {
+        Ok(_) => {
+            tracing::info!(table = "DeviceMetrics", "inserted 1 row of position data");
+        }
+        Err(e) => {
+            state.db_error();
+            tracing::error!(%e, table = "DeviceMetrics", "inserting position data failed");
+        }
+    }
+}
+
+/// Stores the visitor counts of a Paxcounter node
+async fn decode_paxcount(
+    pkt: &MeshPacket,
+    data: &Data,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    let pax = match Paxcount::decode(data.payload.as_ref()) {
+        Ok(pax) => pax,
+        Err(e) => {
+            tracing::warn!(%e, node_id = pkt.from, portnum = ?PortNum::PaxcounterApp, "decode failed");
+            return;
+        }
+    };
+    sinks.publish_packet(pkt, PortNum::PaxcounterApp, Payload::Paxcount(&pax));
+    let Some(pool) = pool else {
+        return;
+    };
+    match paxcount::insert(pkt, &pax, pool).await {
+        Ok(_) => tracing::info!(table = "Paxcount", node_id = pkt.from, "inserted 1 row"),
+        Err(e) => {
+            state.db_error();
+            tracing::error!(%e, table = "Paxcount", node_id = pkt.from, "insert failed");
+        }
+    }
+}
+
+/// Records a range-test message in range-test mode, otherwise it is archived like other ports
+async fn decode_range_test(
+    pkt: &MeshPacket,
+    data: &Data,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    let Some(cfg) = RANGE_TEST.get() else {
+        archive_raw(pkt, PortNum::RangeTestApp, data, state, pool, sinks).await;
+        return;
+    };
+    sinks.publish_packet(pkt, PortNum::RangeTestApp, Payload::Raw(&data.payload));
+    let Some(pool) = pool else {
+        return;
+    };
+    let text = String::from_utf8_lossy(&data.payload);
+    match rangetest::record(pkt, &text, cfg, state, pool).await {
+        Ok(_) => tracing::info!(table = "RangeTest", node_id = pkt.from, "inserted 1 row"),
+        Err(e) => {
+            state.db_error();
+            tracing::error!(%e, table = "RangeTest", node_id = pkt.from, "insert failed");
+        }
+    }
+}
+
+/// Upserts a waypoint of the deployment, or deletes it if it expired
+async fn decode_waypoint(
+    pkt: &MeshPacket,
+    data: &Data,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    let wp = match Waypoint::decode(data.payload.as_ref()) {
+        Ok(wp) => wp,
+        Err(e) => {
+            tracing::warn!(%e, node_id = pkt.from, portnum = ?PortNum::WaypointApp, "decode failed");
+            return;
+        }
+    };
+    sinks.publish_packet(pkt, PortNum::WaypointApp, Payload::Waypoint(&wp));
+    let Some(pool) = pool else {
+        return;
+    };
+    if waypoints::is_deletion(&wp, Utc::now().timestamp()) {
+        match waypoints::delete(pkt, &wp, pool).await {
+            Ok(r) => tracing::info!(
+                table = "Waypoints",
+                waypoint = wp.id,
+                rows = r.rows_affected(),
+                "deleted"
+            ),
             Err(e) => {
-                tracing::warn!(%e, node_id = pkt.from, portnum = ?PortNum::NeighborinfoApp, "decode failed");
+                state.db_error();
+                tracing::error!(%e, table = "Waypoints", waypoint = wp.id, "delete failed");
             }
-        },
-        _other => {
-            #[cfg(feature = "trace")]
-            #[expect(
-                clippy::used_underscore_binding,
-                reason = "conditionally compiled variable"
-            )]
-            trace_portnum(_other, data);
+        }
+    } else {
+        match waypoints::upsert(pkt, &wp, pool).await {
+            Ok(r) => tracing::info!(
+                table = "Waypoints",
+                waypoint = wp.id,
+                rows = r.rows_affected(),
+                "upserted"
+            ),
+            Err(e) => {
+                state.db_error();
+                tracing::error!(%e, table = "Waypoints", waypoint = wp.id, "upsert failed");
+            }
+        }
+    }
+}
+
+/// Records ACKs and NAKs, counting them per node pair and error reason
+async fn decode_routing(
+    pkt: &MeshPacket,
+    data: &Data,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    let r = match Routing::decode(data.payload.as_ref()) {
+        Ok(r) => r,
+        Err(e) => {
+            tracing::warn!(%e, node_id = pkt.from, portnum = ?PortNum::RoutingApp, "decode failed");
+            return;
+        }
+    };
+    sinks.publish_packet(pkt, PortNum::RoutingApp, Payload::Routing(&r));
+    let Some(RoutingVariant::ErrorReason(code)) = r.variant else {
+        tracing::debug!(node_id = pkt.from, "route discovery message not stored");
+        return;
+    };
+    let Some(pool) = pool else {
+        return;
+    };
+    let reason = routing::reason_name(code);
+    match routing::insert(pkt, data, &reason, pool).await {
+        // Already recorded, e.g. heard again through MQTT, so not counted twice
+        Ok(res) if res.rows_affected() == 0 => return,
+        Ok(_) => tracing::info!(table = "Routing", node_id = pkt.from, "inserted 1 row"),
+        Err(e) => {
+            state.db_error();
+            tracing::error!(%e, table = "Routing", node_id = pkt.from, "insert failed");
+            return;
+        }
+    }
+    if let Err(e) = routing::count(pkt, &reason, pool).await {
+        state.db_error();
+        tracing::error!(%e, table = "RoutingPairs", node_id = pkt.from, "upsert failed");
+    }
+}
+
+/// Records store-and-forward routers from their heartbeats, history replays and statistics
+///
+/// Replayed text messages keep their original sender and id, they are archived like text
+/// messages received live.
+async fn decode_store_forward(
+    pkt: &MeshPacket,
+    data: &Data,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    let sf = match StoreAndForward::decode(data.payload.as_ref()) {
+        Ok(sf) => sf,
+        Err(e) => {
+            tracing::warn!(%e, node_id = pkt.from, portnum = ?PortNum::StoreForwardApp, "decode failed");
+            return;
+        }
+    };
+    sinks.publish_packet(pkt, PortNum::StoreForwardApp, Payload::StoreAndForward(&sf));
+    let Some(pool) = pool else {
+        return;
+    };
+    let (table, result) = match &sf.variant {
+        Some(SfVariant::Heartbeat(hb)) => (
+            "StoreForwardRouters",
+            storeforward::heartbeat(pkt, hb, pool).await,
+        ),
+        Some(SfVariant::History(h)) => {
+            tracing::info!(
+                node_id = pkt.from,
+                messages = h.history_messages,
+                window_min = h.window,
+                "store-and-forward router replaying history"
+            );
+            (
+                "StoreForwardRouters",
+                storeforward::history(pkt, h, pool).await,
+            )
+        }
+        Some(SfVariant::Stats(st)) => (
+            "StoreForwardStats",
+            storeforward::insert_stats(pkt, st, pool).await,
+        ),
+        Some(SfVariant::Text(text)) => {
+            let replayed = Data {
+                portnum: PortNum::TextMessageApp.into(),
+                payload: text.clone(),
+                ..data.clone()
+            };
+            ("RawPackets", rawpackets::insert(pkt, &replayed, pool).await)
+        }
+        None => return,
+    };
+    match result {
+        Ok(_) => tracing::info!(table, node_id = pkt.from, "stored 1 row"),
+        Err(e) => {
+            state.db_error();
+            tracing::error!(%e, table, node_id = pkt.from, "store failed");
+        }
+    }
+}
+
+/// Keeps the latest firmware and radio configuration a node reports in its `MapReport`
+async fn decode_map_report(
+    pkt: &MeshPacket,
+    data: &Data,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    let report = match MapReport::decode(data.payload.as_ref()) {
+        Ok(report) => report,
+        Err(e) => {
+            tracing::warn!(%e, node_id = pkt.from, portnum = ?PortNum::MapReportApp, "decode failed");
+            return;
+        }
+    };
+    sinks.publish_packet(pkt, PortNum::MapReportApp, Payload::MapReport(&report));
+    let Some(pool) = pool else {
+        return;
+    };
+    match nodeinventory::upsert(pkt, &report, pool).await {
+        Ok(_) => tracing::info!(
+            table = "NodeInventory",
+            node_id = pkt.from,
+            "upserted 1 row"
+        ),
+        Err(e) => {
+            state.db_error();
+            tracing::error!(%e, table = "NodeInventory", node_id = pkt.from, "upsert failed");
+        }
+    }
+}
+
+/// Stores a detection sensor or alert message as an event, raising it if configured
+async fn record_sensor_event(
+    pkt: &MeshPacket,
+    port: PortNum,
+    data: &Data,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    sinks.publish_packet(pkt, port, Payload::Raw(&data.payload));
+    let text = String::from_utf8_lossy(&data.payload);
+    sinks.sensor_triggered(pkt.from, port, &text);
+    let Some(pool) = pool else {
+        return;
+    };
+    match sensorevents::insert(pkt, port, &text, pool).await {
+        Ok(_) => tracing::info!(table = "SensorEvents", node_id = pkt.from, "inserted 1 row"),
+        Err(e) => {
+            state.db_error();
+            tracing::error!(%e, table = "SensorEvents", node_id = pkt.from, "insert failed");
+        }
+    }
+}
+
+/// Keeps the raw payload of a port without a table of its own in `RawPackets`
+async fn archive_raw(
+    pkt: &MeshPacket,
+    port: PortNum,
+    data: &Data,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    sinks.publish_packet(pkt, port, Payload::Raw(&data.payload));
+    #[cfg(feature = "trace")]
+    trace_portnum(port, data);
+    let Some(pool) = pool else {
+        return;
+    };
+    match rawpackets::insert(pkt, data, pool).await {
+        Ok(_) => tracing::debug!(table = "RawPackets", portnum = ?port, "inserted 1 row"),
+        Err(e) => {
+            state.db_error();
+            tracing::error!(%e, table = "RawPackets", portnum = ?port, "insert failed");
         }
     }
 }
@@ -247,12 +634,6 @@ fn trace_portnum(port: PortNum, data: &Data) {
                 tracing::warn!(%e, portnum = ?port, "decode failed");
             }
         },
-        PortNum::RoutingApp => match Routing::decode(data.payload.as_ref()) {
-            Ok(payload) => decode_and_trace("RoutingApp", payload),
-            Err(e) => {
-                tracing::warn!(%e, portnum = ?port, "decode failed");
-            }
-        },
         PortNum::AdminApp => match AdminMessage::decode(data.payload.as_ref()) {
             Ok(payload) => decode_and_trace("AdminApp", payload),
             Err(e) => {
@@ -265,27 +646,9 @@ fn trace_portnum(port: PortNum, data: &Data) {
                 tracing::warn!(%e, portnum = ?port, "decode failed");
             }
         },
-        PortNum::WaypointApp => match Waypoint::decode(data.payload.as_ref()) {
-            Ok(payload) => decode_and_trace("WaypointApp", payload),
-            Err(e) => {
-                tracing::warn!(%e, portnum = ?port, "decode failed");
-            }
-        },
         PortNum::AudioApp => {
             decode_and_trace("AudioApp", data.payload.as_ref());
         }
-        PortNum::DetectionSensorApp => match String::decode(data.payload.as_ref()) {
-            Ok(payload) => decode_and_trace("DetectionSensorApp", payload),
-            Err(e) => {
-                tracing::warn!(%e, portnum = ?port, "decode failed");
-            }
-        },
-        PortNum::AlertApp => match String::decode(data.payload.as_ref()) {
-            Ok(payload) => decode_and_trace("AlertApp", payload),
-            Err(e) => {
-                tracing::warn!(%e, portnum = ?port, "decode failed");
-            }
-        },
         PortNum::ReplyApp => match String::decode(data.payload.as_ref()) {
             Ok(payload) => decode_and_trace("ReplyApp", payload),
             Err(e) => {
@@ -295,21 +658,9 @@ fn trace_portnum(port: PortNum, data: &Data) {
         PortNum::IpTunnelApp => {
             decode_and_trace("IpTunnelApp", data.payload.as_ref());
         }
-        PortNum::PaxcounterApp => match Paxcount::decode(data.payload.as_ref()) {
-            Ok(payload) => decode_and_trace("PaxcounterApp", payload),
-            Err(e) => {
-                tracing::warn!(%e, portnum = ?port, "decode failed");
-            }
-        },
         PortNum::SerialApp => {
             decode_and_trace("SerialApp", data.payload.as_ref());
         }
-        PortNum::StoreForwardApp => match StoreAndForward::decode(data.payload.as_ref()) {
-            Ok(payload) => decode_and_trace("StoreForwardApp", payload),
-            Err(e) => {
-                tracing::warn!(%e, portnum = ?port, "decode failed");
-            }
-        },
         PortNum::RangeTestApp => match String::decode(data.payload.as_ref()) {
             Ok(payload) => decode_and_trace("RangeTestApp", payload),
             Err(e) => {
@@ -322,24 +673,12 @@ fn trace_portnum(port: PortNum, data: &Data) {
         PortNum::SimulatorApp => {
             decode_and_trace("SimulatorApp", data.payload.as_ref());
         }
-        PortNum::TracerouteApp => match RouteDiscovery::decode(data.payload.as_ref()) {
-            Ok(payload) => decode_and_trace("TracerouteApp", payload),
-            Err(e) => {
-                tracing::warn!(%e, portnum = ?port, "decode failed");
-            }
-        },
         PortNum::AtakPlugin => match TakPacket::decode(data.payload.as_ref()) {
             Ok(payload) => decode_and_trace("AtakPlugin", payload),
             Err(e) => {
                 tracing::warn!(%e, portnum = ?port, "decode failed");
             }
         },
-        PortNum::MapReportApp => match MapReport::decode(data.payload.as_ref()) {
-            Ok(payload) => decode_and_trace("MapReportApp", payload),
-            Err(e) => {
-                tracing::warn!(%e, portnum = ?port, "decode failed");
-            }
-        },
         PortNum::PowerstressApp => match PowerStressMessage::decode(data.payload.as_ref()) {
             Ok(payload) => decode_and_trace("PowerstressApp", payload),
             Err(e) => {
@@ -362,99 +701,278 @@ fn trace_portnum(port: PortNum, data: &Data) {
     }
 }
 
-/// Dispatches a telemetry variant to the matching database insert.
-async fn decode_telemetry(pkt: &MeshPacket, tm: &Telemetry, pool: &Pool<Postgres>) {
+/// Stores the neighbors a node reports with their SNR
+async fn decode_neighbor_info(
+    pkt: &MeshPacket,
+    data: &Data,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    match NeighborInfo::decode(data.payload.as_ref()) {
+        Ok(ni) => {
+            sinks.publish_packet(pkt, PortNum::NeighborinfoApp, Payload::NeighborInfo(&ni));
+            let time = timestamp(pkt.rx_time).and_utc().timestamp();
+            let links = ni
+                .neighbors
+                .iter()
+                .map(|n| Link {
+                    reporter: pkt.from,
+                    neighbor: n.node_id,
+                    snr: n.snr,
+                    time,
+                })
+                .collect();
+            state.neighbor_report(pkt.from, time, links);
+            let Some(pool) = pool else {
+                return;
+            };
+            let (info_result, links_result) = tokio::join!(
+                neighborinfo::insert(pkt, &ni, pool),
+                neighborinfo::insert_links(pkt, &ni, pool),
+            );
+            match info_result {
+                Ok(_) => tracing::info!(table = "NeighborInfo", "inserted 1 row"),
+                Err(e) => {
+                    state.db_error();
+                    tracing::error!(%e, table = "NeighborInfo", "insert failed");
+                }
+            }
+            match links_result {
+                Ok(r) => tracing::info!(
+                    table = "NeighborLinks",
+                    rows = r.rows_affected(),
+                    "inserted"
+                ),
+                Err(e) => {
+                    state.db_error();
+                    tracing::error!(%e, table = "NeighborLinks", "insert failed");
+                }
+            }
+        }
+        Err(e) => {
+            tracing::warn!(%e, node_id = pkt.from, portnum = ?PortNum::NeighborinfoApp, "decode failed");
+        }
+    }
+}
+
+/// Stores the reply to a traceroute the gateway sent, with its round-trip time
+async fn decode_traceroute(
+    pkt: &MeshPacket,
+    data: &Data,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    let rd = match RouteDiscovery::decode(data.payload.as_ref()) {
+        Ok(rd) => rd,
+        Err(e) => {
+            tracing::warn!(%e, node_id = pkt.from, portnum = ?PortNum::TracerouteApp, "decode failed");
+            return;
+        }
+    };
+    sinks.publish_packet(pkt, PortNum::TracerouteApp, Payload::RouteDiscovery(&rd));
+    #[cfg(feature = "trace")]
+    decode_and_trace("TracerouteApp", &rd);
+    // Traceroutes of other nodes are only passed to the sinks
+    let (Some(probe), Some(pool)) = (state.probe_answered(data.request_id, pkt.from), pool) else {
+        return;
+    };
+    match traceroutes::insert(pkt, data.request_id, probe, &rd, pool).await {
+        Ok(_) => tracing::info!(table = "Traceroutes", node_id = pkt.from, "inserted 1 row"),
+        Err(e) => {
+            state.db_error();
+            tracing::error!(%e, table = "Traceroutes", node_id = pkt.from, "insert failed");
+        }
+    }
+}
+
+/// Dispatches a telemetry variant to the sinks and the matching database insert.
+///
+/// Variants the protobufs do not know decode to no variant at all, their `raw` payload is kept
+/// in the `TelemetryFallback` table.
+async fn decode_telemetry(
+    pkt: &MeshPacket,
+    tm: &Telemetry,
+    raw: &[u8],
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
     if let Some(data) = tm.variant {
-        match data {
-            Variant::DeviceMetrics(device_metrics) => {
-                #[cfg(feature = "trace")]
-                decode_and_trace("Telemetry/DeviceMetrics", device_metrics);
-                match devicemetrics::insert_dm(pkt, tm, &device_metrics, pool).await {
-                    Ok(_) => {
-                        tracing::info!(table = "DeviceMetrics", node_id = pkt.from, "insert 1 row");
-                    }
-                    Err(e) => {
-                        tracing::error!(%e, table = "DeviceMetrics", node_id = pkt.from, "insert failed");
-                    }
+        fan_out(pkt, tm, &data, state, pool, sinks).await;
+        if let Some(pool) = pool {
+            store_telemetry(pkt, tm, data, state, pool).await;
+        }
+    } else if let Some(pool) = pool {
+        store_unknown_telemetry(pkt, tm, raw, state, pool).await;
+    }
+}
+
+/// Inserts a telemetry variant into its table.
+#[expect(
+    clippy::too_many_lines,
+    reason = "one insert and its logging per telemetry variant"
+)]
+async fn store_telemetry(
+    pkt: &MeshPacket,
+    tm: &Telemetry,
+    data: Variant,
+    state: &GatewayState,
+    pool: &Pool<Postgres>,
+) {
+    match data {
+        Variant::DeviceMetrics(device_metrics) => {
+            #[cfg(feature = "trace")]
+            decode_and_trace("Telemetry/DeviceMetrics", device_metrics);
+            match devicemetrics::insert_dm(pkt, tm, &device_metrics, pool).await {
+                Ok(_) => {
+                    tracing::info!(table = "DeviceMetrics", node_id = pkt.from, "insert 1 row");
+                }
+                Err(e) => {
+                    state.db_error();
+                    tracing::error!(%e, table = "DeviceMetrics", node_id = pkt.from, "insert failed");
                 }
             }
-            Variant::EnvironmentMetrics(environment_metrics) => {
-                #[cfg(feature = "trace")]
-                decode_and_trace("Telemetry/EnvironmentMetrics", environment_metrics);
-                match environmentmetrics::insert(pkt, tm, &environment_metrics, pool).await {
-                    Ok(_) => tracing::info!(
-                        table = "EnvironmentMetrics",
-                        node_id = pkt.from,
-                        "inserted 1 row"
-                    ),
-                    Err(e) => {
-                        tracing::error!(%e, table = "EnvironmentMetrics", node_id = pkt.from, "insert failed");
-                    }
+        }
+        Variant::EnvironmentMetrics(environment_metrics) => {
+            #[cfg(feature = "trace")]
+            decode_and_trace("Telemetry/EnvironmentMetrics", environment_metrics);
+            match environmentmetrics::insert(pkt, tm, &environment_metrics, pool).await {
+                Ok(_) => tracing::info!(
+                    table = "EnvironmentMetrics",
+                    node_id = pkt.from,
+                    "inserted 1 row"
+                ),
+                Err(e) => {
+                    state.db_error();
+                    tracing::error!(%e, table = "EnvironmentMetrics", node_id = pkt.from, "insert failed");
                 }
             }
-            Variant::AirQualityMetrics(air_quality_metrics) => {
-                #[cfg(feature = "trace")]
-                decode_and_trace("Telemetry/AirQualityMetrics", air_quality_metrics);
-                match airqualitymetrics::insert(pkt, tm, &air_quality_metrics, pool).await {
-                    Ok(_) => tracing::info!(
-                        table = "AirQualityMetrics",
-                        node_id = pkt.from,
-                        "inserted 1 row"
-                    ),
-                    Err(e) => {
-                        tracing::error!(%e, table = "AirQualityMetrics", node_id = pkt.from, "insert failed");
-                    }
+        }
+        Variant::AirQualityMetrics(air_quality_metrics) => {
+            #[cfg(feature = "trace")]
+            decode_and_trace("Telemetry/AirQualityMetrics", air_quality_metrics);
+            match airqualitymetrics::insert(pkt, tm, &air_quality_metrics, pool).await {
+                Ok(_) => tracing::info!(
+                    table = "AirQualityMetrics",
+                    node_id = pkt.from,
+                    "inserted 1 row"
+                ),
+                Err(e) => {
+                    state.db_error();
+                    tracing::error!(%e, table = "AirQualityMetrics", node_id = pkt.from, "insert failed");
                 }
             }
-            Variant::LocalStats(local_stats) => {
-                #[cfg(feature = "trace")]
-                decode_and_trace("Telemetry/LocalStats", local_stats);
-                match localstats::insert(pkt, tm, &local_stats, pool).await {
-                    Ok(_) => {
-                        tracing::info!(table = "LocalStats", node_id = pkt.from, "inserted 1 row");
-                    }
-                    Err(e) => {
-                        tracing::error!(%e, table = "LocalStats", node_id = pkt.from, "insert failed");
-                    }
+        }
+        Variant::LocalStats(local_stats) => {
+            #[cfg(feature = "trace")]
+            decode_and_trace("Telemetry/LocalStats", local_stats);
+            match localstats::insert(pkt, tm, &local_stats, pool).await {
+                Ok(_) => {
+                    tracing::info!(table = "LocalStats", node_id = pkt.from, "inserted 1 row");
+                }
+                Err(e) => {
+                    state.db_error();
+                    tracing::error!(%e, table = "LocalStats", node_id = pkt.from, "insert failed");
                 }
             }
-            Variant::ErrorMetrics(error_metrics) => {
-                #[cfg(feature = "trace")]
-                decode_and_trace("Telemetry/ErrorMetrics", error_metrics);
-                match errormetrics::insert(pkt, tm, &error_metrics, pool).await {
-                    Ok(_) => {
-                        tracing::info!(
-                            table = "ErrorMetrics",
-                            node_id = pkt.from,
-                            "inserted 1 row"
-                        );
-                    }
-                    Err(e) => {
-                        tracing::error!(%e, table = "ErrorMetrics", node_id = pkt.from, "insert failed");
-                    }
+        }
+        Variant::ErrorMetrics(error_metrics) => {
+            #[cfg(feature = "trace")]
+            decode_and_trace("Telemetry/ErrorMetrics", error_metrics);
+            match errormetrics::insert(pkt, tm, &error_metrics, pool).await {
+                Ok(_) => {
+                    tracing::info!(table = "ErrorMetrics", node_id = pkt.from, "inserted 1 row");
+                }
+                Err(e) => {
+                    state.db_error();
+                    tracing::error!(%e, table = "ErrorMetrics", node_id = pkt.from, "insert failed");
                 }
             }
-            Variant::PowerMetrics(power_metrics) => {
-                #[cfg(feature = "trace")]
-                decode_and_trace("Telemetry/PowerMetrics", power_metrics);
-                match powermetrics::insert(pkt, tm, &power_metrics, pool).await {
-                    Ok(_) => {
-                        tracing::info!(
-                            table = "PowerMetrics",
-                            node_id = pkt.from,
-                            "inserted 1 row"
-                        );
-                    }
-                    Err(e) => {
-                        tracing::error!(%e, table = "PowerMetrics", node_id = pkt.from, "insert failed");
-                    }
+        }
+        Variant::PowerMetrics(power_metrics) => {
+            #[cfg(feature = "trace")]
+            decode_and_trace("Telemetry/PowerMetrics", power_metrics);
+            match powermetrics::insert(pkt, tm, &power_metrics, pool).await {
+                Ok(_) => {
+                    tracing::info!(table = "PowerMetrics", node_id = pkt.from, "inserted 1 row");
+                }
+                Err(e) => {
+                    state.db_error();
+                    tracing::error!(%e, table = "PowerMetrics", node_id = pkt.from, "insert failed");
                 }
             }
-            #[cfg(not(feature = "trace"))]
-            _ => {}
+        }
+        Variant::HealthMetrics(health_metrics) => {
             #[cfg(feature = "trace")]
-            Variant::HealthMetrics(health_metrics) => {
-                decode_and_trace("Telemetry/HealthMetrics", health_metrics);
+            decode_and_trace("Telemetry/HealthMetrics", health_metrics);
+            match healthmetrics::insert(pkt, tm, &health_metrics, pool).await {
+                Ok(_) => {
+                    tracing::info!(
+                        table = "HealthMetrics",
+                        node_id = pkt.from,
+                        "inserted 1 row"
+                    );
+                }
+                Err(e) => {
+                    state.db_error();
+                    tracing::error!(%e, table = "HealthMetrics", node_id = pkt.from, "insert failed");
+                }
+            }
+        }
+    }
+}
+
+/// Inserts telemetry of a variant the protobufs do not know, as hex, into the fallback table.
+async fn store_unknown_telemetry(
+    pkt: &MeshPacket,
+    tm: &Telemetry,
+    raw: &[u8],
+    state: &GatewayState,
+    pool: &Pool<Postgres>,
+) {
+    tracing::debug!(node_id = pkt.from, "unknown telemetry variant");
+    let data = json!({ "hex": hex(raw) });
+    match telemetryfallback::insert(pkt, tm, None, data, pool).await {
+        Ok(_) => {
+            tracing::info!(
+                table = "TelemetryFallback",
+                node_id = pkt.from,
+                "inserted 1 row"
+            );
+        }
+        Err(e) => {
+            state.db_error();
+            tracing::error!(%e, table = "TelemetryFallback", node_id = pkt.from, "insert failed");
+        }
+    }
+}
+
+/// Hands a telemetry variant to the sinks, raises battery events and evaluates the threshold
+/// rules, storing every rule transition.
+async fn fan_out(
+    pkt: &MeshPacket,
+    tm: &Telemetry,
+    data: &Variant,
+    state: &GatewayState,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Sinks,
+) {
+    sinks.publish_telemetry(pkt, tm, data, state);
+    if let Variant::DeviceMetrics(device_metrics) = data {
+        sinks.check_battery(pkt.from, device_metrics);
+    }
+    let alerts = sinks.evaluate_rules(pkt.from, data);
+    let Some(pool) = pool else {
+        return;
+    };
+    for alert in alerts {
+        match alerts::insert(pkt, tm, &alert, pool).await {
+            Ok(_) => tracing::info!(table = "Alerts", node_id = pkt.from, "inserted 1 row"),
+            Err(e) => {
+                state.db_error();
+                tracing::error!(%e, table = "Alerts", node_id = pkt.from, "insert failed");
             }
         }
     }
@@ -0,0 +1,207 @@
+use crate::util::enum_name;
+use meshtastic::protobufs::{
+    Channel, Config, DeviceMetadata, HardwareModel, ModuleConfig, channel,
+    config::device_config::Role, from_radio::PayloadVariant,
+};
+use serde_json::{Value, json};
+use std::fmt::Debug;
+
+/// Fields whose values are secrets, left out of the stored configuration
+const SECRETS: [&str; 4] = ["psk", "wifi_psk", "private_key", "password"];
+
+/// Configuration the serial node reports while the connection is configured
+#[derive(Debug, Default)]
+pub(crate) struct RadioSnapshot {
+    /// Firmware and hardware of the node
+    metadata: Option<DeviceMetadata>,
+    /// Config sections in their debug form, secrets redacted
+    config: Vec<String>,
+    /// Module config sections in their debug form, secrets redacted
+    module_config: Vec<String>,
+    /// Channels without their keys
+    channels: Vec<Value>,
+}
+
+impl RadioSnapshot {
+    /// Keeps a configuration frame, returning whether it was one
+    pub(crate) fn add(&mut self, frame: &PayloadVariant) -> bool {
+        match frame {
+            PayloadVariant::Metadata(m) => self.metadata = Some(m.clone()),
+            PayloadVariant::Config(c) => self.config.push(redacted::<Config>(c)),
+            PayloadVariant::ModuleConfig(m) => {
+                self.module_config.push(redacted::<ModuleConfig>(m));
+            }
+            PayloadVariant::Channel(c) => self.channels.push(channel_json(c)),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Whether no frame was kept since the last snapshot
+    pub(crate) const fn is_empty(&self) -> bool {
+        self.metadata.is_none()
+            && self.config.is_empty()
+            && self.module_config.is_empty()
+            && self.channels.is_empty()
+    }
+
+    /// Firmware version of the node, if it sent its metadata
+    pub(crate) fn firmware_version(&self) -> Option<&str> {
+        self.metadata.as_ref().map(|m| m.firmware_version.as_str())
+    }
+
+    /// Role of the node, if it sent its metadata
+    pub(crate) fn role(&self) -> Option<String> {
+        self.metadata
+            .as_ref()
+            .map(|m| enum_name(m.role, Role::as_str_name))
+    }
+
+    /// Hardware model of the node, if it sent its metadata
+    pub(crate) fn hw_model(&self) -> Option<i32> {
+        self.metadata.as_ref().map(|m| m.hw_model)
+    }
+
+    /// JSON form stored and compared against the last snapshot
+    ///
+    /// Sections are sorted so the order the firmware sends them in does not count as a change.
+    pub(crate) fn to_json(&self) -> Value {
+        let mut config = self.config.clone();
+        config.sort_unstable();
+        let mut module_config = self.module_config.clone();
+        module_config.sort_unstable();
+        json!({
+            "metadata": self.metadata.as_ref().map(|m| json!({
+                "firmware_version": m.firmware_version,
+                "device_state_version": m.device_state_version,
+                "can_shutdown": m.can_shutdown,
+                "has_wifi": m.has_wifi,
+                "has_bluetooth": m.has_bluetooth,
+                "has_ethernet": m.has_ethernet,
+                "role": enum_name(m.role, Role::as_str_name),
+                "position_flags": m.position_flags,
+                "hw_model": enum_name(m.hw_model, HardwareModel::as_str_name),
+                "has_remote_hardware": m.has_remote_hardware,
+                "has_pkc": m.has_pkc,
+                "excluded_modules": m.excluded_modules,
+            })),
+            "config": config,
+            "module_config": module_config,
+            "channels": self.channels,
+        })
+    }
+}
+
+/// A channel with its settings, the key is only kept as its length
+fn channel_json(c: &Channel) -> Value {
+    let settings = c.settings.as_ref();
+    json!({
+        "index": c.index,
+        "role": enum_name(c.role, channel::Role::as_str_name),
+        "name": settings.map(|s| s.name.as_str()),
+        "channel_num": settings.map(|s| s.channel_num),
+        "psk_len": settings.map(|s| s.psk.len()),
+        "uplink_enabled": settings.map(|s| s.uplink_enabled),
+        "downlink_enabled": settings.map(|s| s.downlink_enabled),
+    })
+}
+
+/// Debug form of a config section with the values of [`SECRETS`] replaced
+fn redacted<M: Debug>(m: &M) -> String {
+    let mut text = format!("{m:?}");
+    for field in SECRETS {
+        text = redact(&text, field);
+    }
+    text
+}
+
+/// Replaces every value of `field` in a debug form, whether a string, a list or a scalar
+fn redact(text: &str, field: &str) -> String {
+    let key = format!("{field}: ");
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find(&key) {
+        let (before, after) = rest.split_at(at);
+        out.push_str(before);
+        out.push_str(&key);
+        let value = &after[key.len()..];
+        // `wifi_psk: ` also contains `psk: `, only whole field names count
+        if before.ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+            rest = value;
+            continue;
+        }
+        out.push_str("\"<redacted>\"");
+        rest = &value[value_len(value)..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Length of the debug value at the start of `text`
+fn value_len(text: &str) -> usize {
+    let mut depth = 0_usize;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' if quoted && depth == 0 => return i + 1,
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            '[' | '{' | '(' => depth += 1,
+            ']' | '}' | ')' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            ',' | '}' | ')' if depth == 0 => return text[..i].trim_end().len(),
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use meshtastic::protobufs::ChannelSettings;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let text = r#"Mqtt(MqttConfig { address: "broker", password: "s3\"cret", enabled: true })"#;
+        assert_eq!(
+            redact(text, "password"),
+            r#"Mqtt(MqttConfig { address: "broker", password: "<redacted>", enabled: true })"#
+        );
+        let text = "Security(SecurityConfig { private_key: [1, 2, 3], is_managed: false })";
+        assert_eq!(
+            redact(text, "private_key"),
+            r#"Security(SecurityConfig { private_key: "<redacted>", is_managed: false })"#
+        );
+        let text = r#"Network(NetworkConfig { wifi_psk: "x" })"#;
+        assert_eq!(redact(text, "psk"), text);
+    }
+
+    #[test]
+    fn channels_keep_only_the_key_length() {
+        let mut snapshot = RadioSnapshot::default();
+        assert!(snapshot.is_empty());
+        let channel = Channel {
+            index: 0,
+            settings: Some(ChannelSettings {
+                name: String::from("LongFast"),
+                psk: vec![1],
+                ..Default::default()
+            }),
+            role: 1,
+        };
+        assert!(snapshot.add(&PayloadVariant::Channel(channel)));
+        assert!(!snapshot.add(&PayloadVariant::Rebooted(true)));
+        let json = snapshot.to_json();
+        assert_eq!(json["channels"][0]["role"], "PRIMARY");
+        assert_eq!(json["channels"][0]["psk_len"], 1);
+        assert!(!json.to_string().contains("psk\":["));
+    }
+}
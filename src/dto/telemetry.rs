@@ -0,0 +1,211 @@
+use anyhow::{Result, bail};
+use meshtastic::protobufs::{
+    AirQualityMetrics, DeviceMetrics, EnvironmentMetrics, ErrorMetrics, HealthMetrics, LocalStats,
+    MeshPacket, PowerMetrics, Telemetry, telemetry::Variant,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Numeric telemetry reading, keeping integers and floats apart for serialization
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(untagged)]
+pub(crate) enum Metric {
+    /// Counters, percentages and other unsigned readings
+    Unsigned(u32),
+    /// Measurements
+    Float(f32),
+}
+
+impl Metric {
+    /// Reading as a float for comparisons
+    pub(crate) fn as_f64(self) -> f64 {
+        match self {
+            Self::Unsigned(n) => f64::from(n),
+            Self::Float(n) => f64::from(n),
+        }
+    }
+}
+
+impl From<u32> for Metric {
+    fn from(n: u32) -> Self {
+        Self::Unsigned(n)
+    }
+}
+
+impl From<f32> for Metric {
+    fn from(n: f32) -> Self {
+        Self::Float(n)
+    }
+}
+
+/// One telemetry packet flattened into its present fields
+#[derive(Debug, Serialize)]
+pub(crate) struct Reading {
+    /// Node number that sent the packet
+    pub(crate) node_id: u32,
+    /// Packet id
+    pub(crate) msg_id: u32,
+    /// Unix time the node took the reading
+    pub(crate) time: u32,
+    /// Telemetry type, see [`kind`]
+    #[serde(rename = "type")]
+    pub(crate) kind: &'static str,
+    /// Fields present in the packet
+    #[serde(flatten)]
+    pub(crate) fields: BTreeMap<&'static str, Metric>,
+}
+
+#[cfg_attr(
+    not(any(feature = "mqtt", feature = "influxdb")),
+    expect(dead_code, reason = "only published by the optional sinks")
+)]
+impl Reading {
+    /// Flattens a telemetry variant received in a `MeshPacket`
+    pub(crate) fn new(pkt: &MeshPacket, tm: &Telemetry, v: &Variant) -> Self {
+        Self {
+            node_id: pkt.from,
+            msg_id: pkt.id,
+            time: tm.time,
+            kind: kind(v),
+            fields: fields(v),
+        }
+    }
+}
+
+/// Name a telemetry variant is addressed by in rules, topics and exports
+pub(crate) const fn kind(v: &Variant) -> &'static str {
+    match v {
+        Variant::DeviceMetrics(_) => "device",
+        Variant::EnvironmentMetrics(_) => "environment",
+        Variant::AirQualityMetrics(_) => "air_quality",
+        Variant::PowerMetrics(_) => "power",
+        Variant::LocalStats(_) => "local_stats",
+        Variant::HealthMetrics(_) => "health",
+        Variant::ErrorMetrics(_) => "error",
+    }
+}
+
+/// Empty telemetry variant of a kind, used to validate field names
+pub(crate) fn empty(kind: &str) -> Option<Variant> {
+    Some(match kind {
+        "device" => Variant::DeviceMetrics(DeviceMetrics::default()),
+        "environment" => Variant::EnvironmentMetrics(EnvironmentMetrics::default()),
+        "air_quality" => Variant::AirQualityMetrics(AirQualityMetrics::default()),
+        "power" => Variant::PowerMetrics(PowerMetrics::default()),
+        "local_stats" => Variant::LocalStats(LocalStats::default()),
+        "health" => Variant::HealthMetrics(HealthMetrics::default()),
+        "error" => Variant::ErrorMetrics(ErrorMetrics::default()),
+        _ => return None,
+    })
+}
+
+/// Reads a numeric field from a telemetry variant, failing on unknown field names
+pub(crate) fn field(v: &Variant, name: &str) -> Result<Option<Metric>> {
+    match all_fields(v).into_iter().find(|(n, _)| *n == name) {
+        Some((_, value)) => Ok(value),
+        None => bail!("unknown {} telemetry field {name}", kind(v)),
+    }
+}
+
+/// Fields present in a telemetry variant, by name
+pub(crate) fn fields(v: &Variant) -> BTreeMap<&'static str, Metric> {
+    all_fields(v)
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+}
+
+/// Every numeric field of a telemetry variant, present or not
+fn all_fields(v: &Variant) -> Vec<(&'static str, Option<Metric>)> {
+    match v {
+        Variant::DeviceMetrics(m) => vec![
+            ("battery_level", m.battery_level.map(Metric::from)),
+            ("voltage", m.voltage.map(Metric::from)),
+            (
+                "channel_utilization",
+                m.channel_utilization.map(Metric::from),
+            ),
+            ("air_util_tx", m.air_util_tx.map(Metric::from)),
+            ("uptime_seconds", m.uptime_seconds.map(Metric::from)),
+        ],
+        Variant::EnvironmentMetrics(m) => vec![
+            ("temperature", m.temperature.map(Metric::from)),
+            ("relative_humidity", m.relative_humidity.map(Metric::from)),
+            (
+                "barometric_pressure",
+                m.barometric_pressure.map(Metric::from),
+            ),
+            ("gas_resistance", m.gas_resistance.map(Metric::from)),
+            ("iaq", m.iaq.map(Metric::from)),
+            ("wind_direction", m.wind_direction.map(Metric::from)),
+            ("wind_speed", m.wind_speed.map(Metric::from)),
+            ("wind_gust", m.wind_gust.map(Metric::from)),
+            ("wind_lull", m.wind_lull.map(Metric::from)),
+            ("rainfall_1h", m.rainfall_1h.map(Metric::from)),
+            ("rainfall_24h", m.rainfall_24h.map(Metric::from)),
+            ("voltage", m.voltage.map(Metric::from)),
+            ("current", m.current.map(Metric::from)),
+        ],
+        Variant::AirQualityMetrics(m) => vec![
+            ("pm10_standard", m.pm10_standard.map(Metric::from)),
+            ("pm25_standard", m.pm25_standard.map(Metric::from)),
+            ("pm100_standard", m.pm100_standard.map(Metric::from)),
+            ("pm10_environmental", m.pm10_environmental.map(Metric::from)),
+            ("pm25_environmental", m.pm25_environmental.map(Metric::from)),
+            (
+                "pm100_environmental",
+                m.pm100_environmental.map(Metric::from),
+            ),
+            ("particles_03um", m.particles_03um.map(Metric::from)),
+            ("particles_05um", m.particles_05um.map(Metric::from)),
+            ("particles_10um", m.particles_10um.map(Metric::from)),
+            ("particles_25um", m.particles_25um.map(Metric::from)),
+            ("particles_50um", m.particles_50um.map(Metric::from)),
+            ("particles_100um", m.particles_100um.map(Metric::from)),
+            ("co2", m.co2.map(Metric::from)),
+        ],
+        Variant::PowerMetrics(m) => vec![
+            ("ch1_voltage", m.ch1_voltage.map(Metric::from)),
+            ("ch1_current", m.ch1_current.map(Metric::from)),
+            ("ch2_voltage", m.ch2_voltage.map(Metric::from)),
+            ("ch2_current", m.ch2_current.map(Metric::from)),
+            ("ch3_voltage", m.ch3_voltage.map(Metric::from)),
+            ("ch3_current", m.ch3_current.map(Metric::from)),
+        ],
+        Variant::LocalStats(m) => vec![
+            ("uptime_seconds", Some(m.uptime_seconds.into())),
+            ("channel_utilization", Some(m.channel_utilization.into())),
+            ("air_util_tx", Some(m.air_util_tx.into())),
+            ("num_packets_tx", Some(m.num_packets_tx.into())),
+            ("num_packets_rx", Some(m.num_packets_rx.into())),
+            ("num_packets_rx_bad", Some(m.num_packets_rx_bad.into())),
+            ("num_online_nodes", Some(m.num_online_nodes.into())),
+            ("num_total_nodes", Some(m.num_total_nodes.into())),
+            ("num_rx_dupe", Some(m.num_rx_dupe.into())),
+            ("num_tx_relay", Some(m.num_tx_relay.into())),
+            (
+                "num_tx_relay_canceled",
+                Some(m.num_tx_relay_canceled.into()),
+            ),
+        ],
+        Variant::HealthMetrics(m) => vec![
+            ("heart_bpm", m.heart_bpm.map(Metric::from)),
+            ("sp_o2", m.sp_o2.map(Metric::from)),
+            ("temperature", m.temperature.map(Metric::from)),
+        ],
+        Variant::ErrorMetrics(m) => vec![
+            ("collision_rate", m.collision_rate.map(Metric::from)),
+            ("node_reach", m.node_reach.map(Metric::from)),
+            ("num_nodes", m.num_nodes.map(Metric::from)),
+            ("usefulness", m.usefulness.map(Metric::from)),
+            ("avg_delay", m.avg_delay.map(Metric::from)),
+            ("period", m.period.map(Metric::from)),
+            ("noroute", m.noroute.map(Metric::from)),
+            ("naks", m.naks.map(Metric::from)),
+            ("timeouts", m.timeouts.map(Metric::from)),
+            ("max_retransmit", m.max_retransmit.map(Metric::from)),
+            ("no_channel", m.no_channel.map(Metric::from)),
+            ("too_large", m.too_large.map(Metric::from)),
+        ],
+    }
+}
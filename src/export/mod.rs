@@ -0,0 +1,193 @@
+use crate::{
+    export::{
+        output::{Format, Output},
+        tables::{Selection, Table},
+    },
+    util::{
+        config::Settings,
+        parse::{list, node_id, time},
+    },
+};
+use anyhow::{Context as _, Result, bail};
+use chrono::{NaiveDateTime, Utc};
+use std::{env, fs, path::PathBuf};
+
+/// CSV and Parquet output files
+pub(crate) mod output;
+/// Exportable tables and their queries
+pub(crate) mod tables;
+
+/// Usage of the subcommand, shown with argument errors
+const USAGE: &str = "usage: meshtastic-telemetry-daemon-rs export --from <time> [--to <time>] \
+[--tables environment,air_quality,paxcount] [--format csv|parquet] [--nodes <id,...>] \
+[--location <deployment>] [--out <dir>]
+times are Unix seconds, RFC 3339 or YYYY-MM-DD in UTC, node ids decimal or !hex";
+
+/// Arguments of the `export` subcommand
+#[derive(Debug)]
+pub(crate) struct Args {
+    /// Tables to export, one file each
+    tables: Vec<Table>,
+    /// Output file format
+    format: Format,
+    /// Start of the time window, inclusive
+    from: NaiveDateTime,
+    /// End of the time window, exclusive
+    to: NaiveDateTime,
+    /// Nodes to export, every node if empty
+    nodes: Vec<u32>,
+    /// Deployment to export, the configured one if unset
+    location: Option<String>,
+    /// Directory the files are written to
+    out: PathBuf,
+}
+
+impl Args {
+    /// Parses the command line if the daemon was started as `export`
+    pub(crate) fn from_env() -> Result<Option<Self>> {
+        let mut argv = env::args().skip(1);
+        if argv.next().as_deref() != Some("export") {
+            return Ok(None);
+        }
+        Self::parse(argv)
+            .map(Some)
+            .with_context(|| format!("Invalid export arguments\n{USAGE}"))
+    }
+
+    /// Parses the arguments following `export`
+    fn parse(mut argv: impl Iterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self {
+            tables: Table::ALL.to_vec(),
+            format: Format::Csv,
+            from: NaiveDateTime::MIN,
+            to: Utc::now().naive_utc(),
+            nodes: Vec::new(),
+            location: None,
+            out: PathBuf::from("."),
+        };
+        let mut from = None;
+        while let Some(flag) = argv.next() {
+            let value = argv
+                .next()
+                .with_context(|| format!("{flag} needs a value"))?;
+            match flag.as_str() {
+                "--tables" => {
+                    parsed.tables = list(Some(&value))
+                        .map(Table::parse)
+                        .collect::<Result<_>>()?;
+                }
+                "--format" => parsed.format = Format::parse(&value)?,
+                "--from" => from = Some(time(&value)?),
+                "--to" => parsed.to = time(&value)?,
+                "--nodes" => {
+                    parsed.nodes = list(Some(&value)).map(node_id).collect::<Result<_>>()?;
+                }
+                "--location" => parsed.location = Some(value),
+                "--out" => parsed.out = PathBuf::from(value),
+                other => bail!("unknown argument {other}"),
+            }
+        }
+        parsed.from = from.context("--from is required")?;
+        if parsed.tables.is_empty() {
+            bail!("--tables names no table");
+        }
+        if parsed.from >= parsed.to {
+            bail!("--from {} is not before --to {}", parsed.from, parsed.to);
+        }
+        Ok(parsed)
+    }
+}
+
+/// Writes one file per table for the selected deployment, nodes and time window
+pub(crate) async fn run(settings: &Settings, args: Args) -> Result<()> {
+    let pool = settings
+        .setup_postgres()
+        .await
+        .context("Failed to connect to postgresql database")?;
+    fs::create_dir_all(&args.out)
+        .with_context(|| format!("Failed to create {}", args.out.display()))?;
+    let selection = Selection {
+        location: args
+            .location
+            .unwrap_or_else(|| settings.deployment.location.clone()),
+        nodes: args.nodes,
+        from: args.from,
+        to: args.to,
+    };
+    let stamp = |t: NaiveDateTime| t.format("%Y%m%dT%H%M%SZ");
+    for table in args.tables {
+        let path = args.out.join(format!(
+            "{}-{}-{}.{}",
+            table.name(),
+            stamp(selection.from),
+            stamp(selection.to),
+            args.format.extension()
+        ));
+        let mut out = Output::create(&path, args.format, table.name(), table.fields())?;
+        let rows = table.export(&selection, &pool, &mut out).await?;
+        out.finish()?;
+        tracing::info!(path = %path.display(), rows, location = selection.location, "exported");
+    }
+    pool.close().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse(args.iter().map(|a| (*a).to_owned()))
+    }
+
+    fn rejected(args: &[&str]) -> bool {
+        parse(args).is_err()
+    }
+
+    #[test]
+    fn defaults_cover_every_table_as_csv() -> Result<()> {
+        let args = parse(&["--from", "2025-10-01"])?;
+        assert_eq!(
+            args.tables,
+            [Table::Environment, Table::AirQuality, Table::Paxcount]
+        );
+        assert_eq!(args.format, Format::Csv);
+        assert!(args.nodes.is_empty());
+        assert!(args.location.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn flags_select_tables_nodes_and_window() -> Result<()> {
+        let args = parse(&[
+            "--tables",
+            "air_quality,paxcount",
+            "--nodes",
+            "!12345678,7",
+            "--from",
+            "2025-10-01",
+            "--to",
+            "2025-10-02",
+            "--location",
+            "Portland",
+            "--out",
+            "dumps",
+        ])?;
+        assert_eq!(args.tables, [Table::AirQuality, Table::Paxcount]);
+        assert_eq!(args.nodes, [0x1234_5678, 7]);
+        assert_eq!(args.to.to_string(), "2025-10-02 00:00:00");
+        assert_eq!(args.location.as_deref(), Some("Portland"));
+        assert_eq!(args.out, PathBuf::from("dumps"));
+        Ok(())
+    }
+
+    #[test]
+    fn bad_arguments_are_rejected() {
+        assert!(rejected(&[]));
+        assert!(rejected(&["--from"]));
+        assert!(rejected(&["--from", "2025-10-02", "--to", "2025-10-01"]));
+        assert!(rejected(&["--from", "2025-10-01", "--tables", "device"]));
+        assert!(rejected(&["--from", "2025-10-01", "--format", "xlsx"]));
+        assert!(rejected(&["--from", "2025-10-01", "--verbose", "1"]));
+    }
+}
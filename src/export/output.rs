@@ -0,0 +1,424 @@
+use anyhow::{Context as _, Result, bail};
+use chrono::{NaiveDateTime, SecondsFormat};
+#[cfg(feature = "parquet")]
+use parquet::{
+    basic::Compression,
+    data_type::{ByteArray, ByteArrayType, FloatType, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+#[cfg(feature = "parquet")]
+use std::sync::Arc;
+use std::{fs::File, path::Path};
+
+/// Output file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// Comma separated values with a header row, times in RFC 3339
+    Csv,
+    /// Snappy compressed Parquet, times as UTC microsecond timestamps
+    Parquet,
+}
+
+impl Format {
+    /// Parses `csv` or `parquet`
+    pub(crate) fn parse(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "parquet" if cfg!(feature = "parquet") => Ok(Self::Parquet),
+            "parquet" => bail!("parquet output needs the daemon built with the `parquet` feature"),
+            other => bail!("unknown format {other}, expected csv or parquet"),
+        }
+    }
+
+    /// File name extension
+    pub(crate) const fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+/// Type of an exported column, the same for every table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    /// UTC time, never empty
+    Time,
+    /// 64 bit integer
+    Int,
+    /// 32 bit float
+    Float,
+    /// UTF-8 text
+    Text,
+}
+
+/// An exported column
+#[derive(Debug)]
+pub(crate) struct Field {
+    /// Column name, suffixed with the unit where there is one
+    pub(crate) name: &'static str,
+    /// Column type
+    pub(crate) kind: Kind,
+}
+
+/// One cell of a row
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    /// A `Kind::Time` cell
+    Time(NaiveDateTime),
+    /// A `Kind::Int` cell
+    Int(Option<i64>),
+    /// A `Kind::Float` cell
+    Float(Option<f32>),
+    /// A `Kind::Text` cell
+    Text(Option<String>),
+}
+
+/// Rows gathered column by column until they are written
+#[derive(Debug)]
+pub(crate) struct Batch {
+    /// Columns of the table
+    fields: &'static [Field],
+    /// Cells by column
+    columns: Vec<Vec<Value>>,
+}
+
+impl Batch {
+    /// Creates an empty batch for a table's columns
+    pub(crate) fn new(fields: &'static [Field]) -> Self {
+        Self {
+            fields,
+            columns: fields.iter().map(|_| Vec::new()).collect(),
+        }
+    }
+
+    /// Number of rows in the batch
+    pub(crate) fn len(&self) -> usize {
+        self.columns.first().map_or(0, Vec::len)
+    }
+
+    /// Adds a row, failing if its cells do not match the columns
+    pub(crate) fn push(&mut self, row: Vec<Value>) -> Result<()> {
+        if row.len() != self.fields.len() {
+            bail!(
+                "row of {} cells for {} columns",
+                row.len(),
+                self.fields.len()
+            );
+        }
+        for ((value, field), column) in row.into_iter().zip(self.fields).zip(&mut self.columns) {
+            let kind = match value {
+                Value::Time(_) => Kind::Time,
+                Value::Int(_) => Kind::Int,
+                Value::Float(_) => Kind::Float,
+                Value::Text(_) => Kind::Text,
+            };
+            if kind != field.kind {
+                bail!("{kind:?} cell in {:?} column {}", field.kind, field.name);
+            }
+            column.push(value);
+        }
+        Ok(())
+    }
+
+    /// Empties the batch for the next rows
+    pub(crate) fn clear(&mut self) {
+        self.columns.iter_mut().for_each(Vec::clear);
+    }
+}
+
+/// An output file being written
+pub(crate) enum Output {
+    /// CSV writer
+    Csv(csv::Writer<File>),
+    /// Parquet writer, each batch becomes a row group
+    #[cfg(feature = "parquet")]
+    Parquet(SerializedFileWriter<File>),
+}
+
+impl Output {
+    /// Creates the file, writing the CSV header or the Parquet schema
+    pub(crate) fn create(
+        path: &Path,
+        format: Format,
+        table: &str,
+        fields: &[Field],
+    ) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        match format {
+            Format::Csv => {
+                let mut writer = csv::Writer::from_writer(file);
+                writer
+                    .write_record(fields.iter().map(|f| f.name))
+                    .context("Failed to write CSV header")?;
+                Ok(Self::Csv(writer))
+            }
+            #[cfg(feature = "parquet")]
+            Format::Parquet => {
+                let schema = parse_message_type(&schema(table, fields))
+                    .context("Failed to build Parquet schema")?;
+                let props = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                Ok(Self::Parquet(
+                    SerializedFileWriter::new(file, Arc::new(schema), Arc::new(props))
+                        .context("Failed to start Parquet file")?,
+                ))
+            }
+            #[cfg(not(feature = "parquet"))]
+            Format::Parquet => {
+                let _ = table;
+                bail!("parquet output needs the daemon built with the `parquet` feature")
+            }
+        }
+    }
+
+    /// Writes the rows of a batch
+    pub(crate) fn write(&mut self, batch: &Batch) -> Result<()> {
+        match self {
+            Self::Csv(writer) => {
+                for i in 0..batch.len() {
+                    writer
+                        .write_record(batch.columns.iter().map(|c| csv_cell(&c[i])))
+                        .context("Failed to write CSV row")?;
+                }
+                Ok(())
+            }
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer) => row_group(writer, batch),
+        }
+    }
+
+    /// Flushes the file, writing the Parquet footer
+    pub(crate) fn finish(self) -> Result<()> {
+        match self {
+            Self::Csv(mut writer) => writer.flush().context("Failed to flush CSV file"),
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer) => writer
+                .close()
+                .map(|_| ())
+                .context("Failed to finish Parquet file"),
+        }
+    }
+}
+
+/// Text of a CSV cell, empty for missing values
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Time(t) => t.and_utc().to_rfc3339_opts(SecondsFormat::Secs, true),
+        Value::Int(n) => n.map(|n| n.to_string()).unwrap_or_default(),
+        Value::Float(f) => f.map(|f| f.to_string()).unwrap_or_default(),
+        Value::Text(s) => s.clone().unwrap_or_default(),
+    }
+}
+
+/// Parquet message type of a table
+#[cfg(feature = "parquet")]
+fn schema(table: &str, fields: &[Field]) -> String {
+    let columns = fields
+        .iter()
+        .map(|f| match f.kind {
+            Kind::Time => format!("REQUIRED INT64 {} (TIMESTAMP(MICROS,true));", f.name),
+            Kind::Int => format!("OPTIONAL INT64 {};", f.name),
+            Kind::Float => format!("OPTIONAL FLOAT {};", f.name),
+            Kind::Text => format!("OPTIONAL BYTE_ARRAY {} (STRING);", f.name),
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("message {table} {{ {columns} }}")
+}
+
+/// Writes a batch as one Parquet row group
+#[cfg(feature = "parquet")]
+fn row_group(writer: &mut SerializedFileWriter<File>, batch: &Batch) -> Result<()> {
+    /// Present values and definition levels of an optional column
+    fn levels<'a, T, U>(
+        cells: &'a [Value],
+        get: impl Fn(&'a Value) -> Option<T>,
+        to: impl Fn(T) -> U,
+    ) -> (Vec<U>, Vec<i16>) {
+        let mut values = Vec::with_capacity(cells.len());
+        let mut defs = Vec::with_capacity(cells.len());
+        for cell in cells {
+            match get(cell) {
+                Some(v) => {
+                    values.push(to(v));
+                    defs.push(1);
+                }
+                None => defs.push(0),
+            }
+        }
+        (values, defs)
+    }
+
+    let mut group = writer
+        .next_row_group()
+        .context("Failed to start row group")?;
+    for (field, cells) in batch.fields.iter().zip(&batch.columns) {
+        let mut column = group
+            .next_column()?
+            .with_context(|| format!("Parquet schema lacks column {}", field.name))?;
+        match field.kind {
+            Kind::Time => {
+                let micros = cells
+                    .iter()
+                    .filter_map(|c| match c {
+                        Value::Time(t) => Some(t.and_utc().timestamp_micros()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                column
+                    .typed::<Int64Type>()
+                    .write_batch(&micros, None, None)?;
+            }
+            Kind::Int => {
+                let (values, defs) = levels(
+                    cells,
+                    |c| match c {
+                        Value::Int(n) => *n,
+                        _ => None,
+                    },
+                    |n| n,
+                );
+                column
+                    .typed::<Int64Type>()
+                    .write_batch(&values, Some(&defs), None)?;
+            }
+            Kind::Float => {
+                let (values, defs) = levels(
+                    cells,
+                    |c| match c {
+                        Value::Float(f) => *f,
+                        _ => None,
+                    },
+                    |f| f,
+                );
+                column
+                    .typed::<FloatType>()
+                    .write_batch(&values, Some(&defs), None)?;
+            }
+            Kind::Text => {
+                let (values, defs) = levels(
+                    cells,
+                    |c| match c {
+                        Value::Text(s) => s.as_deref(),
+                        _ => None,
+                    },
+                    ByteArray::from,
+                );
+                column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, Some(&defs), None)?;
+            }
+        }
+        column
+            .close()
+            .with_context(|| format!("Failed to write column {}", field.name))?;
+    }
+    group.close().context("Failed to finish row group")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use std::{env::temp_dir, fs, process};
+
+    static FIELDS: [Field; 4] = [
+        Field {
+            name: "time",
+            kind: Kind::Time,
+        },
+        Field {
+            name: "node_id",
+            kind: Kind::Int,
+        },
+        Field {
+            name: "temperature_c",
+            kind: Kind::Float,
+        },
+        Field {
+            name: "short_name",
+            kind: Kind::Text,
+        },
+    ];
+
+    fn batch() -> Result<Batch> {
+        let time = DateTime::from_timestamp(1_760_000_000, 0)
+            .context("out of range")?
+            .naive_utc();
+        let mut batch = Batch::new(&FIELDS);
+        batch.push(vec![
+            Value::Time(time),
+            Value::Int(Some(305_419_896)),
+            Value::Float(Some(21.5)),
+            Value::Text(Some(String::from("GH1"))),
+        ])?;
+        batch.push(vec![
+            Value::Time(time),
+            Value::Int(Some(305_419_896)),
+            Value::Float(None),
+            Value::Text(None),
+        ])?;
+        Ok(batch)
+    }
+
+    #[test]
+    fn rows_must_match_the_columns() -> Result<()> {
+        let mut batch = batch()?;
+        assert_eq!(batch.push(vec![Value::Int(None)]).ok(), None);
+        let mistyped = vec![
+            Value::Int(None),
+            Value::Int(None),
+            Value::Float(None),
+            Value::Text(None),
+        ];
+        assert_eq!(batch.push(mistyped).ok(), None);
+        assert_eq!(batch.len(), 2);
+        batch.clear();
+        assert_eq!(batch.len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn csv_has_a_header_and_empty_missing_cells() -> Result<()> {
+        let path = temp_dir().join(format!("export-{}.csv", process::id()));
+        let mut out = Output::create(&path, Format::Csv, "environment", &FIELDS)?;
+        out.write(&batch()?)?;
+        out.finish()?;
+        let text = fs::read_to_string(&path)?;
+        fs::remove_file(&path)?;
+        assert_eq!(
+            text,
+            "time,node_id,temperature_c,short_name\n\
+             2025-10-09T08:53:20Z,305419896,21.5,GH1\n\
+             2025-10-09T08:53:20Z,305419896,,\n"
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_keeps_types_and_nulls() -> Result<()> {
+        use parquet::file::reader::{FileReader as _, SerializedFileReader};
+
+        let path = temp_dir().join(format!("export-{}.parquet", process::id()));
+        let mut out = Output::create(&path, Format::Parquet, "environment", &FIELDS)?;
+        out.write(&batch()?)?;
+        out.finish()?;
+        let reader = SerializedFileReader::new(File::open(&path)?)?;
+        let rows = reader
+            .get_row_iter(None)?
+            .map(|row| row.map(|r| r.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        fs::remove_file(&path)?;
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        assert!(rows[0].contains("temperature_c: 21.5"), "{}", rows[0]);
+        assert!(rows[1].contains("temperature_c: null"), "{}", rows[1]);
+        assert!(rows[0].contains("short_name: \"GH1\""), "{}", rows[0]);
+        Ok(())
+    }
+}
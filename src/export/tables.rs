@@ -0,0 +1,561 @@
+use crate::export::output::{Batch, Field, Kind, Output, Value};
+use anyhow::{Context as _, Error, Result, bail};
+use chrono::NaiveDateTime;
+use futures_util::{Stream, TryStreamExt as _};
+use sqlx::{Pool, Postgres, postgres::types::Oid, query_as};
+
+/// Rows written per CSV flush or Parquet row group
+const BATCH_ROWS: usize = 10_000;
+
+/// Rows selected for an export
+#[derive(Debug)]
+pub(crate) struct Selection {
+    /// Deployment whose nodes are exported
+    pub(crate) location: String,
+    /// Nodes to export, every node of the deployment if empty
+    pub(crate) nodes: Vec<u32>,
+    /// Start of the time window, inclusive
+    pub(crate) from: NaiveDateTime,
+    /// End of the time window, exclusive
+    pub(crate) to: NaiveDateTime,
+}
+
+/// A table that can be exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Table {
+    /// `EnvironmentMetrics`
+    Environment,
+    /// `AirQualityMetrics`
+    AirQuality,
+    /// `Paxcount`
+    Paxcount,
+}
+
+impl Table {
+    /// Every exportable table, exported when none are named
+    pub(crate) const ALL: [Self; 3] = [Self::Environment, Self::AirQuality, Self::Paxcount];
+
+    /// Parses a table by its telemetry type name
+    pub(crate) fn parse(s: &str) -> Result<Self> {
+        match s {
+            "environment" => Ok(Self::Environment),
+            "air_quality" => Ok(Self::AirQuality),
+            "paxcount" => Ok(Self::Paxcount),
+            other => bail!("unknown table {other}, expected environment, air_quality or paxcount"),
+        }
+    }
+
+    /// Telemetry type name, used for file names
+    pub(crate) const fn name(self) -> &'static str {
+        match self {
+            Self::Environment => "environment",
+            Self::AirQuality => "air_quality",
+            Self::Paxcount => "paxcount",
+        }
+    }
+
+    /// Columns of the exported file
+    pub(crate) const fn fields(self) -> &'static [Field] {
+        match self {
+            Self::Environment => &ENVIRONMENT,
+            Self::AirQuality => &AIR_QUALITY,
+            Self::Paxcount => &PAXCOUNT,
+        }
+    }
+
+    /// Streams the selected rows into the output, returning the number of rows
+    pub(crate) async fn export(
+        self,
+        selection: &Selection,
+        pool: &Pool<Postgres>,
+        out: &mut Output,
+    ) -> Result<usize> {
+        let nodes = selection.nodes.iter().copied().map(Oid).collect::<Vec<_>>();
+        match self {
+            Self::Environment => {
+                let rows = query_as!(
+                    EnvironmentRow,
+                    r#"
+SELECT
+    e.time,
+    e.node_id::bigint AS "node_id!",
+    n.longname,
+    n.shortname,
+    n.deployment_location,
+    e.temperature,
+    e.relative_humidity,
+    e.barometric_pressure,
+    e.gas_resistance,
+    e.iaq::bigint AS iaq,
+    e.wind_direction::bigint AS wind_direction,
+    e.wind_speed,
+    e.wind_gust,
+    e.wind_lull,
+    e.rainfall_1h,
+    e.rainfall_24h,
+    e.voltage,
+    e.current,
+    e.sensor_type
+FROM EnvironmentMetrics e
+JOIN NodeInfo n ON n.node_id = e.node_id
+WHERE
+    n.deployment_location = $1
+    AND e.time >= $2
+    AND e.time < $3
+    AND (cardinality($4::oid[]) = 0 OR e.node_id = ANY($4))
+ORDER BY e.time, e.node_id
+                    "#,
+                    selection.location,
+                    selection.from,
+                    selection.to,
+                    &nodes,
+                )
+                .fetch(pool);
+                drain(rows, self.fields(), out)
+                    .await
+                    .context("Failed to export EnvironmentMetrics table")
+            }
+            Self::AirQuality => {
+                let rows = query_as!(
+                    AirQualityRow,
+                    r#"
+SELECT
+    a.time,
+    a.node_id::bigint AS "node_id!",
+    n.longname,
+    n.shortname,
+    n.deployment_location,
+    a.pm10standard::bigint AS pm10_standard,
+    a.pm25standard::bigint AS pm25_standard,
+    a.pm100standard::bigint AS pm100_standard,
+    a.pm10environmental::bigint AS pm10_environmental,
+    a.pm25environmental::bigint AS pm25_environmental,
+    a.pm100environmental::bigint AS pm100_environmental,
+    a.particles03um::bigint AS particles_03um,
+    a.particles05um::bigint AS particles_05um,
+    a.particles10um::bigint AS particles_10um,
+    a.particles25um::bigint AS particles_25um,
+    a.particles50um::bigint AS particles_50um,
+    a.particles100um::bigint AS particles_100um,
+    a.co2::bigint AS co2,
+    a.sensor_type
+FROM AirQualityMetrics a
+JOIN NodeInfo n ON n.node_id = a.node_id
+WHERE
+    n.deployment_location = $1
+    AND a.time >= $2
+    AND a.time < $3
+    AND (cardinality($4::oid[]) = 0 OR a.node_id = ANY($4))
+ORDER BY a.time, a.node_id
+                    "#,
+                    selection.location,
+                    selection.from,
+                    selection.to,
+                    &nodes,
+                )
+                .fetch(pool);
+                drain(rows, self.fields(), out)
+                    .await
+                    .context("Failed to export AirQualityMetrics table")
+            }
+            Self::Paxcount => export_paxcount(selection, &nodes, pool, out).await,
+        }
+    }
+}
+
+/// Streams the selected `Paxcount` rows into the output
+async fn export_paxcount(
+    selection: &Selection,
+    nodes: &[Oid],
+    pool: &Pool<Postgres>,
+    out: &mut Output,
+) -> Result<usize> {
+    let rows = query_as!(
+        PaxcountRow,
+        r#"
+SELECT
+    p.time,
+    p.node_id::bigint AS "node_id!",
+    n.longname,
+    n.shortname,
+    n.deployment_location,
+    p.wifi::bigint AS "wifi!",
+    p.ble::bigint AS "ble!",
+    p.uptime::bigint AS "uptime!"
+FROM Paxcount p
+JOIN NodeInfo n ON n.node_id = p.node_id
+WHERE
+    n.deployment_location = $1
+    AND p.time >= $2
+    AND p.time < $3
+    AND (cardinality($4::oid[]) = 0 OR p.node_id = ANY($4))
+ORDER BY p.time, p.node_id
+        "#,
+        selection.location,
+        selection.from,
+        selection.to,
+        nodes,
+    )
+    .fetch(pool);
+    drain(rows, &PAXCOUNT, out)
+        .await
+        .context("Failed to export Paxcount table")
+}
+
+/// Writes streamed rows in batches
+async fn drain<R: Into<Vec<Value>>>(
+    rows: impl Stream<Item = Result<R, sqlx::Error>>,
+    fields: &'static [Field],
+    out: &mut Output,
+) -> Result<usize> {
+    let mut rows = Box::pin(rows);
+    let mut batch = Batch::new(fields);
+    let mut total = 0;
+    while let Some(row) = rows.try_next().await.map_err(Error::from)? {
+        batch.push(row.into())?;
+        if batch.len() == BATCH_ROWS {
+            out.write(&batch)?;
+            total += batch.len();
+            batch.clear();
+        }
+    }
+    if batch.len() > 0 || total == 0 {
+        out.write(&batch)?;
+        total += batch.len();
+    }
+    Ok(total)
+}
+
+/// Shorthand for the column definitions
+const fn field(name: &'static str, kind: Kind) -> Field {
+    Field { name, kind }
+}
+
+/// Columns of every export, ahead of the table's readings
+macro_rules! node_columns {
+    ($($rest:expr),* $(,)?) => {
+        [
+            field("time", Kind::Time),
+            field("node_id", Kind::Int),
+            field("node", Kind::Text),
+            field("long_name", Kind::Text),
+            field("short_name", Kind::Text),
+            field("deployment_location", Kind::Text),
+            $($rest),*
+        ]
+    };
+}
+
+/// Columns of the environment export, units as sent by the firmware
+static ENVIRONMENT: [Field; 20] = node_columns![
+    field("temperature_c", Kind::Float),
+    field("relative_humidity_pct", Kind::Float),
+    field("barometric_pressure_hpa", Kind::Float),
+    field("gas_resistance_mohm", Kind::Float),
+    field("iaq", Kind::Int),
+    field("wind_direction_deg", Kind::Int),
+    field("wind_speed_mps", Kind::Float),
+    field("wind_gust_mps", Kind::Float),
+    field("wind_lull_mps", Kind::Float),
+    field("rainfall_1h_mm", Kind::Float),
+    field("rainfall_24h_mm", Kind::Float),
+    field("voltage_v", Kind::Float),
+    field("current_ma", Kind::Float),
+    field("sensor_type", Kind::Int),
+];
+
+/// Columns of the air quality export, particle counts are per 0.1 liter
+static AIR_QUALITY: [Field; 20] = node_columns![
+    field("pm10_standard_ugm3", Kind::Int),
+    field("pm25_standard_ugm3", Kind::Int),
+    field("pm100_standard_ugm3", Kind::Int),
+    field("pm10_environmental_ugm3", Kind::Int),
+    field("pm25_environmental_ugm3", Kind::Int),
+    field("pm100_environmental_ugm3", Kind::Int),
+    field("particles_03um_per_dl", Kind::Int),
+    field("particles_05um_per_dl", Kind::Int),
+    field("particles_10um_per_dl", Kind::Int),
+    field("particles_25um_per_dl", Kind::Int),
+    field("particles_50um_per_dl", Kind::Int),
+    field("particles_100um_per_dl", Kind::Int),
+    field("co2_ppm", Kind::Int),
+    field("sensor_type", Kind::Int),
+];
+
+/// Columns of the Paxcount export, devices seen within the counter's interval
+static PAXCOUNT: [Field; 9] = node_columns![
+    field("wifi_devices", Kind::Int),
+    field("ble_devices", Kind::Int),
+    field("counter_uptime_s", Kind::Int),
+];
+
+/// Cells of the columns every export starts with
+fn node_cells(
+    time: NaiveDateTime,
+    node_id: i64,
+    longname: String,
+    shortname: String,
+    location: String,
+) -> Vec<Value> {
+    vec![
+        Value::Time(time),
+        Value::Int(Some(node_id)),
+        Value::Text(Some(format!("!{node_id:08x}"))),
+        Value::Text(Some(longname)),
+        Value::Text(Some(shortname)),
+        Value::Text(Some(location)),
+    ]
+}
+
+/// An `EnvironmentMetrics` row joined with its node
+#[derive(Debug)]
+struct EnvironmentRow {
+    /// Time of the reading
+    time: NaiveDateTime,
+    /// Node number
+    node_id: i64,
+    /// Long name of the node
+    longname: String,
+    /// Short name of the node
+    shortname: String,
+    /// Deployment of the node
+    deployment_location: String,
+    /// Degrees Celsius
+    temperature: Option<f32>,
+    /// Percent
+    relative_humidity: Option<f32>,
+    /// hPa
+    barometric_pressure: Option<f32>,
+    /// `MOhm`
+    gas_resistance: Option<f32>,
+    /// Indoor air quality index
+    iaq: Option<i64>,
+    /// Degrees
+    wind_direction: Option<i64>,
+    /// m/s
+    wind_speed: Option<f32>,
+    /// m/s
+    wind_gust: Option<f32>,
+    /// m/s
+    wind_lull: Option<f32>,
+    /// mm
+    rainfall_1h: Option<f32>,
+    /// mm
+    rainfall_24h: Option<f32>,
+    /// Volts
+    voltage: Option<f32>,
+    /// Milliamperes
+    current: Option<f32>,
+    /// Sensor model number
+    sensor_type: Option<i32>,
+}
+
+impl From<EnvironmentRow> for Vec<Value> {
+    fn from(r: EnvironmentRow) -> Self {
+        let mut cells = node_cells(
+            r.time,
+            r.node_id,
+            r.longname,
+            r.shortname,
+            r.deployment_location,
+        );
+        cells.extend([
+            Value::Float(r.temperature),
+            Value::Float(r.relative_humidity),
+            Value::Float(r.barometric_pressure),
+            Value::Float(r.gas_resistance),
+            Value::Int(r.iaq),
+            Value::Int(r.wind_direction),
+            Value::Float(r.wind_speed),
+            Value::Float(r.wind_gust),
+            Value::Float(r.wind_lull),
+            Value::Float(r.rainfall_1h),
+            Value::Float(r.rainfall_24h),
+            Value::Float(r.voltage),
+            Value::Float(r.current),
+            Value::Int(r.sensor_type.map(i64::from)),
+        ]);
+        cells
+    }
+}
+
+/// An `AirQualityMetrics` row joined with its node
+#[derive(Debug)]
+struct AirQualityRow {
+    /// Time of the reading
+    time: NaiveDateTime,
+    /// Node number
+    node_id: i64,
+    /// Long name of the node
+    longname: String,
+    /// Short name of the node
+    shortname: String,
+    /// Deployment of the node
+    deployment_location: String,
+    /// PM1.0 standard, µg/m³
+    pm10_standard: Option<i64>,
+    /// PM2.5 standard, µg/m³
+    pm25_standard: Option<i64>,
+    /// PM10.0 standard, µg/m³
+    pm100_standard: Option<i64>,
+    /// PM1.0 environmental, µg/m³
+    pm10_environmental: Option<i64>,
+    /// PM2.5 environmental, µg/m³
+    pm25_environmental: Option<i64>,
+    /// PM10.0 environmental, µg/m³
+    pm100_environmental: Option<i64>,
+    /// Particles above 0.3 µm per 0.1 l
+    particles_03um: Option<i64>,
+    /// Particles above 0.5 µm per 0.1 l
+    particles_05um: Option<i64>,
+    /// Particles above 1.0 µm per 0.1 l
+    particles_10um: Option<i64>,
+    /// Particles above 2.5 µm per 0.1 l
+    particles_25um: Option<i64>,
+    /// Particles above 5.0 µm per 0.1 l
+    particles_50um: Option<i64>,
+    /// Particles above 10.0 µm per 0.1 l
+    particles_100um: Option<i64>,
+    /// CO2 in ppm
+    co2: Option<i64>,
+    /// Sensor model number
+    sensor_type: Option<i32>,
+}
+
+impl From<AirQualityRow> for Vec<Value> {
+    fn from(r: AirQualityRow) -> Self {
+        let mut cells = node_cells(
+            r.time,
+            r.node_id,
+            r.longname,
+            r.shortname,
+            r.deployment_location,
+        );
+        cells.extend(
+            [
+                r.pm10_standard,
+                r.pm25_standard,
+                r.pm100_standard,
+                r.pm10_environmental,
+                r.pm25_environmental,
+                r.pm100_environmental,
+                r.particles_03um,
+                r.particles_05um,
+                r.particles_10um,
+                r.particles_25um,
+                r.particles_50um,
+                r.particles_100um,
+                r.co2,
+                r.sensor_type.map(i64::from),
+            ]
+            .map(Value::Int),
+        );
+        cells
+    }
+}
+
+/// A `Paxcount` row joined with its node
+#[derive(Debug)]
+struct PaxcountRow {
+    /// Time the counts were received
+    time: NaiveDateTime,
+    /// Node number
+    node_id: i64,
+    /// Long name of the node
+    longname: String,
+    /// Short name of the node
+    shortname: String,
+    /// Deployment of the node
+    deployment_location: String,
+    /// Wi-Fi devices seen
+    wifi: i64,
+    /// Bluetooth devices seen
+    ble: i64,
+    /// Seconds
+    uptime: i64,
+}
+
+impl From<PaxcountRow> for Vec<Value> {
+    fn from(r: PaxcountRow) -> Self {
+        let mut cells = node_cells(
+            r.time,
+            r.node_id,
+            r.longname,
+            r.shortname,
+            r.deployment_location,
+        );
+        cells.extend([r.wifi, r.ble, r.uptime].map(|n| Value::Int(Some(n))));
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_fill_every_column() -> Result<()> {
+        let time = NaiveDateTime::default();
+        let env = EnvironmentRow {
+            time,
+            node_id: 0x1234_5678,
+            longname: String::from("Greenhouse"),
+            shortname: String::from("GH1"),
+            deployment_location: String::from("Portland"),
+            temperature: Some(21.5),
+            relative_humidity: None,
+            barometric_pressure: None,
+            gas_resistance: None,
+            iaq: None,
+            wind_direction: None,
+            wind_speed: None,
+            wind_gust: None,
+            wind_lull: None,
+            rainfall_1h: None,
+            rainfall_24h: None,
+            voltage: None,
+            current: None,
+            sensor_type: Some(7),
+        };
+        let mut batch = Batch::new(Table::Environment.fields());
+        let cells: Vec<Value> = env.into();
+        assert_eq!(cells[2], Value::Text(Some(String::from("!12345678"))));
+        batch.push(cells)?;
+
+        let aq = AirQualityRow {
+            time,
+            node_id: 1,
+            longname: String::new(),
+            shortname: String::new(),
+            deployment_location: String::new(),
+            pm10_standard: Some(3),
+            pm25_standard: None,
+            pm100_standard: None,
+            pm10_environmental: None,
+            pm25_environmental: None,
+            pm100_environmental: None,
+            particles_03um: None,
+            particles_05um: None,
+            particles_10um: None,
+            particles_25um: None,
+            particles_50um: None,
+            particles_100um: None,
+            co2: Some(415),
+            sensor_type: None,
+        };
+        Batch::new(Table::AirQuality.fields()).push(aq.into())?;
+
+        let pax = PaxcountRow {
+            time,
+            node_id: 2,
+            longname: String::new(),
+            shortname: String::new(),
+            deployment_location: String::new(),
+            wifi: 12,
+            ble: 30,
+            uptime: 600,
+        };
+        Batch::new(Table::Paxcount.fields()).push(pax.into())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use aes::{Aes128, Aes256};
+use anyhow::{Context as _, Result, bail};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use ctr::{
+    Ctr128BE,
+    cipher::{KeyIvInit as _, StreamCipher as _},
+};
+use meshtastic::protobufs::MeshPacket;
+
+/// Key the firmware expands one byte PSKs from, the PSK `AQ==` selects it unchanged
+const DEFAULT_KEY: [u8; 16] = [
+    0xd4, 0xf1, 0xbb, 0x3a, 0x20, 0x29, 0x07, 0x59, 0xf0, 0xbc, 0xff, 0xab, 0xcf, 0x4e, 0x69, 0x01,
+];
+
+/// Channel key decrypting Meshtastic channel traffic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChannelKey {
+    /// Unencrypted channel, payloads are sent as plain `Data`
+    Plain,
+    /// AES-128 key
+    Aes128([u8; 16]),
+    /// AES-256 key
+    Aes256([u8; 32]),
+}
+
+impl ChannelKey {
+    /// Parses a base64 encoded PSK the way the firmware expands it
+    pub(crate) fn from_base64(psk: &str) -> Result<Self> {
+        let bytes = STANDARD
+            .decode(psk)
+            .context("Channel PSK is not valid base64")?;
+        Ok(match bytes.as_slice() {
+            [] | [0] => Self::Plain,
+            // One byte PSKs pick a variant of the default key
+            [index] => {
+                let mut key = DEFAULT_KEY;
+                key[15] = key[15].wrapping_add(index - 1);
+                Self::Aes128(key)
+            }
+            b => match b.len() {
+                16 => Self::Aes128(b.try_into()?),
+                32 => Self::Aes256(b.try_into()?),
+                n => bail!("Channel PSK must be 0, 1, 16 or 32 bytes, got {n}"),
+            },
+        })
+    }
+
+    /// Decrypts the payload of a packet, keyed by its id and sender
+    pub(crate) fn decrypt(self, pkt: &MeshPacket, ciphertext: &[u8]) -> Vec<u8> {
+        let mut buf = ciphertext.to_vec();
+        let mut nonce = [0_u8; 16];
+        nonce[..8].copy_from_slice(&u64::from(pkt.id).to_le_bytes());
+        nonce[8..12].copy_from_slice(&pkt.from.to_le_bytes());
+        match self {
+            Self::Plain => {}
+            Self::Aes128(key) => {
+                Ctr128BE::<Aes128>::new(&key.into(), &nonce.into()).apply_keystream(&mut buf);
+            }
+            Self::Aes256(key) => {
+                Ctr128BE::<Aes256>::new(&key.into(), &nonce.into()).apply_keystream(&mut buf);
+            }
+        }
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use meshtastic::{
+        Message as _,
+        protobufs::{Data, PortNum},
+    };
+
+    /// `Data` on the telemetry port holding a `DeviceMetrics` battery level of 87
+    const PLAINTEXT: [u8; 13] = [
+        0x08, 0x43, 0x12, 0x09, 0x0d, 0x00, 0xf1, 0x53, 0x65, 0x12, 0x02, 0x08, 0x57,
+    ];
+
+    /// `PLAINTEXT` encrypted with the default key as packet 0x12345678 from 0xdeadbeef
+    const AES128_CIPHERTEXT: [u8; 13] = [
+        0xab, 0xe0, 0x29, 0x98, 0x8b, 0x74, 0x98, 0x6d, 0xcc, 0xc6, 0x92, 0x0a, 0x00,
+    ];
+
+    /// `PLAINTEXT` encrypted with the AES-256 key 0x00..0x1f as the same packet
+    const AES256_CIPHERTEXT: [u8; 13] = [
+        0x09, 0x28, 0xf6, 0x2f, 0xf7, 0xc7, 0x1a, 0xbe, 0x4d, 0x82, 0x30, 0x61, 0x87,
+    ];
+
+    fn packet() -> MeshPacket {
+        MeshPacket {
+            id: 0x1234_5678,
+            from: 0xdead_beef,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn default_key_decrypts() {
+        let key = ChannelKey::Aes128(DEFAULT_KEY);
+        assert_eq!(ChannelKey::from_base64("AQ==").ok(), Some(key));
+        let plain = key.decrypt(&packet(), &AES128_CIPHERTEXT);
+        assert_eq!(plain, PLAINTEXT);
+        assert_eq!(
+            Data::decode(plain.as_slice()).ok().map(|d| d.portnum()),
+            Some(PortNum::TelemetryApp)
+        );
+        // CTR mode encrypts with the same keystream
+        assert_eq!(key.decrypt(&packet(), &PLAINTEXT), AES128_CIPHERTEXT);
+    }
+
+    #[test]
+    fn aes256_key_decrypts() {
+        let key = ChannelKey::from_base64("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=").ok();
+        assert_eq!(
+            key.map(|k| k.decrypt(&packet(), &AES256_CIPHERTEXT)),
+            Some(PLAINTEXT.to_vec())
+        );
+        assert_eq!(
+            key.map(|k| k.decrypt(&packet(), &PLAINTEXT)),
+            Some(AES256_CIPHERTEXT.to_vec())
+        );
+    }
+
+    #[test]
+    fn short_psks_expand_like_the_firmware() {
+        assert_eq!(ChannelKey::from_base64("").ok(), Some(ChannelKey::Plain));
+        assert_eq!(
+            ChannelKey::from_base64("AA==").ok(),
+            Some(ChannelKey::Plain)
+        );
+        assert_eq!(
+            ChannelKey::from_base64("Ag==").ok(),
+            Some(ChannelKey::Aes128([
+                0xd4, 0xf1, 0xbb, 0x3a, 0x20, 0x29, 0x07, 0x59, 0xf0, 0xbc, 0xff, 0xab, 0xcf, 0x4e,
+                0x69, 0x02,
+            ]))
+        );
+        assert_eq!(
+            ChannelKey::Plain.decrypt(&packet(), &PLAINTEXT),
+            PLAINTEXT.to_vec()
+        );
+    }
+
+    #[test]
+    fn odd_psk_lengths_are_rejected() {
+        assert_eq!(ChannelKey::from_base64("AAEC").ok(), None);
+    }
+}
@@ -0,0 +1,332 @@
+use anyhow::{Context as _, Result};
+use meshtastic::{
+    Message as _,
+    protobufs::{
+        AirQualityMetrics, Data, DeviceMetrics, EnvironmentMetrics, MeshPacket, NodeInfo, PortNum,
+        Position, PowerMetrics, ServiceEnvelope, Telemetry, User, mesh_packet, telemetry::Variant,
+    },
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Packet as serialized by the firmware on `msh/.../2/json/<channel>/<gateway>` topics
+#[derive(Debug, Deserialize)]
+struct JsonPacket {
+    /// Sending node number
+    from: u32,
+    /// Destination node number
+    #[serde(default)]
+    to: u32,
+    /// Packet id
+    id: u32,
+    /// Unix time the gateway received the packet
+    #[serde(default)]
+    timestamp: u32,
+    /// Payload type, e.g. `telemetry`
+    #[serde(rename = "type")]
+    kind: String,
+    /// Decoded payload, its shape depends on `kind`
+    #[serde(default)]
+    payload: Value,
+}
+
+/// `nodeinfo` payload
+#[derive(Debug, Deserialize)]
+struct JsonUser {
+    /// User id, e.g. `!1234abcd`
+    #[serde(default)]
+    id: String,
+    /// Long name
+    #[serde(default)]
+    longname: String,
+    /// Short name
+    #[serde(default)]
+    shortname: String,
+    /// Hardware model number
+    #[serde(default)]
+    hardware: i32,
+}
+
+/// `position` payload
+#[derive(Debug, Deserialize)]
+struct JsonPosition {
+    /// Latitude in 1e-7 degrees
+    latitude_i: Option<i32>,
+    /// Longitude in 1e-7 degrees
+    longitude_i: Option<i32>,
+    /// Altitude in meters
+    altitude: Option<i32>,
+    /// Unix time of the fix
+    #[serde(default)]
+    time: u32,
+}
+
+/// `telemetry` payload, holding the fields of whichever variant was sent
+#[derive(Debug, Default, Deserialize)]
+struct JsonTelemetry {
+    /// `DeviceMetrics` battery level
+    battery_level: Option<u32>,
+    /// Device or environment sensor voltage
+    voltage: Option<f32>,
+    /// `DeviceMetrics` channel utilization
+    channel_utilization: Option<f32>,
+    /// `DeviceMetrics` transmit air utilization
+    air_util_tx: Option<f32>,
+    /// `DeviceMetrics` uptime
+    uptime_seconds: Option<u32>,
+    /// `EnvironmentMetrics` temperature
+    temperature: Option<f32>,
+    /// `EnvironmentMetrics` relative humidity
+    relative_humidity: Option<f32>,
+    /// `EnvironmentMetrics` barometric pressure
+    barometric_pressure: Option<f32>,
+    /// `EnvironmentMetrics` gas resistance
+    gas_resistance: Option<f32>,
+    /// `EnvironmentMetrics` current
+    current: Option<f32>,
+    /// `EnvironmentMetrics` indoor air quality
+    iaq: Option<u32>,
+    /// `EnvironmentMetrics` wind direction
+    wind_direction: Option<u32>,
+    /// `EnvironmentMetrics` wind speed
+    wind_speed: Option<f32>,
+    /// `AirQualityMetrics` PM1.0 standard
+    pm10: Option<u32>,
+    /// `AirQualityMetrics` PM2.5 standard
+    pm25: Option<u32>,
+    /// `AirQualityMetrics` PM10.0 standard
+    pm100: Option<u32>,
+    /// `AirQualityMetrics` PM1.0 environmental
+    pm10_e: Option<u32>,
+    /// `AirQualityMetrics` PM2.5 environmental
+    pm25_e: Option<u32>,
+    /// `AirQualityMetrics` PM10.0 environmental
+    pm100_e: Option<u32>,
+    /// `PowerMetrics` channel 1 voltage
+    voltage_ch1: Option<f32>,
+    /// `PowerMetrics` channel 1 current
+    current_ch1: Option<f32>,
+    /// `PowerMetrics` channel 2 voltage
+    voltage_ch2: Option<f32>,
+    /// `PowerMetrics` channel 2 current
+    current_ch2: Option<f32>,
+    /// `PowerMetrics` channel 3 voltage
+    voltage_ch3: Option<f32>,
+    /// `PowerMetrics` channel 3 current
+    current_ch3: Option<f32>,
+}
+
+impl JsonTelemetry {
+    /// Picks the telemetry variant from the keys present, as the JSON drops the variant name
+    fn variant(self) -> Option<Variant> {
+        if self.battery_level.is_some()
+            || self.channel_utilization.is_some()
+            || self.air_util_tx.is_some()
+            || self.uptime_seconds.is_some()
+        {
+            Some(Variant::DeviceMetrics(DeviceMetrics {
+                battery_level: self.battery_level,
+                voltage: self.voltage,
+                channel_utilization: self.channel_utilization,
+                air_util_tx: self.air_util_tx,
+                uptime_seconds: self.uptime_seconds,
+            }))
+        } else if self.temperature.is_some()
+            || self.relative_humidity.is_some()
+            || self.barometric_pressure.is_some()
+            || self.gas_resistance.is_some()
+            || self.iaq.is_some()
+            || self.wind_speed.is_some()
+        {
+            Some(Variant::EnvironmentMetrics(EnvironmentMetrics {
+                temperature: self.temperature,
+                relative_humidity: self.relative_humidity,
+                barometric_pressure: self.barometric_pressure,
+                gas_resistance: self.gas_resistance,
+                voltage: self.voltage,
+                current: self.current,
+                iaq: self.iaq,
+                wind_direction: self.wind_direction,
+                wind_speed: self.wind_speed,
+                ..Default::default()
+            }))
+        } else if self.pm10.is_some() || self.pm25.is_some() || self.pm100.is_some() {
+            Some(Variant::AirQualityMetrics(AirQualityMetrics {
+                pm10_standard: self.pm10,
+                pm25_standard: self.pm25,
+                pm100_standard: self.pm100,
+                pm10_environmental: self.pm10_e,
+                pm25_environmental: self.pm25_e,
+                pm100_environmental: self.pm100_e,
+                ..Default::default()
+            }))
+        } else if self.voltage_ch1.is_some() || self.current_ch1.is_some() {
+            Some(Variant::PowerMetrics(PowerMetrics {
+                ch1_voltage: self.voltage_ch1,
+                ch1_current: self.current_ch1,
+                ch2_voltage: self.voltage_ch2,
+                ch2_current: self.current_ch2,
+                ch3_voltage: self.voltage_ch3,
+                ch3_current: self.current_ch3,
+            }))
+        } else {
+            None
+        }
+    }
+}
+
+/// Re-encodes the payload of a JSON packet as the protobuf the firmware would have sent
+fn encode(pkt: &JsonPacket) -> Result<Option<(PortNum, Vec<u8>)>> {
+    let payload = pkt.payload.clone();
+    Ok(Some(match pkt.kind.as_str() {
+        "telemetry" => {
+            let tm: JsonTelemetry = serde_json::from_value(payload)?;
+            let Some(variant) = tm.variant() else {
+                return Ok(None);
+            };
+            let telemetry = Telemetry {
+                time: pkt.timestamp,
+                variant: Some(variant),
+            };
+            (PortNum::TelemetryApp, telemetry.encode_to_vec())
+        }
+        "nodeinfo" => {
+            let user: JsonUser = serde_json::from_value(payload)?;
+            let user = User {
+                id: user.id,
+                long_name: user.longname,
+                short_name: user.shortname,
+                hw_model: user.hardware,
+                ..Default::default()
+            };
+            // Stored the way the serial path decodes the node info port
+            let node_info = NodeInfo {
+                num: pkt.from,
+                user: Some(user),
+                ..Default::default()
+            };
+            (PortNum::NodeinfoApp, node_info.encode_to_vec())
+        }
+        "position" => {
+            let pos: JsonPosition = serde_json::from_value(payload)?;
+            let position = Position {
+                latitude_i: pos.latitude_i,
+                longitude_i: pos.longitude_i,
+                altitude: pos.altitude,
+                time: pos.time,
+                ..Default::default()
+            };
+            (PortNum::PositionApp, position.encode_to_vec())
+        }
+        _ => return Ok(None),
+    }))
+}
+
+/// Builds a `ServiceEnvelope` from a JSON uplink, `None` for payload types that are not stored
+pub(crate) fn envelope(
+    channel_id: &str,
+    gateway_id: &str,
+    payload: &[u8],
+) -> Result<Option<ServiceEnvelope>> {
+    let pkt: JsonPacket =
+        serde_json::from_slice(payload).context("Failed to parse JSON uplink packet")?;
+    let Some((portnum, bytes)) = encode(&pkt)
+        .with_context(|| format!("Failed to parse JSON uplink {} payload", pkt.kind))?
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(ServiceEnvelope {
+        packet: Some(MeshPacket {
+            from: pkt.from,
+            to: pkt.to,
+            id: pkt.id,
+            rx_time: pkt.timestamp,
+            via_mqtt: true,
+            payload_variant: Some(mesh_packet::PayloadVariant::Decoded(Data {
+                portnum: portnum.into(),
+                payload: bytes,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }),
+        channel_id: channel_id.to_owned(),
+        gateway_id: gateway_id.to_owned(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(envelope: &ServiceEnvelope) -> Option<&Data> {
+        match envelope.packet.as_ref()?.payload_variant.as_ref()? {
+            mesh_packet::PayloadVariant::Decoded(data) => Some(data),
+            mesh_packet::PayloadVariant::Encrypted(_) => None,
+        }
+    }
+
+    #[test]
+    fn environment_telemetry_is_reencoded() -> Result<()> {
+        let payload = br#"{"channel":0,"from":3735928559,"id":305419896,"payload":{"barometric_pressure":1013.2,"relative_humidity":48.5,"temperature":21.5},"sender":"!cafef00d","timestamp":1760000000,"to":4294967295,"type":"telemetry"}"#;
+        let envelope = envelope("LongFast", "!cafef00d", payload)?.context("not stored")?;
+        assert_eq!(envelope.channel_id, "LongFast");
+        assert_eq!(envelope.gateway_id, "!cafef00d");
+
+        let data = decoded(&envelope).context("not decoded")?;
+        assert_eq!(data.portnum(), PortNum::TelemetryApp);
+        let tm = Telemetry::decode(data.payload.as_slice())?;
+        assert_eq!(tm.time, 1_760_000_000);
+        let Some(Variant::EnvironmentMetrics(m)) = tm.variant else {
+            anyhow::bail!("expected environment metrics, got {:?}", tm.variant);
+        };
+        assert_eq!(m.temperature, Some(21.5));
+        assert_eq!(m.relative_humidity, Some(48.5));
+        Ok(())
+    }
+
+    #[test]
+    fn device_telemetry_takes_precedence_over_voltage() -> Result<()> {
+        let payload = br#"{"from":1,"id":2,"payload":{"battery_level":101,"voltage":4.2},"type":"telemetry"}"#;
+        let envelope = envelope("LongFast", "!00000001", payload)?.context("not stored")?;
+        let tm = Telemetry::decode(
+            decoded(&envelope)
+                .context("not decoded")?
+                .payload
+                .as_slice(),
+        )?;
+        let Some(Variant::DeviceMetrics(m)) = tm.variant else {
+            anyhow::bail!("expected device metrics, got {:?}", tm.variant);
+        };
+        assert_eq!(m.battery_level, Some(101));
+        assert_eq!(m.voltage, Some(4.2));
+        Ok(())
+    }
+
+    #[test]
+    fn nodeinfo_is_reencoded_as_node_info() -> Result<()> {
+        let payload = br#"{"from":305419896,"id":9,"payload":{"hardware":43,"id":"!12345678","longname":"Field Node","shortname":"FN"},"type":"nodeinfo"}"#;
+        let envelope = envelope("LongFast", "!00000001", payload)?.context("not stored")?;
+        let ni = NodeInfo::decode(
+            decoded(&envelope)
+                .context("not decoded")?
+                .payload
+                .as_slice(),
+        )?;
+        assert_eq!(ni.num, 0x1234_5678);
+        let user = ni.user.context("no user")?;
+        assert_eq!(user.long_name, "Field Node");
+        assert_eq!(user.short_name, "FN");
+        assert_eq!(user.hw_model, 43);
+        Ok(())
+    }
+
+    #[test]
+    fn other_types_are_skipped() -> Result<()> {
+        let payload = br#"{"from":1,"id":2,"payload":{"text":"hi"},"type":"text"}"#;
+        assert!(envelope("LongFast", "!00000001", payload)?.is_none());
+        let empty = br#"{"from":1,"id":3,"payload":{},"type":"telemetry"}"#;
+        assert!(envelope("LongFast", "!00000001", empty)?.is_none());
+        Ok(())
+    }
+}
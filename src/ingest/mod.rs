@@ -0,0 +1,6 @@
+/// Meshtastic channel encryption
+pub(crate) mod crypto;
+/// Firmware JSON uplink format
+pub(crate) mod json;
+/// MQTT uplink subscriber feeding the packet handler
+pub(crate) mod mqtt;
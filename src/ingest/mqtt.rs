@@ -0,0 +1,425 @@
+use crate::{
+    dto::{
+        dbops::packetsources,
+        packet_handler::{decode_new_payload, is_new},
+    },
+    ingest::{crypto::ChannelKey, json},
+    sinks::Sinks,
+    util::{
+        config::MqttIngestSettings,
+        mqtt::options,
+        state::{GatewayState, RecentPackets},
+    },
+};
+use anyhow::{Context as _, Result};
+use meshtastic::{
+    Message as _,
+    protobufs::{Data, MeshPacket, ServiceEnvelope, mesh_packet},
+};
+use rumqttc::{AsyncClient, ConnectionError, Event, EventLoop, Outgoing, Packet, QoS};
+use sqlx::{Pool, Postgres};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{
+    sync::Semaphore,
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+use tracing::Instrument as _;
+
+/// Number of client requests that may wait for the connection
+const REQUEST_QUEUE: usize = 16;
+
+/// Number of recent packets remembered to drop copies uplinked by several gateways
+const RECENT_PACKETS: usize = 1024;
+
+/// Delay before reconnecting after the broker connection failed
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Time allowed to disconnect on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Format of an uplink, told apart by its topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// `ServiceEnvelope` protobuf on `.../2/e/<channel>/<gateway>` or `.../2/map/`
+    Protobuf,
+    /// Firmware JSON on `.../2/json/<channel>/<gateway>`
+    Json,
+}
+
+/// Tells the uplink format from a topic, `None` for status and other topics
+fn format(topic: &str) -> Option<Format> {
+    let mut levels = topic.split('/');
+    if levels.any(|l| l == "json") {
+        return Some(Format::Json);
+    }
+    topic
+        .split('/')
+        .any(|l| matches!(l, "e" | "c" | "map"))
+        .then_some(Format::Protobuf)
+}
+
+/// Turns uplinked envelopes into plain `MeshPacket`s on the configured channels
+#[derive(Debug)]
+struct Uplink {
+    /// Keys of the channels to ingest, by channel name
+    channels: HashMap<String, ChannelKey>,
+    /// Packets already ingested
+    recent: RecentPackets,
+}
+
+impl Uplink {
+    /// Parses the configured channel keys
+    fn new(channels: &HashMap<String, String>) -> Result<Self> {
+        let channels = channels
+            .iter()
+            .map(|(name, psk)| {
+                ChannelKey::from_base64(psk)
+                    .with_context(|| format!("Invalid PSK for channel {name}"))
+                    .map(|key| (name.clone(), key))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            channels,
+            recent: RecentPackets::new(RECENT_PACKETS),
+        })
+    }
+
+    /// Decodes an uplink, returning its decrypted packet with the gateway and channel it came
+    /// through, or `None` if it is not on a configured channel or was already ingested
+    fn unwrap(&mut self, topic: &str, payload: &[u8]) -> Result<Option<ServiceEnvelope>> {
+        let envelope = match format(topic) {
+            Some(Format::Protobuf) => {
+                Some(ServiceEnvelope::decode(payload).context("Failed to decode ServiceEnvelope")?)
+            }
+            Some(Format::Json) => {
+                // msh/<region>/2/json/<channel>/<gateway>
+                let mut levels = topic.rsplit('/');
+                let gateway_id = levels.next().unwrap_or_default();
+                let channel_id = levels.next().unwrap_or_default();
+                json::envelope(channel_id, gateway_id, payload)?
+            }
+            None => None,
+        };
+        let Some(mut envelope) = envelope else {
+            return Ok(None);
+        };
+        let Some(key) = self.channels.get(&envelope.channel_id).copied() else {
+            return Ok(None);
+        };
+        let Some(pkt) = &mut envelope.packet else {
+            return Ok(None);
+        };
+        if let Some(mesh_packet::PayloadVariant::Encrypted(bytes)) = &pkt.payload_variant {
+            let data = Data::decode(key.decrypt(pkt, bytes).as_slice())
+                .context("Failed to decrypt packet, check the channel PSK")?;
+            pkt.payload_variant = Some(mesh_packet::PayloadVariant::Decoded(data));
+        }
+        // Only a decrypted packet counts as ingested, a copy of a corrupted one may still be good
+        if !self.recent.insert(pkt.from, pkt.id) {
+            return Ok(None);
+        }
+        // The configured channels stand in for the telemetry channel of the serial node
+        pkt.channel = 0;
+        pkt.via_mqtt = true;
+        Ok(Some(envelope))
+    }
+}
+
+/// Ingest of Meshtastic MQTT uplink traffic into the packet handler
+#[derive(Debug)]
+pub(crate) struct Ingest {
+    /// Handle used to disconnect from the broker
+    client: AsyncClient,
+    /// The task driving the broker connection and handling uplinks
+    task: JoinHandle<()>,
+}
+
+impl Ingest {
+    /// Connects to the broker and spawns the ingest task
+    pub(crate) fn new(
+        cfg: &MqttIngestSettings,
+        state: Arc<GatewayState>,
+        pool: Option<Pool<Postgres>>,
+        sinks: Arc<Sinks>,
+        semaphore: Arc<Semaphore>,
+    ) -> Result<Self> {
+        let uplink = Uplink::new(&cfg.channels)?;
+        let (client, eventloop) = AsyncClient::new(options(&cfg.broker)?, REQUEST_QUEUE);
+        let task = tokio::spawn(run(
+            eventloop,
+            client.clone(),
+            cfg.topics.clone(),
+            uplink,
+            Arc::new(Handler {
+                state,
+                pool,
+                sinks,
+                semaphore,
+            }),
+        ));
+        Ok(Self { client, task })
+    }
+
+    /// Starts the ingest if it is configured
+    pub(crate) fn spawn(
+        cfg: Option<&MqttIngestSettings>,
+        state: &Arc<GatewayState>,
+        pool: Option<&Pool<Postgres>>,
+        sinks: &Arc<Sinks>,
+        semaphore: &Arc<Semaphore>,
+    ) -> Result<Option<Self>> {
+        cfg.map(|cfg| {
+            Self::new(
+                cfg,
+                Arc::clone(state),
+                pool.cloned(),
+                Arc::clone(sinks),
+                Arc::clone(semaphore),
+            )
+        })
+        .transpose()
+        .context("Failed to set up MQTT ingest")
+    }
+
+    /// Disconnects from the broker and stops the ingest task
+    pub(crate) async fn shutdown(mut self) {
+        if let Err(e) = self.client.disconnect().await {
+            tracing::warn!(%e, "Failed to queue MQTT ingest disconnect");
+        }
+        if timeout(SHUTDOWN_TIMEOUT, &mut self.task).await.is_err() {
+            self.task.abort();
+        }
+    }
+}
+
+/// Shared state packets are handed to, as for packets from the serial node
+#[derive(Debug)]
+struct Handler {
+    /// Gateway state
+    state: Arc<GatewayState>,
+    /// Database pool, unless the NDJSON archive replaces the database
+    pool: Option<Pool<Postgres>>,
+    /// Configured sinks
+    sinks: Arc<Sinks>,
+    /// Bounds the packets handled at once, shared with the serial node
+    semaphore: Arc<Semaphore>,
+}
+
+impl Handler {
+    /// Handles the packet of an uplink in its own task, holding a permit of the semaphore
+    ///
+    /// The event loop does not wait on the database, only for a free permit.
+    async fn spawn(self: &Arc<Self>, envelope: ServiceEnvelope) -> Result<()> {
+        let Some(pkt) = envelope.packet else {
+            return Ok(());
+        };
+        let permit = Arc::clone(&self.semaphore).acquire_owned().await?;
+        let span = tracing::info_span!("mqtt", gateway = envelope.gateway_id);
+        let handler = Arc::clone(self);
+        tokio::spawn(
+            async move {
+                handler
+                    .handle(&pkt, &envelope.gateway_id, &envelope.channel_id)
+                    .await;
+                drop(permit);
+            }
+            .instrument(span),
+        );
+        Ok(())
+    }
+
+    /// Records the gateway a new packet came through and hands it to the packet handler
+    ///
+    /// Copies already received over serial or through another gateway are not recorded.
+    async fn handle(&self, pkt: &MeshPacket, gateway_id: &str, channel_id: &str) {
+        if !is_new(pkt, &self.state) {
+            return;
+        }
+        if let Some(pool) = &self.pool {
+            match packetsources::insert(pkt, gateway_id, channel_id, pool).await {
+                Ok(_) => tracing::info!(
+                    table = "PacketSources",
+                    node_id = pkt.from,
+                    "inserted 1 row"
+                ),
+                Err(e) => {
+                    self.state.db_error();
+                    tracing::error!(%e, table = "PacketSources", "insert failed");
+                }
+            }
+        }
+        decode_new_payload(pkt, &self.state, self.pool.as_ref(), &self.sinks).await;
+    }
+}
+
+/// Drives the broker connection, subscribing on every connect and handing uplinks to tasks
+async fn run(
+    mut eventloop: EventLoop,
+    client: AsyncClient,
+    topics: Vec<String>,
+    mut uplink: Uplink,
+    handler: Arc<Handler>,
+) {
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                tracing::info!("Connected to MQTT broker for ingest");
+                for topic in &topics {
+                    if let Err(e) = client.try_subscribe(topic, QoS::AtMostOnce) {
+                        tracing::error!(%e, topic, "Failed to subscribe");
+                    }
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                match uplink.unwrap(&publish.topic, &publish.payload) {
+                    Ok(Some(envelope)) => {
+                        if let Err(e) = handler.spawn(envelope).await {
+                            tracing::error!(%e, "Could not acquire a permit for an uplink");
+                            break;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::debug!(%e, topic = publish.topic, "Dropped uplink"),
+                }
+            }
+            Ok(Event::Outgoing(Outgoing::Disconnect)) | Err(ConnectionError::RequestsDone) => {
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::warn!(%e, "MQTT ingest connection failed, reconnecting");
+                sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use meshtastic::protobufs::PortNum;
+
+    const PLAINTEXT: [u8; 13] = [
+        0x08, 0x43, 0x12, 0x09, 0x0d, 0x00, 0xf1, 0x53, 0x65, 0x12, 0x02, 0x08, 0x57,
+    ];
+
+    /// `PLAINTEXT` encrypted with the default key as packet 0x12345678 from 0xdeadbeef
+    const CIPHERTEXT: [u8; 13] = [
+        0xab, 0xe0, 0x29, 0x98, 0x8b, 0x74, 0x98, 0x6d, 0xcc, 0xc6, 0x92, 0x0a, 0x00,
+    ];
+
+    fn uplink() -> Result<Uplink> {
+        Uplink::new(&HashMap::from([(
+            String::from("LongFast"),
+            String::from("AQ=="),
+        )]))
+    }
+
+    fn rejected(psk: &str) -> bool {
+        Uplink::new(&HashMap::from([(
+            String::from("LongFast"),
+            String::from(psk),
+        )]))
+        .is_err()
+    }
+
+    fn encrypted(channel_id: &str) -> Vec<u8> {
+        ServiceEnvelope {
+            packet: Some(MeshPacket {
+                from: 0xdead_beef,
+                id: 0x1234_5678,
+                channel: 8,
+                payload_variant: Some(mesh_packet::PayloadVariant::Encrypted(CIPHERTEXT.to_vec())),
+                ..Default::default()
+            }),
+            channel_id: channel_id.to_owned(),
+            gateway_id: String::from("!cafef00d"),
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn formats_follow_the_topic() {
+        assert_eq!(
+            format("msh/US/2/e/LongFast/!cafef00d"),
+            Some(Format::Protobuf)
+        );
+        assert_eq!(format("msh/US/2/map/"), Some(Format::Protobuf));
+        assert_eq!(
+            format("msh/US/2/json/LongFast/!cafef00d"),
+            Some(Format::Json)
+        );
+        assert_eq!(format("msh/US/2/stat/!cafef00d"), None);
+    }
+
+    #[test]
+    fn encrypted_uplinks_are_decrypted_once() -> Result<()> {
+        let mut uplink = uplink()?;
+        let payload = encrypted("LongFast");
+
+        let envelope = uplink
+            .unwrap("msh/US/2/e/LongFast/!cafef00d", &payload)?
+            .context("not ingested")?;
+        assert_eq!(envelope.gateway_id, "!cafef00d");
+        let pkt = envelope.packet.context("no packet")?;
+        assert_eq!(pkt.channel, 0);
+        assert!(pkt.via_mqtt);
+        let Some(mesh_packet::PayloadVariant::Decoded(data)) = pkt.payload_variant else {
+            anyhow::bail!("payload was not decrypted");
+        };
+        assert_eq!(data.encode_to_vec(), PLAINTEXT);
+        assert_eq!(data.portnum(), PortNum::TelemetryApp);
+
+        // The same packet uplinked by a second gateway
+        assert!(
+            uplink
+                .unwrap("msh/US/2/e/LongFast/!0badf00d", &payload)?
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn undecryptable_copies_do_not_hide_good_ones() -> Result<()> {
+        let mut uplink = uplink()?;
+        let mut corrupted = ServiceEnvelope::decode(encrypted("LongFast").as_slice())?;
+        let pkt = corrupted.packet.as_mut().context("no packet")?;
+        pkt.payload_variant = Some(mesh_packet::PayloadVariant::Encrypted(
+            CIPHERTEXT[..5].to_vec(),
+        ));
+        let Err(e) = uplink.unwrap("msh/US/2/e/LongFast/!0badf00d", &corrupted.encode_to_vec())
+        else {
+            anyhow::bail!("truncated packet was ingested");
+        };
+        assert!(e.to_string().contains("decrypt"));
+        assert!(
+            uplink
+                .unwrap("msh/US/2/e/LongFast/!cafef00d", &encrypted("LongFast"))?
+                .is_some()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unconfigured_channels_and_topics_are_ignored() -> Result<()> {
+        let mut uplink = uplink()?;
+        assert!(
+            uplink
+                .unwrap("msh/US/2/e/Private/!cafef00d", &encrypted("Private"))?
+                .is_none()
+        );
+        assert!(
+            uplink
+                .unwrap("msh/US/2/stat/!cafef00d", b"online")?
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_psks_are_rejected() {
+        assert!(rejected("AQI="));
+        assert!(rejected("not base64"));
+    }
+}
@@ -7,29 +7,64 @@
 
 //! Meshtastic to `PostgreSQL` database daemon
 
+#[cfg(feature = "api")]
+use crate::api::Api;
 use crate::dto::packet_handler::process_packet;
-use crate::util::MAX_INFLIGHT_TASKS;
-use crate::util::config::DEPLOYMENT_LOCATION;
+#[cfg(feature = "mqtt-ingest")]
+use crate::ingest::mqtt::Ingest;
+use crate::sched::Scheduler;
+use crate::sinks::{Sinks, event::Event, monitor};
+use crate::util::config::{DEPLOYMENT_LOCATION, RANGE_TEST};
 #[cfg(feature = "log_perf")]
 use crate::util::log::log_perf;
-use crate::util::{config::Settings, log::set_logger, state::GatewayState};
+use crate::util::{MAX_INFLIGHT_TASKS, tick_interval};
+use crate::util::{
+    config::{GatewayStatsSettings, RangeTestSettings, Settings, TopologySettings},
+    host,
+    log::set_logger,
+    state::GatewayState,
+};
 use anyhow::{Context as _, Error, Result, anyhow};
-use meshtastic::api::StreamApi;
+use meshtastic::api::{ConnectedStreamApi, StreamApi};
+use meshtastic::protobufs::FromRadio;
 use meshtastic::utils;
 #[cfg(feature = "mimalloc")]
 use mimalloc::MiMalloc;
 #[cfg(feature = "print-packets")]
 use serde_json::to_string_pretty;
-use std::sync::Arc;
-use tokio::{signal::ctrl_c, sync::Semaphore};
+use sqlx::{Pool, Postgres};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    signal::ctrl_c,
+    sync::{Semaphore, mpsc::UnboundedReceiver},
+    task::JoinHandle,
+    time::sleep_until,
+};
 use tracing::Instrument as _;
 
 #[cfg(feature = "mimalloc")]
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
+/// Read-only HTTP API over the stored telemetry
+#[cfg(feature = "api")]
+pub(crate) mod api;
 /// Handle data transfer objects
 pub(crate) mod dto;
+/// Export subcommand writing stored telemetry to dataset files
+#[cfg(feature = "export")]
+pub(crate) mod export;
+/// Packet sources besides the serial node
+#[cfg(feature = "mqtt-ingest")]
+pub(crate) mod ingest;
+/// Range-test recording and the `range-report` subcommand
+pub(crate) mod rangetest;
+/// Requests sent to nodes on a schedule
+pub(crate) mod sched;
+/// Outputs for alert events besides the database
+pub(crate) mod sinks;
+/// Mesh topology snapshots and the `topology` subcommand
+pub(crate) mod topology;
 /// Utilities module
 pub(crate) mod util;
 
@@ -44,45 +79,64 @@ async fn main() -> Result<(), Error> {
     // Read settings
     let settings = Settings::new().context("Error initializing Settings")?;
 
+    // Run a subcommand over the stored data instead of the daemon if asked to
+    if run_subcommand(&settings).await? {
+        return Ok(());
+    }
+
     // Create the gateway's state object
     let state = Arc::new(GatewayState::new());
 
-    // Create PostgreSQL connection
+    // Create the configured sinks for alert events
+    let sinks = Arc::new(Sinks::new(&settings).context("Failed to set up sinks")?);
+
+    // Create PostgreSQL connection, unless the NDJSON archive replaces the database
     let postgres_db = settings
-        .setup_postgres()
+        .setup_daemon_postgres()
         .await
         .context("Failed to connect to postgresql database")?;
 
-    // Connect to serial Meshtastic
-    let stream_api = StreamApi::new();
-    let entered_port = settings
-        .get_serial_port()
-        .context("Failed to get serial port")?;
-    let dbg_entered_port = entered_port.clone();
-    let serial_stream = utils::stream::build_serial_stream(entered_port, None, None, None)
-        .with_context(|| format!("Failed to build serial stream for {dbg_entered_port}"))?;
-    let (mut decoded_listener, stream_api) = stream_api.connect(serial_stream).await;
-
-    let config_id = utils::generate_rand_id();
-    let stream_api = stream_api
-        .configure(config_id)
-        .await
-        .context("Failed to configure serial stream")?;
+    // Connect to serial Meshtastic, it also carries the scheduled requests to nodes
+    let (mut decoded_listener, stream_api) = connect_serial(&settings).await?;
+    let mut scheduler = Scheduler::new(settings.requests.as_ref(), stream_api, &state)?;
 
     // Create a semaphore to bound the unbounded channel, maximum value of 32 tasks
     let max_tasks = (settings.get_max_connections() * 2).min(MAX_INFLIGHT_TASKS);
     let semaphore = Arc::new(Semaphore::new(max_tasks));
 
-    // Set the global deployment location string
-    DEPLOYMENT_LOCATION
-        .set(settings.deployment.location)
-        .map_err(|e| anyhow!("DEPLOYMENT_LOCATION initialized twice: {e}"))?;
+    // Set the global deployment location string and range-test settings
+    set_globals(settings.deployment.location, settings.range_test)?;
 
     // Output the version of the daemon to the logger
     tracing::info!("Daemon version: {VERSION}");
 
     // Load the already filled in nodeinfo tables to the state
-    state.load_from_db(&postgres_db).await?;
+    if let Some(pool) = &postgres_db {
+        state.load_from_db(pool).await?;
+    }
+
+    // Ingest MQTT uplink traffic alongside the serial node if configured
+    #[cfg(feature = "mqtt-ingest")]
+    let ingest = Ingest::spawn(
+        settings.mqtt_ingest.as_ref(),
+        &state,
+        postgres_db.as_ref(),
+        &sinks,
+        &semaphore,
+    )?;
+
+    // Serve the HTTP API if configured
+    #[cfg(feature = "api")]
+    let api = Api::start(settings.api.as_ref(), &state, postgres_db.as_ref(), &sinks).await?;
+
+    // Watch node liveness and database health, record the gateway's own stats and the topology
+    let periodic = spawn_periodic(
+        settings.gateway_stats,
+        settings.topology,
+        &state,
+        postgres_db.as_ref(),
+        &sinks,
+    );
 
     // This loop can be broken with ctrl+c, or by disconnecting
     // the attached serial port, or by sending a SIGTERM signal
@@ -93,6 +147,7 @@ async fn main() -> Result<(), Error> {
                 tracing::warn!("Received SIGINT");
                 break;
             }
+            () = sleep_until(scheduler.wake()), if scheduler.active() => scheduler.send_due().await,
             msg = decoded_listener.recv() => {
                 if let Some(from_radio) = msg {
                     let permit = match Arc::clone(&semaphore).acquire_owned().await {
@@ -104,9 +159,10 @@ async fn main() -> Result<(), Error> {
                     };
                     let s = Arc::clone(&state);
                     let pool = postgres_db.clone();
+                    let sinks = Arc::clone(&sinks);
                     let span = tracing::info_span!("packet", from = from_radio.id);
                     match tokio::spawn(async move {
-                        process_packet(&from_radio, &s, &pool).await;
+                        process_packet(&from_radio, &s, pool.as_ref(), &sinks).await;
 
                         // Debug logging in task after receiving/processing/inserting
                         #[cfg(feature = "debug")]
@@ -128,22 +184,149 @@ async fn main() -> Result<(), Error> {
                     }
                 } else {
                     tracing::error!("Serial connection closed");
+                    sinks.notify(Event::SerialDown);
                     break;
                 }
             }
         }
     }
 
+    // Stop taking in packets before waiting for the ones in flight
+    #[cfg(feature = "mqtt-ingest")]
+    if let Some(i) = ingest {
+        i.shutdown().await;
+    }
+    #[cfg(feature = "api")]
+    if let Some(a) = api {
+        a.shutdown().await;
+    }
+
     tracing::info!("Waiting for in-flight tasks to finish...");
     let _shutdown_lock = semaphore.acquire_many(u32::try_from(max_tasks)?).await;
     tracing::info!("All tasks finished.");
 
+    stop_sinks(periodic, sinks).await;
+
     // Called when either the radio is disconnected or the daemon receives
     // a SIGTERM or SIGKILL signal from systemctl or by other means
-    match stream_api.disconnect().await {
+    match scheduler.into_api().disconnect().await {
         Ok(_) => tracing::warn!("StreamApi disconnected without error"),
         Err(e) => tracing::error!(%e, "StreamApi disconnected with error"),
     }
 
     Ok(())
 }
+
+/// Sets the global deployment location and the range-test settings if configured
+fn set_globals(location: String, range_test: Option<RangeTestSettings>) -> Result<()> {
+    DEPLOYMENT_LOCATION
+        .set(location)
+        .map_err(|e| anyhow!("DEPLOYMENT_LOCATION initialized twice: {e}"))?;
+    if let Some(range_test) = range_test {
+        RANGE_TEST
+            .set(range_test)
+            .map_err(|e| anyhow!("RANGE_TEST initialized twice: {e:?}"))?;
+    }
+    Ok(())
+}
+
+/// Runs the subcommand the daemon was started with, returning whether there was one
+async fn run_subcommand(settings: &Settings) -> Result<bool> {
+    // Export stored telemetry
+    #[cfg(feature = "export")]
+    if let Some(args) = export::Args::from_env()? {
+        export::run(settings, args).await?;
+        return Ok(true);
+    }
+
+    // Summarise recorded range tests
+    if let Some(args) = rangetest::report::Args::from_env()? {
+        rangetest::report::run(settings, args).await?;
+        return Ok(true);
+    }
+
+    // Write a stored topology snapshot
+    if let Some(args) = topology::export::Args::from_env()? {
+        topology::export::run(settings, args).await?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Connects to the serial node and requests its configuration
+async fn connect_serial(
+    settings: &Settings,
+) -> Result<(UnboundedReceiver<FromRadio>, ConnectedStreamApi)> {
+    let stream_api = StreamApi::new();
+    let entered_port = settings
+        .get_serial_port()
+        .context("Failed to get serial port")?;
+    let dbg_entered_port = entered_port.clone();
+    let serial_stream = utils::stream::build_serial_stream(entered_port, None, None, None)
+        .with_context(|| format!("Failed to build serial stream for {dbg_entered_port}"))?;
+    let (decoded_listener, stream_api) = stream_api.connect(serial_stream).await;
+
+    let config_id = utils::generate_rand_id();
+    let stream_api = stream_api
+        .configure(config_id)
+        .await
+        .context("Failed to configure serial stream")?;
+    Ok((decoded_listener, stream_api))
+}
+
+/// Starts the liveness monitor if alerts are configured, the self-report and the topology
+/// snapshots if configured
+fn spawn_periodic(
+    gateway_stats: Option<GatewayStatsSettings>,
+    topology: Option<TopologySettings>,
+    state: &Arc<GatewayState>,
+    pool: Option<&Pool<Postgres>>,
+    sinks: &Arc<Sinks>,
+) -> [Option<JoinHandle<()>>; 3] {
+    let monitor = sinks.monitor_intervals().map(|(every, offline_after)| {
+        tokio::spawn(monitor::watch(
+            Arc::clone(state),
+            pool.cloned(),
+            Arc::clone(sinks),
+            every,
+            offline_after,
+        ))
+    });
+    if pool.is_none() && (gateway_stats.is_some() || topology.is_some()) {
+        tracing::warn!("Gateway stats and topology snapshots are not recorded without PostgreSQL");
+    }
+    let reporter = gateway_stats.zip(pool).map(|(cfg, pool)| {
+        tokio::spawn(host::report(
+            Arc::clone(state),
+            pool.clone(),
+            tick_interval(cfg.interval_secs),
+            cfg.disk_path,
+        ))
+    });
+    let snapshots = topology.zip(pool).map(|(cfg, pool)| {
+        tokio::spawn(topology::snapshot(
+            Arc::clone(state),
+            pool.clone(),
+            tick_interval(cfg.interval_secs),
+            Duration::from_secs(cfg.max_age_secs),
+        ))
+    });
+    [monitor, reporter, snapshots]
+}
+
+/// Stops the periodic tasks, then lets the sinks flush pending events
+async fn stop_sinks(tasks: [Option<JoinHandle<()>>; 3], sinks: Arc<Sinks>) {
+    for task in tasks.into_iter().flatten() {
+        task.abort();
+        if let Err(e) = task.await
+            && !e.is_cancelled()
+        {
+            tracing::error!(%e, "Periodic task failed");
+        }
+    }
+    if let Ok(s) = Arc::try_unwrap(sinks) {
+        s.shutdown().await;
+    } else {
+        tracing::warn!("Sinks still in use at shutdown, pending events may be lost");
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{
+    dto::dbops::{devicemetrics, rangetest},
+    util::{config::RangeTestSettings, state::GatewayState},
+};
+use anyhow::{Error, Result};
+use chrono::NaiveDateTime;
+use meshtastic::protobufs::MeshPacket;
+use sqlx::{Pool, Postgres, postgres::PgQueryResult};
+
+/// The `range-report` subcommand summarising recorded sessions
+pub(crate) mod report;
+
+/// Mean Earth radius in meters
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Degrees per unit of the integer coordinates `Position` packets carry
+const DEGREES_PER_UNIT: f64 = 1e-7;
+
+/// A position in the 1e-7 degree integers of `Position` packets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Point {
+    /// Latitude in 1e-7 degrees
+    pub(crate) latitude_i: i32,
+    /// Longitude in 1e-7 degrees
+    pub(crate) longitude_i: i32,
+}
+
+impl Point {
+    /// Converts a position given in degrees, `None` outside of the valid ranges
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "the ranges are checked, 1e-7 degrees of ±180 fit an i32"
+    )]
+    pub(crate) fn from_degrees(latitude: f64, longitude: f64) -> Option<Self> {
+        ((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude)).then(|| {
+            Self {
+                latitude_i: (latitude / DEGREES_PER_UNIT).round() as i32,
+                longitude_i: (longitude / DEGREES_PER_UNIT).round() as i32,
+            }
+        })
+    }
+
+    /// Great-circle distance to another position in meters
+    pub(crate) fn distance_m(self, other: Self) -> f64 {
+        let radians = |units: i32| (f64::from(units) * DEGREES_PER_UNIT).to_radians();
+        let (lat1, lat2) = (radians(self.latitude_i), radians(other.latitude_i));
+        let dlat = lat2 - lat1;
+        let dlon = radians(other.longitude_i) - radians(self.longitude_i);
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+    }
+}
+
+/// Sequence number of a range-test message, sent by the firmware as `seq 42`
+pub(crate) fn seq(text: &str) -> Option<i32> {
+    text.trim().strip_prefix("seq ")?.trim().parse().ok()
+}
+
+/// A received range-test message with what is known of where it was sent from
+#[derive(Debug)]
+pub(crate) struct Sample<'a> {
+    /// Text of the message
+    pub(crate) text: &'a str,
+    /// Sequence number, if the text carries one
+    pub(crate) seq: Option<i32>,
+    /// Last position the sender reported, with the time it was taken
+    pub(crate) sender: Option<(Point, NaiveDateTime)>,
+    /// Position of the gateway
+    pub(crate) gateway: Option<Point>,
+    /// Distance between sender and gateway in meters
+    pub(crate) distance_m: Option<f64>,
+}
+
+/// Records a range-test message with the sender's last position and its distance to the gateway
+///
+/// The gateway is at the configured position, or else at the serial node's last position.
+pub(crate) async fn record(
+    pkt: &MeshPacket,
+    text: &str,
+    cfg: &RangeTestSettings,
+    state: &GatewayState,
+    pool: &Pool<Postgres>,
+) -> Result<PgQueryResult, Error> {
+    let sender = devicemetrics::latest_position(pkt.from, pool).await?;
+    let configured = cfg
+        .latitude
+        .zip(cfg.longitude)
+        .and_then(|(lat, lon)| Point::from_degrees(lat, lon));
+    let gateway = match configured {
+        Some(point) => Some(point),
+        None => devicemetrics::latest_position(state.serial_number(), pool)
+            .await?
+            .map(|(point, _)| point),
+    };
+    let distance_m = sender
+        .zip(gateway)
+        .map(|((from, _), to)| from.distance_m(to));
+    let sample = Sample {
+        text,
+        seq: seq(text),
+        sender,
+        gateway,
+        distance_m,
+    };
+    rangetest::insert(pkt, &sample, pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context as _;
+
+    #[test]
+    fn distances_follow_the_great_circle() -> Result<()> {
+        let portland = Point::from_degrees(45.5152, -122.6784).context("valid coordinates")?;
+        let seattle = Point::from_degrees(47.6062, -122.3321).context("valid coordinates")?;
+        let km = portland.distance_m(seattle) / 1000.0;
+        assert!((km - 233.6).abs() < 0.5, "{km} km");
+        assert!(portland.distance_m(portland).abs() < f64::EPSILON);
+        assert_eq!(Point::from_degrees(91.0, 0.0), None);
+        Ok(())
+    }
+
+    #[test]
+    fn sequence_numbers_are_parsed() {
+        assert_eq!(seq("seq 42"), Some(42));
+        assert_eq!(seq(" seq 7\n"), Some(7));
+        assert_eq!(seq("hello"), None);
+    }
+}
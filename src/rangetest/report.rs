@@ -0,0 +1,411 @@
+use crate::util::{
+    config::Settings,
+    parse::{list, node_id, time},
+};
+use anyhow::{Context as _, Error, Result, bail};
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{Pool, Postgres, postgres::types::Oid, query_as};
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{self, Write as _},
+};
+
+/// Usage of the subcommand, shown with argument errors
+const USAGE: &str =
+    "usage: meshtastic-telemetry-daemon-rs range-report --from <time> [--to <time>] \
+[--nodes <id,...>] [--location <deployment>] [--bin-m <meters>] [--gap-secs <seconds>]
+times are Unix seconds, RFC 3339 or YYYY-MM-DD in UTC, node ids decimal or !hex";
+
+/// Arguments of the `range-report` subcommand
+#[derive(Debug)]
+pub(crate) struct Args {
+    /// Start of the time window, inclusive
+    from: NaiveDateTime,
+    /// End of the time window, exclusive
+    to: NaiveDateTime,
+    /// Senders to report on, every sender if empty
+    nodes: Vec<u32>,
+    /// Deployment to report on, the configured one if unset
+    location: Option<String>,
+    /// Width of the distance bins in meters
+    bin_m: u32,
+    /// Seconds of silence after which a sender starts a new session
+    gap_secs: i64,
+}
+
+impl Args {
+    /// Parses the command line if the daemon was started as `range-report`
+    pub(crate) fn from_env() -> Result<Option<Self>> {
+        let mut argv = env::args().skip(1);
+        if argv.next().as_deref() != Some("range-report") {
+            return Ok(None);
+        }
+        Self::parse(argv)
+            .map(Some)
+            .with_context(|| format!("Invalid range-report arguments\n{USAGE}"))
+    }
+
+    /// Parses the arguments following `range-report`
+    fn parse(mut argv: impl Iterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self {
+            from: NaiveDateTime::MIN,
+            to: Utc::now().naive_utc(),
+            nodes: Vec::new(),
+            location: None,
+            bin_m: 500,
+            gap_secs: 600,
+        };
+        let mut from = None;
+        while let Some(flag) = argv.next() {
+            let value = argv
+                .next()
+                .with_context(|| format!("{flag} needs a value"))?;
+            match flag.as_str() {
+                "--from" => from = Some(time(&value)?),
+                "--to" => parsed.to = time(&value)?,
+                "--nodes" => {
+                    parsed.nodes = list(Some(&value)).map(node_id).collect::<Result<_>>()?;
+                }
+                "--location" => parsed.location = Some(value),
+                "--bin-m" => parsed.bin_m = value.parse().context("invalid --bin-m")?,
+                "--gap-secs" => parsed.gap_secs = value.parse().context("invalid --gap-secs")?,
+                other => bail!("unknown argument {other}"),
+            }
+        }
+        parsed.from = from.context("--from is required")?;
+        if parsed.from >= parsed.to {
+            bail!("--from {} is not before --to {}", parsed.from, parsed.to);
+        }
+        if parsed.bin_m == 0 || parsed.gap_secs <= 0 {
+            bail!("--bin-m and --gap-secs must be above 0");
+        }
+        Ok(parsed)
+    }
+}
+
+/// A received message with a sequence number
+#[derive(Debug)]
+struct Row {
+    /// Sending node number
+    node_id: i64,
+    /// Time the message was received
+    time: NaiveDateTime,
+    /// Sequence number
+    seq: i32,
+    /// Distance to the gateway in meters, if the sender's position was known
+    distance_m: Option<f64>,
+}
+
+/// Messages of one sender without a restart of the sequence or a long silence
+#[derive(Debug)]
+struct Session {
+    /// Sending node number
+    node_id: i64,
+    /// Time of the first message
+    start: NaiveDateTime,
+    /// Time of the last message
+    end: NaiveDateTime,
+    /// Sequence numbers and distances of the received messages, ascending
+    received: Vec<(i32, Option<f64>)>,
+}
+
+/// Received and lost messages within a distance bin
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Tally {
+    /// Messages received
+    received: u64,
+    /// Messages missing from the sequence
+    lost: u64,
+}
+
+impl Tally {
+    /// Lost share of the sent messages in percent
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "message counts of a session stay far below 2^52"
+    )]
+    fn loss_pct(self) -> f64 {
+        let sent = self.received + self.lost;
+        if sent == 0 {
+            0.0
+        } else {
+            self.lost as f64 * 100.0 / sent as f64
+        }
+    }
+}
+
+/// Splits rows ordered by sender and time into sessions
+///
+/// A session ends when the sender falls silent for `gap_secs` or its sequence starts over.
+fn sessions(rows: Vec<Row>, gap_secs: i64) -> Vec<Session> {
+    let mut sessions: Vec<Session> = Vec::new();
+    for row in rows {
+        if let Some(last) = sessions.last_mut()
+            && last.node_id == row.node_id
+            && (row.time - last.end).num_seconds() < gap_secs
+            && last.received.last().is_some_and(|(seq, _)| *seq <= row.seq)
+        {
+            last.end = row.time;
+            // Repeats of the last message add nothing to the tally
+            if last.received.last().is_some_and(|(seq, _)| *seq < row.seq) {
+                last.received.push((row.seq, row.distance_m));
+            }
+            continue;
+        }
+        sessions.push(Session {
+            node_id: row.node_id,
+            start: row.time,
+            end: row.time,
+            received: vec![(row.seq, row.distance_m)],
+        });
+    }
+    sessions
+}
+
+impl Session {
+    /// Received and lost messages by distance bin, `None` for messages without a distance
+    ///
+    /// Lost messages are placed at the distance interpolated between the received messages
+    /// around them.
+    fn bins(&self, bin_m: u32) -> BTreeMap<Option<u64>, Tally> {
+        let bin = |d: Option<f64>| {
+            #[expect(
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss,
+                reason = "distances are positive and far below 2^64 meters"
+            )]
+            d.map(|d| (d / f64::from(bin_m)).floor() as u64)
+        };
+        let mut bins = BTreeMap::<Option<u64>, Tally>::new();
+        for pair in self.received.windows(2) {
+            let [(from_seq, from_d), (to_seq, to_d)] = [pair[0], pair[1]];
+            for missing in from_seq + 1..to_seq {
+                let d = match (from_d, to_d) {
+                    (Some(a), Some(b)) => {
+                        let t = f64::from(missing - from_seq) / f64::from(to_seq - from_seq);
+                        Some(a + (b - a) * t)
+                    }
+                    (a, b) => a.or(b),
+                };
+                bins.entry(bin(d)).or_default().lost += 1;
+            }
+        }
+        for (_, d) in &self.received {
+            bins.entry(bin(*d)).or_default().received += 1;
+        }
+        bins
+    }
+
+    /// The session's totals followed by its loss by distance
+    fn render(&self, bin_m: u32, out: &mut String) -> fmt::Result {
+        let bins = self.bins(bin_m);
+        let total = bins.values().fold(Tally::default(), |t, b| Tally {
+            received: t.received + b.received,
+            lost: t.lost + b.lost,
+        });
+        let (first, last) = (
+            self.received.first().map_or(0, |r| r.0),
+            self.received.last().map_or(0, |r| r.0),
+        );
+        let farthest = self
+            .received
+            .iter()
+            .filter_map(|r| r.1)
+            .fold(None, |max: Option<f64>, d| {
+                Some(max.map_or(d, |m| m.max(d)))
+            });
+        write!(
+            out,
+            "!{:08x} {} to {} UTC, seq {first} to {last}: {} of {} received, {:.1}% lost",
+            self.node_id,
+            self.start,
+            self.end.format("%H:%M:%S"),
+            total.received,
+            total.received + total.lost,
+            total.loss_pct(),
+        )?;
+        if let Some(m) = farthest {
+            write!(out, ", farthest {:.2} km", m / 1000.0)?;
+        }
+        writeln!(out)?;
+        writeln!(
+            out,
+            "  {:<16} {:>9} {:>6} {:>7}",
+            "distance m", "received", "lost", "loss"
+        )?;
+        for (bin, tally) in bins {
+            let range = bin.map_or_else(
+                || String::from("no position"),
+                |b| format!("{}-{}", b * u64::from(bin_m), (b + 1) * u64::from(bin_m)),
+            );
+            writeln!(
+                out,
+                "  {range:<16} {:>9} {:>6} {:>6.1}%",
+                tally.received,
+                tally.lost,
+                tally.loss_pct()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Received range-test messages with a sequence number, ordered by sender and time
+async fn rows(location: &str, args: &Args, pool: &Pool<Postgres>) -> Result<Vec<Row>, Error> {
+    let nodes = args.nodes.iter().copied().map(Oid).collect::<Vec<_>>();
+    query_as!(
+        Row,
+        r#"
+SELECT
+    node_id::bigint AS "node_id!",
+    time,
+    seq AS "seq!",
+    distance_m
+FROM RangeTest
+WHERE
+    deployment_location = $1
+    AND time >= $2
+    AND time < $3
+    AND seq IS NOT NULL
+    AND (cardinality($4::oid[]) = 0 OR node_id = ANY($4))
+ORDER BY node_id, time
+        "#,
+        location,
+        args.from,
+        args.to,
+        &nodes,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select rows from RangeTest table")
+}
+
+/// Prints packet loss against distance for every session in the selected window
+pub(crate) async fn run(settings: &Settings, args: Args) -> Result<()> {
+    let pool = settings
+        .setup_postgres()
+        .await
+        .context("Failed to connect to postgresql database")?;
+    let location = args
+        .location
+        .clone()
+        .unwrap_or_else(|| settings.deployment.location.clone());
+    let rows = rows(&location, &args, &pool).await?;
+    pool.close().await;
+
+    let mut out = String::new();
+    for session in sessions(rows, args.gap_secs) {
+        session.render(args.bin_m, &mut out)?;
+        writeln!(out)?;
+    }
+    if out.is_empty() {
+        out = format!("No range-test messages for {location} in the window\n");
+    }
+    print!("{out}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    fn row(node_id: i64, secs: i64, seq: i32, distance_m: Option<f64>) -> Result<Row> {
+        Ok(Row {
+            node_id,
+            time: DateTime::from_timestamp(1_760_000_000 + secs, 0)
+                .context("out of range")?
+                .naive_utc(),
+            seq,
+            distance_m,
+        })
+    }
+
+    fn rejected(args: &[&str]) -> bool {
+        Args::parse(args.iter().map(|a| (*a).to_owned())).is_err()
+    }
+
+    #[test]
+    fn sessions_split_on_silence_restart_and_sender() -> Result<()> {
+        let rows = vec![
+            row(1, 0, 1, None)?,
+            row(1, 60, 2, None)?,
+            row(1, 120, 2, None)?,
+            // Sequence starts over
+            row(1, 180, 1, None)?,
+            // Silence longer than the gap
+            row(1, 1000, 2, None)?,
+            row(2, 1000, 3, None)?,
+        ];
+        let split = sessions(rows, 600);
+        let seqs = split
+            .iter()
+            .map(|s| (s.node_id, s.received.iter().map(|r| r.0).collect()))
+            .collect::<Vec<(i64, Vec<i32>)>>();
+        assert_eq!(
+            seqs,
+            [(1, vec![1, 2]), (1, vec![1]), (1, vec![2]), (2, vec![3])]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lost_messages_are_binned_between_their_neighbours() -> Result<()> {
+        // Seq 2 to 4 are lost between 400 m and 1200 m, at 600, 800 and 1000 m
+        let rows = vec![
+            row(1, 0, 1, Some(400.0))?,
+            row(1, 60, 5, Some(1200.0))?,
+            row(1, 120, 6, None)?,
+        ];
+        let split = sessions(rows, 600);
+        let bins = split[0].bins(500);
+        assert_eq!(
+            bins.into_iter().collect::<Vec<_>>(),
+            [
+                (
+                    None,
+                    Tally {
+                        received: 1,
+                        lost: 0
+                    }
+                ),
+                (
+                    Some(0),
+                    Tally {
+                        received: 1,
+                        lost: 0
+                    }
+                ),
+                (
+                    Some(1),
+                    Tally {
+                        received: 0,
+                        lost: 2
+                    }
+                ),
+                (
+                    Some(2),
+                    Tally {
+                        received: 1,
+                        lost: 1
+                    }
+                ),
+            ]
+        );
+        let mut out = String::new();
+        split[0].render(500, &mut out)?;
+        assert!(out.starts_with("!00000001 2025-10-09 08:53:20 to 08:55:20 UTC, seq 1 to 6: "));
+        assert!(out.contains("3 of 6 received, 50.0% lost, farthest 1.20 km"));
+        Ok(())
+    }
+
+    #[test]
+    fn bad_arguments_are_rejected() {
+        assert!(rejected(&[]));
+        assert!(rejected(&["--from", "2025-10-01", "--bin-m", "0"]));
+        assert!(rejected(&["--from", "2025-10-01", "--gap-secs", "-5"]));
+        assert!(rejected(&["--from", "2025-10-02", "--to", "2025-10-01"]));
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{
+    sched::plan::{Plan, Request},
+    util::{
+        config::{RequestKind, RequestSettings},
+        state::{GatewayState, Probe},
+    },
+};
+use anyhow::{Context as _, Result, anyhow};
+use chrono::Utc;
+use meshtastic::{
+    Message as _,
+    api::ConnectedStreamApi,
+    packet::{PacketDestination, PacketRouter},
+    protobufs::{
+        DeviceMetrics, EnvironmentMetrics, FromRadio, LocalStats, MeshPacket, PortNum, Position,
+        RouteDiscovery, Telemetry, telemetry::Variant,
+    },
+    types::{EncodedMeshPacketData, MeshChannel, NodeId},
+};
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use tokio::time::Instant;
+
+/// When scheduled requests go out within the duty cycle
+pub(crate) mod plan;
+
+/// Sends scheduled requests through the serial node
+///
+/// `send_mesh_packet` futures are not `Send`, so instead of running in its own task the
+/// scheduler is driven by the main loop: sleep until [`Self::wake`], then [`Self::send_due`].
+#[derive(Debug)]
+pub(crate) struct Scheduler {
+    /// Connection to the serial node
+    api: ConnectedStreamApi,
+    /// Requests and their timing, `None` if nothing is scheduled
+    plan: Option<Plan>,
+    /// Source of the sending node number
+    router: Router,
+    /// Channel the requests are sent on
+    channel: MeshChannel,
+}
+
+impl Scheduler {
+    /// Plans the configured requests, replies arrive through the packet listener
+    pub(crate) fn new(
+        cfg: Option<&RequestSettings>,
+        api: ConnectedStreamApi,
+        state: &Arc<GatewayState>,
+    ) -> Result<Self> {
+        let plan = cfg
+            .filter(|c| !c.schedule.is_empty() || c.traceroute.is_some())
+            .map(|c| Plan::new(c, Utc::now().timestamp()))
+            .transpose()
+            .context("Invalid [requests] config")?;
+        let channel = cfg.map_or(0, |c| c.channel);
+        Ok(Self {
+            api,
+            plan,
+            router: Router {
+                state: Arc::clone(state),
+                echoed: None,
+            },
+            channel: MeshChannel::new(channel)
+                .map_err(|e| anyhow!("Invalid requests.channel {channel}: {e}"))?,
+        })
+    }
+
+    /// Whether any requests are scheduled
+    pub(crate) const fn active(&self) -> bool {
+        self.plan.is_some()
+    }
+
+    /// When the next request may be due
+    pub(crate) fn wake(&self) -> Instant {
+        let wait = self.plan.as_ref().map_or(1, |plan| {
+            plan.wake_at().saturating_sub(Utc::now().timestamp()).max(1)
+        });
+        Instant::now() + Duration::from_secs(wait.unsigned_abs())
+    }
+
+    /// Sends every request the duty cycle allows now
+    #[expect(
+        clippy::future_not_send,
+        reason = "send_mesh_packet borrows a non-Send dyn PacketRouter, this runs on the main task"
+    )]
+    pub(crate) async fn send_due(&mut self) {
+        let Some(plan) = &mut self.plan else {
+            return;
+        };
+        let known = self.router.state.node_ids();
+        while let Some(request) = plan.poll(Utc::now().timestamp(), &known) {
+            send(&mut self.api, &mut self.router, self.channel, request).await;
+        }
+    }
+
+    /// Hands back the connection for the disconnect
+    pub(crate) fn into_api(self) -> ConnectedStreamApi {
+        self.api
+    }
+}
+
+/// Sends a `want_response` request to its node
+///
+/// Traceroutes are echoed to the router, which learns their packet id that way so the reply
+/// can be matched and timed.
+#[expect(
+    clippy::future_not_send,
+    reason = "send_mesh_packet borrows a non-Send dyn PacketRouter, this runs on the main task"
+)]
+async fn send(
+    api: &mut ConnectedStreamApi,
+    router: &mut Router,
+    channel: MeshChannel,
+    request: Request,
+) {
+    let (port, payload) = request.kind.payload();
+    let traceroute = request.kind == RequestKind::Traceroute;
+    let sent_ms = Utc::now().timestamp_millis();
+    let sent = api
+        .send_mesh_packet(
+            router,
+            EncodedMeshPacketData::new(payload),
+            port,
+            PacketDestination::Node(NodeId::new(request.node)),
+            channel,
+            false,
+            true,
+            traceroute,
+            None,
+            None,
+        )
+        .await;
+    if let Err(e) = sent {
+        tracing::warn!(%e, node_id = request.node, kind = ?request.kind, "request not sent");
+        return;
+    }
+    tracing::debug!(node_id = request.node, kind = ?request.kind, "request sent");
+    if traceroute {
+        if let Some(id) = router.echoed.take() {
+            let probe = Probe {
+                node_id: request.node,
+                sent_ms,
+            };
+            router.state.probe_sent(id, probe);
+        } else {
+            tracing::warn!(
+                node_id = request.node,
+                "traceroute id unknown, reply not timed"
+            );
+        }
+    }
+}
+
+impl RequestKind {
+    /// Port and encoded payload asking a node for this
+    ///
+    /// Telemetry requests carry an empty reading of the wanted type, which the firmware
+    /// answers with a current one.
+    fn payload(self) -> (PortNum, Vec<u8>) {
+        let variant = match self {
+            Self::Device => Variant::DeviceMetrics(DeviceMetrics::default()),
+            Self::Environment => Variant::EnvironmentMetrics(EnvironmentMetrics::default()),
+            Self::LocalStats => Variant::LocalStats(LocalStats::default()),
+            Self::Position => return (PortNum::PositionApp, Position::default().encode_to_vec()),
+            Self::Traceroute => {
+                return (
+                    PortNum::TracerouteApp,
+                    RouteDiscovery::default().encode_to_vec(),
+                );
+            }
+        };
+        let telemetry = Telemetry {
+            time: 0,
+            variant: Some(variant),
+        };
+        (PortNum::TelemetryApp, telemetry.encode_to_vec())
+    }
+}
+
+/// Packet router the `StreamApi` takes the sending node from
+///
+/// Only traceroutes are echoed back to it, replies reach `process_packet` through the listener.
+#[derive(Debug)]
+struct Router {
+    /// Gateway state holding the serial node number and the pending traceroutes
+    state: Arc<GatewayState>,
+    /// Packet id of the last echoed request
+    echoed: Option<u32>,
+}
+
+impl PacketRouter<(), Infallible> for Router {
+    fn handle_packet_from_radio(&mut self, _packet: FromRadio) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn handle_mesh_packet(&mut self, packet: MeshPacket) -> Result<(), Infallible> {
+        self.echoed = Some(packet.id);
+        Ok(())
+    }
+
+    fn source_node_id(&self) -> NodeId {
+        // 0 until the node reported itself, the firmware fills in its own number then
+        NodeId::new(self.state.serial_number())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telemetry_requests_name_their_variant() -> Result<()> {
+        let (port, payload) = RequestKind::LocalStats.payload();
+        assert_eq!(port, PortNum::TelemetryApp);
+        let telemetry = Telemetry::decode(payload.as_slice())?;
+        assert!(matches!(telemetry.variant, Some(Variant::LocalStats(_))));
+
+        let (port, payload) = RequestKind::Position.payload();
+        assert_eq!(port, PortNum::PositionApp);
+        assert_eq!(Position::decode(payload.as_slice())?, Position::default());
+        Ok(())
+    }
+
+    #[test]
+    fn echoed_requests_are_remembered() {
+        let state = Arc::new(GatewayState::new());
+        state.set_serial_number(7);
+        let mut router = Router {
+            state,
+            echoed: None,
+        };
+        assert_eq!(router.source_node_id(), NodeId::new(7));
+        let echo = MeshPacket {
+            id: 42,
+            ..Default::default()
+        };
+        let Ok(()) = router.handle_mesh_packet(echo);
+        assert_eq!(router.echoed, Some(42));
+    }
+}
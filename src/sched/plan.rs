@@ -0,0 +1,352 @@
+use crate::util::config::{RequestKind, RequestSettings, TracerouteSettings};
+use anyhow::{Result, bail};
+use std::collections::VecDeque;
+
+/// Seconds `max_per_hour` counts requests over
+const HOUR: i64 = 3600;
+
+/// A request to one node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Request {
+    /// Node number the request is sent to
+    pub(crate) node: u32,
+    /// What the node is asked for
+    pub(crate) kind: RequestKind,
+}
+
+/// The requests of one schedule entry, queued together every round
+#[derive(Debug)]
+struct Round {
+    /// Requests of the round, node by node
+    requests: Vec<Request>,
+    /// Seconds between two rounds
+    every: i64,
+    /// Seconds the rounds are shifted from multiples of `every`
+    offset: i64,
+    /// Unix time of the next round
+    due: i64,
+}
+
+/// Start of the first round after `now` of rounds `every` seconds apart, shifted by `offset`
+const fn next_round(now: i64, every: i64, offset: i64) -> i64 {
+    ((now - offset).div_euclid(every) + 1) * every + offset
+}
+
+/// Traceroutes sent to one node after the other
+#[derive(Debug)]
+struct Probing {
+    /// Nodes probed in turn, ascending, every known node if empty
+    nodes: Vec<u32>,
+    /// Seconds between two traceroutes
+    every: i64,
+    /// Unix time of the next traceroute
+    due: i64,
+    /// Node probed last
+    last: Option<u32>,
+}
+
+impl Probing {
+    /// Starts with the first aligned time after `now`
+    fn new(cfg: &TracerouteSettings, now: i64) -> Result<Self> {
+        if cfg.every_secs == 0 {
+            bail!("requests.traceroute.every_secs must be above 0");
+        }
+        let every = i64::try_from(cfg.every_secs)?;
+        let mut nodes = cfg.nodes.clone();
+        nodes.sort_unstable();
+        nodes.dedup();
+        Ok(Self {
+            nodes,
+            every,
+            due: next_round(now, every, 0),
+            last: None,
+        })
+    }
+
+    /// The node after the one probed last, starting over past the highest
+    fn next(&mut self, known: &[u32]) -> Option<u32> {
+        let nodes = if self.nodes.is_empty() {
+            known
+        } else {
+            &self.nodes
+        };
+        let next = self
+            .last
+            .and_then(|last| nodes.iter().find(|n| **n > last))
+            .or_else(|| nodes.first())
+            .copied();
+        self.last = next.or(self.last);
+        next
+    }
+}
+
+/// Airtime budget of the gateway, a minimum gap and a sliding hourly cap
+#[derive(Debug)]
+struct DutyCycle {
+    /// Minimum seconds between two requests
+    min_gap: i64,
+    /// Most requests within any hour
+    max_per_hour: usize,
+    /// Send times within the last hour, oldest first
+    sent: VecDeque<i64>,
+}
+
+impl DutyCycle {
+    /// Earliest Unix time the next request may go out
+    fn earliest(&self) -> i64 {
+        let gap = self
+            .sent
+            .back()
+            .map_or(i64::MIN, |last| last + self.min_gap);
+        let cap = if self.sent.len() >= self.max_per_hour {
+            self.sent[self.sent.len() - self.max_per_hour] + HOUR
+        } else {
+            i64::MIN
+        };
+        gap.max(cap)
+    }
+
+    /// Counts a request sent at `now`, forgetting the ones that left the window
+    fn record(&mut self, now: i64) {
+        self.sent.push_back(now);
+        while self.sent.len() > self.max_per_hour
+            || self.sent.front().is_some_and(|t| *t <= now - HOUR)
+        {
+            self.sent.pop_front();
+        }
+    }
+}
+
+/// When each scheduled request goes out
+///
+/// Requests of a round wait in a queue until the duty cycle allows them. A request still
+/// queued when its next round comes up is not queued twice.
+#[derive(Debug)]
+pub(crate) struct Plan {
+    /// Configured rounds
+    rounds: Vec<Round>,
+    /// Traceroutes, if configured
+    probing: Option<Probing>,
+    /// Airtime budget
+    duty: DutyCycle,
+    /// Requests waiting for the duty cycle, oldest first
+    pending: VecDeque<Request>,
+}
+
+impl Plan {
+    /// Builds the rounds of the config, the first ones start after `now`
+    pub(crate) fn new(cfg: &RequestSettings, now: i64) -> Result<Self> {
+        if cfg.max_per_hour == 0 {
+            bail!("requests.max_per_hour must be above 0");
+        }
+        let mut rounds = Vec::with_capacity(cfg.schedule.len());
+        for entry in &cfg.schedule {
+            if entry.every_secs == 0 {
+                bail!("requests.schedule.every_secs must be above 0");
+            }
+            let mut round = Round {
+                requests: entry
+                    .nodes
+                    .iter()
+                    .flat_map(|node| {
+                        entry.requests.iter().map(|kind| Request {
+                            node: *node,
+                            kind: *kind,
+                        })
+                    })
+                    .collect(),
+                every: i64::try_from(entry.every_secs)?,
+                offset: i64::try_from(entry.offset_secs)?,
+                due: 0,
+            };
+            round.due = next_round(now, round.every, round.offset);
+            rounds.push(round);
+        }
+        Ok(Self {
+            rounds,
+            probing: cfg
+                .traceroute
+                .as_ref()
+                .map(|t| Probing::new(t, now))
+                .transpose()?,
+            duty: DutyCycle {
+                min_gap: i64::try_from(cfg.min_gap_secs)?,
+                max_per_hour: usize::try_from(cfg.max_per_hour)?,
+                sent: VecDeque::new(),
+            },
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Queues the rounds due by `now` and takes the next request the duty cycle allows
+    ///
+    /// `known` lists the nodes traceroutes go to when none are configured.
+    pub(crate) fn poll(&mut self, now: i64, known: &[u32]) -> Option<Request> {
+        for round in &mut self.rounds {
+            if round.due > now {
+                continue;
+            }
+            let skipped = round
+                .requests
+                .iter()
+                .filter(|r| !queue(&mut self.pending, **r))
+                .count();
+            if skipped > 0 {
+                tracing::warn!(
+                    skipped,
+                    "requests still queued from the last round, the schedule exceeds the duty cycle"
+                );
+            }
+            round.due = next_round(now, round.every, round.offset);
+        }
+        if let Some(probing) = &mut self.probing
+            && probing.due <= now
+        {
+            probing.due = next_round(now, probing.every, 0);
+            if let Some(node) = probing.next(known) {
+                let request = Request {
+                    node,
+                    kind: RequestKind::Traceroute,
+                };
+                if !queue(&mut self.pending, request) {
+                    tracing::warn!(node_id = node, "traceroute still queued from its last turn");
+                }
+            } else {
+                tracing::debug!("no node to traceroute yet");
+            }
+        }
+        if self.pending.is_empty() || self.duty.earliest() > now {
+            return None;
+        }
+        self.duty.record(now);
+        self.pending.pop_front()
+    }
+
+    /// Unix time at which `poll` next has something to do
+    pub(crate) fn wake_at(&self) -> i64 {
+        let due = self
+            .rounds
+            .iter()
+            .map(|r| r.due)
+            .chain(self.probing.as_ref().map(|p| p.due))
+            .min()
+            .unwrap_or(i64::MAX);
+        if self.pending.is_empty() {
+            due
+        } else {
+            due.min(self.duty.earliest())
+        }
+    }
+}
+
+/// Queues a request unless it is already waiting, returning whether it was queued
+fn queue(pending: &mut VecDeque<Request>, request: Request) -> bool {
+    let new = !pending.contains(&request);
+    if new {
+        pending.push_back(request);
+    }
+    new
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::config::ScheduleSettings;
+
+    fn settings(min_gap_secs: u64, max_per_hour: u32, nodes: Vec<u32>) -> RequestSettings {
+        RequestSettings {
+            channel: 0,
+            min_gap_secs,
+            max_per_hour,
+            schedule: vec![ScheduleSettings {
+                nodes,
+                requests: vec![RequestKind::Environment, RequestKind::Position],
+                every_secs: 3600,
+                offset_secs: 300,
+            }],
+            traceroute: None,
+        }
+    }
+
+    /// Requests handed out by polling every second over `[from, to)`
+    fn drain(plan: &mut Plan, from: i64, to: i64) -> Vec<(i64, Request)> {
+        (from..to)
+            .filter_map(|now| plan.poll(now, &[]).map(|r| (now, r)))
+            .collect()
+    }
+
+    #[test]
+    fn rounds_start_on_aligned_times() -> Result<()> {
+        let mut plan = Plan::new(&settings(0, 100, vec![1]), 10_000)?;
+        // Hourly rounds at five past, the first one after 10_000 is 11_100
+        assert_eq!(plan.wake_at(), 11_100);
+        assert_eq!(plan.poll(11_099, &[]), None);
+        let sent = drain(&mut plan, 11_100, 11_102);
+        assert_eq!(
+            sent,
+            [
+                (
+                    11_100,
+                    Request {
+                        node: 1,
+                        kind: RequestKind::Environment
+                    }
+                ),
+                (
+                    11_101,
+                    Request {
+                        node: 1,
+                        kind: RequestKind::Position
+                    }
+                ),
+            ]
+        );
+        assert_eq!(plan.wake_at(), 14_700);
+        Ok(())
+    }
+
+    #[test]
+    fn duty_cycle_spaces_and_caps_requests() -> Result<()> {
+        // Two nodes, two requests each, at most 3 an hour 60 seconds apart
+        let mut plan = Plan::new(&settings(60, 3, vec![1, 2]), 0)?;
+        let times = drain(&mut plan, 300, 301 + 2 * HOUR)
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect::<Vec<_>>();
+        // The fourth request waits for the first to leave the hour, then the
+        // second round finds it queued and only adds the other three
+        assert_eq!(times, [300, 360, 420, 3900, 3960, 4020, 7500]);
+        Ok(())
+    }
+
+    #[test]
+    fn traceroutes_take_turns() -> Result<()> {
+        let mut cfg = settings(0, 100, vec![]);
+        cfg.schedule.clear();
+        cfg.traceroute = Some(TracerouteSettings {
+            every_secs: 60,
+            nodes: vec![],
+        });
+        let mut plan = Plan::new(&cfg, 0)?;
+        let mut probed = |now, known: &[u32]| plan.poll(now, known).map(|r| (r.node, r.kind));
+        assert_eq!(probed(60, &[]), None);
+        assert_eq!(probed(120, &[5, 9]), Some((5, RequestKind::Traceroute)));
+        assert_eq!(probed(180, &[5, 9]), Some((9, RequestKind::Traceroute)));
+        // Node 7 joined behind the last probed node, the turn wraps around first
+        assert_eq!(probed(240, &[5, 7, 9]), Some((5, RequestKind::Traceroute)));
+        assert_eq!(probed(300, &[5, 7, 9]), Some((7, RequestKind::Traceroute)));
+        Ok(())
+    }
+
+    fn rejected(cfg: &RequestSettings) -> bool {
+        Plan::new(cfg, 0).is_err()
+    }
+
+    #[test]
+    fn invalid_limits_are_rejected() {
+        assert!(rejected(&settings(0, 0, vec![1])));
+        let mut cfg = settings(0, 1, vec![1]);
+        cfg.schedule[0].every_secs = 0;
+        assert!(rejected(&cfg));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::sinks::rules::Alert;
+use serde::Serialize;
+
+/// Alert events raised by the daemon and pushed to the configured sinks
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event {
+    /// A known node has not been heard from within the configured window
+    NodeOffline {
+        /// Node number of the quiet node
+        node_id: u32,
+        /// Long name of the quiet node
+        long_name: String,
+        /// Unix time the node was last heard from
+        last_heard: i64,
+    },
+    /// A `DeviceMetrics` packet reported a battery level below the configured threshold
+    LowBattery {
+        /// Node number reporting the battery level
+        node_id: u32,
+        /// Reported battery level in percent
+        battery_level: u32,
+        /// Configured threshold in percent
+        threshold: u32,
+    },
+    /// A telemetry reading crossed the threshold of a configured rule
+    RuleTriggered(Alert),
+    /// A telemetry reading returned past the hysteresis band of a triggered rule
+    RuleCleared(Alert),
+    /// A detection sensor or alert message was received
+    SensorTriggered {
+        /// Node number of the sensor
+        node_id: u32,
+        /// Port the message came on, `DETECTION_SENSOR_APP` or `ALERT_APP`
+        portnum: &'static str,
+        /// Text of the message
+        text: String,
+    },
+    /// The serial connection to the Meshtastic node closed
+    SerialDown,
+    /// The `PostgreSQL` database could not be reached
+    DatabaseUnavailable {
+        /// Error returned by the health check
+        error: String,
+    },
+}
+
+impl Event {
+    /// Name of the event type, matching the serialized `event` field
+    pub(crate) const fn kind(&self) -> &'static str {
+        match self {
+            Self::NodeOffline { .. } => "node_offline",
+            Self::LowBattery { .. } => "low_battery",
+            Self::RuleTriggered(_) => "rule_triggered",
+            Self::RuleCleared(_) => "rule_cleared",
+            Self::SensorTriggered { .. } => "sensor_triggered",
+            Self::SerialDown => "serial_down",
+            Self::DatabaseUnavailable { .. } => "database_unavailable",
+        }
+    }
+
+    /// Node the event is about, if any
+    pub(crate) const fn node_id(&self) -> Option<u32> {
+        match self {
+            Self::NodeOffline { node_id, .. }
+            | Self::LowBattery { node_id, .. }
+            | Self::SensorTriggered { node_id, .. } => Some(*node_id),
+            Self::RuleTriggered(alert) | Self::RuleCleared(alert) => Some(alert.node_id),
+            Self::SerialDown | Self::DatabaseUnavailable { .. } => None,
+        }
+    }
+
+    /// Name of the threshold rule the event is about, if any
+    #[cfg(feature = "webhook")]
+    pub(crate) fn rule(&self) -> Option<&str> {
+        match self {
+            Self::RuleTriggered(alert) | Self::RuleCleared(alert) => Some(&alert.rule),
+            _ => None,
+        }
+    }
+}
+
+impl From<Alert> for Event {
+    fn from(alert: Alert) -> Self {
+        if alert.cleared {
+            Self::RuleCleared(alert)
+        } else {
+            Self::RuleTriggered(alert)
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::Result;
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::TcpListener,
+    sync::mpsc::{UnboundedReceiver, unbounded_channel},
+};
+
+/// A request as seen by the stand-in
+#[derive(Debug)]
+pub(crate) struct Request {
+    /// Request line and headers
+    #[cfg_attr(
+        not(feature = "influxdb"),
+        expect(dead_code, reason = "only checked by the InfluxDB tests")
+    )]
+    pub(crate) head: String,
+    /// Request body
+    pub(crate) body: String,
+}
+
+/// Local HTTP stand-in answering requests with the given status codes in order, then 200,
+/// and forwarding every request
+pub(crate) async fn stand_in(statuses: Vec<u16>) -> Result<(String, UnboundedReceiver<Request>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/hook", listener.local_addr()?);
+    let (tx, rx) = unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut statuses = statuses.into_iter();
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut buf = Vec::new();
+            let mut chunk = [0_u8; 1024];
+            // Read until the headers and the announced body length have arrived
+            let request = loop {
+                let Ok(n) = socket.read(&mut chunk).await else {
+                    break None;
+                };
+                if n == 0 {
+                    break None;
+                }
+                buf.extend_from_slice(&chunk[..n]);
+                let text = String::from_utf8_lossy(&buf);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let len = head
+                        .lines()
+                        .find_map(|l| {
+                            l.to_ascii_lowercase()
+                                .strip_prefix("content-length:")
+                                .and_then(|v| v.trim().parse::<usize>().ok())
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= len {
+                        break Some(Request {
+                            head: head.to_owned(),
+                            body: body.to_owned(),
+                        });
+                    }
+                }
+            };
+            // Forward the request before answering so it is seen once the client returns
+            if let Some(request) = request
+                && tx.send(request).is_err()
+            {
+                break;
+            }
+            let status = statuses.next().unwrap_or(200);
+            let response =
+                format!("HTTP/1.1 {status} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            if let Err(e) = socket.write_all(response.as_bytes()).await {
+                tracing::warn!(%e, "stand-in failed to answer");
+            }
+        }
+    });
+
+    Ok((url, rx))
+}
@@ -0,0 +1,353 @@
+use crate::{
+    dto::telemetry::{Metric, Reading},
+    util::{config::InfluxSettings, timestamp},
+};
+use anyhow::{Context as _, Result};
+use reqwest::{Client, Response, StatusCode, header::AUTHORIZATION};
+use std::{collections::VecDeque, fmt::Write as _, time::Duration};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior, interval_at, timeout},
+};
+
+/// Number of points that may wait for the writer task before new ones are dropped
+const POINT_QUEUE: usize = 1024;
+
+/// Timeout of a single write request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of batches kept for retrying while the server is unreachable
+const RETAINED_BATCHES: usize = 10;
+
+/// Time allowed to write pending points on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// `InfluxDB` sink writing every decoded telemetry packet as line protocol in batches
+#[derive(Debug)]
+pub(crate) struct Influx {
+    /// Queue feeding the writer task
+    tx: Sender<String>,
+    /// The writer task
+    task: JoinHandle<()>,
+    /// Deployment location tag of every point
+    location: String,
+}
+
+/// Escapes the characters that end a measurement, tag key or tag value in line protocol
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            ',' | '=' | ' ' => {
+                out.push('\\');
+                out.push(c);
+            }
+            // Line protocol has no way to escape line breaks
+            '\n' | '\r' => out.push_str("\\ "),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Formats a reading as one line protocol point, `None` if it has no writable field
+///
+/// The measurement is the telemetry type, e.g. `environment`, tagged with the deployment
+/// location, the node number and its short name when known, and timestamped in seconds.
+fn point(reading: &Reading, short_name: Option<&str>, location: &str) -> Option<String> {
+    let fields = reading
+        .fields
+        .iter()
+        .filter_map(|(name, value)| match value {
+            Metric::Unsigned(n) => Some(format!("{name}={n}i")),
+            Metric::Float(f) if f.is_finite() => Some(format!("{name}={f}")),
+            // NaN and infinity are not valid field values
+            Metric::Float(_) => None,
+        })
+        .collect::<Vec<_>>();
+    if fields.is_empty() {
+        return None;
+    }
+
+    // Tags sorted by key, as the server prefers them
+    let mut line = String::from(reading.kind);
+    if !location.is_empty() {
+        let _ = write!(line, ",deployment_location={}", escape(location));
+    }
+    let _ = write!(line, ",node_id={}", reading.node_id);
+    if let Some(short_name) = short_name.filter(|s| !s.is_empty()) {
+        let _ = write!(line, ",short_name={}", escape(short_name));
+    }
+    let _ = write!(
+        line,
+        " {} {}",
+        fields.join(","),
+        timestamp(reading.time).and_utc().timestamp()
+    );
+    Some(line)
+}
+
+impl Influx {
+    /// Creates the HTTP client and spawns the writer task
+    pub(crate) fn new(cfg: &InfluxSettings, location: &str) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to build InfluxDB HTTP client")?;
+        let mut query = vec![
+            ("bucket", cfg.bucket.clone()),
+            ("precision", String::from("s")),
+        ];
+        if let Some(org) = &cfg.org {
+            query.push(("org", org.clone()));
+        }
+        let writer = Writer {
+            client,
+            url: format!("{}/api/v2/write", cfg.url.trim_end_matches('/')),
+            query,
+            token: cfg.token.clone(),
+            batch_size: cfg.batch_size.max(1),
+            pending: VecDeque::new(),
+            unreachable: false,
+        };
+        let (tx, rx) = mpsc::channel(POINT_QUEUE);
+
+        Ok(Self {
+            tx,
+            task: tokio::spawn(run(
+                rx,
+                writer,
+                Duration::from_secs(cfg.flush_interval_secs.max(1)),
+            )),
+            location: location.to_owned(),
+        })
+    }
+
+    /// Queues a reading for the next batch without waiting on the network
+    pub(crate) fn write(&self, reading: &Reading, short_name: Option<&str>) {
+        let Some(line) = point(reading, short_name, &self.location) else {
+            return;
+        };
+        if let Err(e) = self.tx.try_send(line) {
+            tracing::warn!(%e, "InfluxDB queue unavailable, dropping point");
+        }
+    }
+
+    /// Closes the queue and writes the pending points
+    pub(crate) async fn shutdown(self) {
+        drop(self.tx);
+        let mut task = self.task;
+        match timeout(SHUTDOWN_TIMEOUT, &mut task).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!(%e, "InfluxDB writer failed"),
+            Err(_) => {
+                tracing::warn!("InfluxDB did not take the pending points in time");
+                task.abort();
+            }
+        }
+    }
+}
+
+/// Batches points and writes them to the server
+#[derive(Debug)]
+struct Writer {
+    /// HTTP client with the request timeout
+    client: Client,
+    /// Write endpoint
+    url: String,
+    /// Bucket, organization and precision query parameters
+    query: Vec<(&'static str, String)>,
+    /// API token sent in the `Authorization` header
+    token: Option<String>,
+    /// Number of points written per request
+    batch_size: usize,
+    /// Points waiting to be written, oldest first
+    pending: VecDeque<String>,
+    /// Whether the last write failed, holding further writes until the next flush interval
+    unreachable: bool,
+}
+
+impl Writer {
+    /// Posts one batch of points
+    async fn post(&self, body: String) -> reqwest::Result<Response> {
+        let mut request = self.client.post(&self.url).query(&self.query).body(body);
+        if let Some(token) = &self.token {
+            request = request.header(AUTHORIZATION, format!("Token {token}"));
+        }
+        request.send().await?.error_for_status()
+    }
+
+    /// Writes the pending points in batches, keeping them for the next flush while the server
+    /// is unreachable
+    async fn flush(&mut self) {
+        while !self.pending.is_empty() {
+            let n = self.pending.len().min(self.batch_size);
+            let body = self
+                .pending
+                .range(..n)
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join("\n");
+            match self.post(body).await {
+                Ok(_) => {
+                    tracing::info!(sink = "InfluxDB", points = n, "wrote batch");
+                    self.unreachable = false;
+                }
+                // Retrying a batch the server rejected would only fail again
+                Err(e)
+                    if e.status().is_some_and(|s| {
+                        s.is_client_error() && s != StatusCode::TOO_MANY_REQUESTS
+                    }) =>
+                {
+                    tracing::error!(%e, sink = "InfluxDB", points = n, "batch rejected, dropping it");
+                }
+                Err(e) => {
+                    tracing::warn!(%e, sink = "InfluxDB", "write failed, retrying later");
+                    self.unreachable = true;
+                    let retained = self.batch_size.saturating_mul(RETAINED_BATCHES);
+                    if self.pending.len() > retained {
+                        let dropped = self.pending.len() - retained;
+                        self.pending.drain(..dropped);
+                        tracing::warn!(
+                            sink = "InfluxDB",
+                            points = dropped,
+                            "dropped oldest points"
+                        );
+                    }
+                    return;
+                }
+            }
+            self.pending.drain(..n);
+        }
+    }
+}
+
+/// Collects queued points, writing a batch once it is full or the flush interval passed
+async fn run(mut rx: Receiver<String>, mut writer: Writer, every: Duration) {
+    let mut ticker = interval_at(Instant::now() + every, every);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            line = rx.recv() => {
+                let Some(line) = line else {
+                    break;
+                };
+                writer.pending.push_back(line);
+                if writer.pending.len() >= writer.batch_size && !writer.unreachable {
+                    writer.flush().await;
+                }
+            }
+            _ = ticker.tick() => writer.flush().await,
+        }
+    }
+    writer.flush().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::http_stand_in::{Request, stand_in};
+    use std::collections::BTreeMap;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    fn settings(url: String, batch_size: usize) -> InfluxSettings {
+        InfluxSettings {
+            url,
+            bucket: String::from("mesh"),
+            org: Some(String::from("lab")),
+            token: Some(String::from("secret")),
+            batch_size,
+            flush_interval_secs: 3600,
+            replace_postgres: false,
+        }
+    }
+
+    fn environment(node_id: u32, temperature: f32) -> Reading {
+        Reading {
+            node_id,
+            msg_id: 7,
+            time: 1_760_000_000,
+            kind: "environment",
+            fields: BTreeMap::from([
+                ("temperature", Metric::Float(temperature)),
+                ("iaq", Metric::Unsigned(50)),
+            ]),
+        }
+    }
+
+    fn drain(rx: &mut UnboundedReceiver<Request>) -> Vec<Request> {
+        let mut requests = Vec::new();
+        while let Ok(request) = rx.try_recv() {
+            requests.push(request);
+        }
+        requests
+    }
+
+    #[test]
+    fn points_are_tagged_and_typed() {
+        assert_eq!(
+            point(
+                &environment(305_419_896, 21.5),
+                Some("FN 1"),
+                "Portland, OR"
+            )
+            .as_deref(),
+            Some(
+                "environment,deployment_location=Portland\\,\\ OR,node_id=305419896,\
+                 short_name=FN\\ 1 iaq=50i,temperature=21.5 1760000000"
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_names_and_invalid_fields_are_left_out() {
+        let mut reading = environment(1, f32::NAN);
+        assert_eq!(
+            point(&reading, None, "site").as_deref(),
+            Some("environment,deployment_location=site,node_id=1 iaq=50i 1760000000")
+        );
+        reading.fields.clear();
+        assert_eq!(point(&reading, Some("FN"), "site"), None);
+    }
+
+    #[tokio::test]
+    async fn writes_full_batches_and_flushes_the_rest() -> Result<()> {
+        let (url, mut rx) = stand_in(vec![]).await?;
+        let influx = Influx::new(&settings(url, 2), "site")?;
+        for node_id in 1..=3 {
+            influx.write(&environment(node_id, 20.0), None);
+        }
+        influx.shutdown().await;
+
+        let requests = drain(&mut rx);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].body.lines().count(), 2);
+        assert_eq!(requests[1].body.lines().count(), 1);
+        let head = requests[0].head.to_ascii_lowercase();
+        assert!(head.starts_with("post /hook/api/v2/write?bucket=mesh&precision=s&org=lab "));
+        assert!(head.contains("authorization: token secret"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keeps_points_while_unreachable_and_drops_rejected_batches() -> Result<()> {
+        let (url, mut rx) = stand_in(vec![503, 400]).await?;
+        let influx = Influx::new(&settings(url, 1), "site")?;
+        influx.write(&environment(1, 20.0), None);
+        influx.write(&environment(2, 20.0), None);
+        influx.shutdown().await;
+
+        // The first point fails with 503 and is retried on shutdown alongside the second,
+        // the retry is rejected with 400 and dropped, then the second point is written
+        let bodies = drain(&mut rx)
+            .into_iter()
+            .map(|r| r.body)
+            .collect::<Vec<_>>();
+        assert_eq!(bodies.len(), 3);
+        assert_eq!(bodies[0], bodies[1]);
+        assert!(bodies[2].contains("node_id=2 "));
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use crate::dto::packet::{Decoded, Payload};
+use meshtastic::protobufs::{MeshPacket, PortNum};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Packets a slow subscriber may fall behind by before it skips ahead
+const CAPACITY: usize = 256;
+
+/// A decoded packet as fanned out to live subscribers, serialized once for all of them
+#[derive(Debug)]
+pub(crate) struct LivePacket {
+    /// Sending node number
+    pub(crate) from: u32,
+    /// Port name, e.g. `TELEMETRY_APP`
+    pub(crate) portnum: &'static str,
+    /// Telemetry type of `TELEMETRY_APP` packets
+    pub(crate) variant: Option<&'static str>,
+    /// The packet as JSON
+    pub(crate) json: String,
+}
+
+/// Broadcast of every decoded packet to the connected stream clients
+#[derive(Debug, Clone)]
+pub(crate) struct Live {
+    /// Sender side of the broadcast, subscribed to per client
+    tx: broadcast::Sender<Arc<LivePacket>>,
+}
+
+impl Live {
+    /// Creates the broadcast with no subscribers
+    pub(crate) fn new() -> Self {
+        Self {
+            tx: broadcast::Sender::new(CAPACITY),
+        }
+    }
+
+    /// Serializes and sends a packet, skipping the work while nobody listens
+    pub(crate) fn send(&self, pkt: &MeshPacket, port: PortNum, payload: Payload<'_>) {
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+        let decoded = Decoded::new(pkt, port, payload);
+        match serde_json::to_string(&decoded) {
+            Ok(json) => {
+                // Subscribers may have left since the count, the packet is then dropped
+                drop(self.tx.send(Arc::new(LivePacket {
+                    from: decoded.from,
+                    portnum: decoded.portnum,
+                    variant: decoded.variant,
+                    json,
+                })));
+            }
+            Err(e) => tracing::warn!(%e, node_id = decoded.from, "packet serialization failed"),
+        }
+    }
+
+    /// Subscribes a new client to the packets sent from now on
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<Arc<LivePacket>> {
+        self.tx.subscribe()
+    }
+}
@@ -0,0 +1,262 @@
+#[cfg(any(feature = "mqtt", feature = "influxdb"))]
+use crate::dto::telemetry::Reading;
+#[cfg(feature = "influxdb")]
+use crate::sinks::influx::Influx;
+#[cfg(feature = "api")]
+use crate::sinks::live::Live;
+#[cfg(feature = "mqtt")]
+use crate::sinks::mqtt::Mqtt;
+#[cfg(feature = "ndjson")]
+use crate::sinks::ndjson::Ndjson;
+#[cfg(feature = "webhook")]
+use crate::sinks::webhook::Webhook;
+use crate::{
+    dto::packet::Payload,
+    sinks::{
+        event::Event,
+        rules::{Alert, Rules},
+    },
+    util::{
+        config::{AlertSettings, Settings},
+        state::GatewayState,
+        tick_interval,
+    },
+};
+use anyhow::{Context as _, Result};
+use meshtastic::protobufs::{DeviceMetrics, MeshPacket, PortNum, Telemetry, telemetry::Variant};
+use std::time::Duration;
+
+/// Alert events shared by the notification sinks
+pub(crate) mod event;
+/// Local HTTP server standing in for webhook endpoints and `InfluxDB` in tests
+#[cfg(all(test, any(feature = "webhook", feature = "influxdb")))]
+mod http_stand_in;
+/// `InfluxDB` sink writing decoded telemetry as line protocol
+#[cfg(feature = "influxdb")]
+pub(crate) mod influx;
+/// Broadcast of decoded packets to live API stream clients
+#[cfg(feature = "api")]
+pub(crate) mod live;
+/// Periodic checks raising node liveness and database health events
+pub(crate) mod monitor;
+/// MQTT sink publishing decoded telemetry
+#[cfg(feature = "mqtt")]
+pub(crate) mod mqtt;
+/// Newline-delimited JSON archive of decoded packets
+#[cfg(feature = "ndjson")]
+pub(crate) mod ndjson;
+/// Threshold rules with hysteresis over incoming telemetry
+pub(crate) mod rules;
+/// HTTP webhook notification sink
+#[cfg(feature = "webhook")]
+pub(crate) mod webhook;
+
+/// Outputs besides `PostgreSQL` that alert events and decoded telemetry fan out to
+#[derive(Debug, Default)]
+pub(crate) struct Sinks {
+    /// Thresholds deciding when alert events are raised
+    alerts: Option<AlertSettings>,
+    /// Threshold rules raising events from telemetry readings
+    rules: Rules,
+    /// Webhook endpoints receiving alert events
+    #[cfg(feature = "webhook")]
+    webhook: Option<Webhook>,
+    /// MQTT broker receiving decoded telemetry
+    #[cfg(feature = "mqtt")]
+    mqtt: Option<Mqtt>,
+    /// `InfluxDB` server receiving decoded telemetry
+    #[cfg(feature = "influxdb")]
+    influxdb: Option<Influx>,
+    /// Live stream of decoded packets served by the API
+    #[cfg(feature = "api")]
+    live: Option<Live>,
+    /// Rotated files receiving every decoded packet
+    #[cfg(feature = "ndjson")]
+    ndjson: Option<Ndjson>,
+}
+
+impl Sinks {
+    /// Creates the sinks enabled in the config file
+    pub(crate) fn new(settings: &Settings) -> Result<Self> {
+        Ok(Self {
+            alerts: settings.alerts,
+            rules: Rules::new(&settings.rules).context("Invalid threshold rule")?,
+            #[cfg(feature = "webhook")]
+            webhook: settings.webhook.as_ref().map(Webhook::new).transpose()?,
+            #[cfg(feature = "mqtt")]
+            mqtt: settings
+                .mqtt
+                .as_ref()
+                .map(|cfg| Mqtt::new(cfg, &settings.deployment.location))
+                .transpose()
+                .context("Failed to set up MQTT sink")?,
+            #[cfg(feature = "influxdb")]
+            influxdb: settings
+                .influxdb
+                .as_ref()
+                .map(|cfg| Influx::new(cfg, &settings.deployment.location))
+                .transpose()
+                .context("Failed to set up InfluxDB sink")?,
+            #[cfg(feature = "api")]
+            live: settings.api.as_ref().map(|_| Live::new()),
+            #[cfg(feature = "ndjson")]
+            ndjson: settings
+                .ndjson
+                .as_ref()
+                .map(Ndjson::new)
+                .transpose()
+                .context("Failed to set up NDJSON sink")?,
+        })
+    }
+
+    /// Live packet stream for the API, if the API is configured
+    #[cfg(feature = "api")]
+    pub(crate) const fn live(&self) -> Option<&Live> {
+        self.live.as_ref()
+    }
+
+    /// Logs an alert event and hands it to every configured sink
+    #[cfg_attr(
+        not(feature = "webhook"),
+        expect(
+            clippy::needless_pass_by_value,
+            clippy::unused_self,
+            reason = "consumed by the webhook sink when enabled"
+        )
+    )]
+    pub(crate) fn notify(&self, event: Event) {
+        tracing::warn!(event = event.kind(), node_id = event.node_id(), "{event:?}");
+        #[cfg(feature = "webhook")]
+        if let Some(webhook) = &self.webhook {
+            webhook.send(event);
+        }
+    }
+
+    /// Hands any decoded packet to every sink taking whole packets
+    #[cfg_attr(
+        not(any(feature = "api", feature = "ndjson")),
+        expect(
+            unused_variables,
+            clippy::unused_self,
+            clippy::missing_const_for_fn,
+            reason = "streamed by the API and archived by the NDJSON sink when enabled"
+        )
+    )]
+    pub(crate) fn publish_packet(&self, pkt: &MeshPacket, port: PortNum, payload: Payload<'_>) {
+        #[cfg(feature = "api")]
+        if let Some(live) = &self.live {
+            live.send(pkt, port, payload);
+        }
+        #[cfg(feature = "ndjson")]
+        if let Some(ndjson) = &self.ndjson {
+            ndjson.write(pkt, port, payload);
+        }
+    }
+
+    /// Hands a decoded telemetry packet to every sink publishing readings
+    #[cfg_attr(
+        not(any(feature = "mqtt", feature = "influxdb")),
+        expect(
+            unused_variables,
+            clippy::unused_self,
+            clippy::missing_const_for_fn,
+            reason = "published by the MQTT and InfluxDB sinks when enabled"
+        )
+    )]
+    #[cfg_attr(
+        all(feature = "mqtt", not(feature = "influxdb")),
+        expect(unused_variables, reason = "short names only tag InfluxDB points")
+    )]
+    pub(crate) fn publish_telemetry(
+        &self,
+        pkt: &MeshPacket,
+        tm: &Telemetry,
+        v: &Variant,
+        state: &GatewayState,
+    ) {
+        #[cfg(any(feature = "mqtt", feature = "influxdb"))]
+        let reading = Reading::new(pkt, tm, v);
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.publish(&reading);
+        }
+        #[cfg(feature = "influxdb")]
+        if let Some(influxdb) = &self.influxdb {
+            influxdb.write(&reading, state.short_name(pkt.from).as_deref());
+        }
+    }
+
+    /// Raises a low battery event if a `DeviceMetrics` packet is below the configured threshold
+    pub(crate) fn check_battery(&self, node_id: u32, dm: &DeviceMetrics) {
+        if let Some(threshold) = self.alerts.and_then(|a| a.battery_threshold)
+            && let Some(battery_level) = dm.battery_level
+            && battery_level < threshold
+        {
+            self.notify(Event::LowBattery {
+                node_id,
+                battery_level,
+                threshold,
+            });
+        }
+    }
+
+    /// Raises an event for a detection sensor or alert message if configured
+    pub(crate) fn sensor_triggered(&self, node_id: u32, port: PortNum, text: &str) {
+        if self.alerts.is_some_and(|a| a.sensor_events) {
+            self.notify(Event::SensorTriggered {
+                node_id,
+                portnum: port.as_str_name(),
+                text: text.to_owned(),
+            });
+        }
+    }
+
+    /// Evaluates the threshold rules against a telemetry reading, raising an event for each
+    /// rule that triggered or cleared and returning them for storage
+    pub(crate) fn evaluate_rules(&self, node_id: u32, v: &Variant) -> Vec<Alert> {
+        let alerts = self.rules.evaluate(node_id, v);
+        for alert in &alerts {
+            self.notify(Event::from(alert.clone()));
+        }
+        alerts
+    }
+
+    /// Check interval and offline window for the liveness monitor, if alerts are configured
+    pub(crate) fn monitor_intervals(&self) -> Option<(Duration, Duration)> {
+        self.alerts.map(|a| {
+            (
+                tick_interval(a.check_interval_secs),
+                Duration::from_secs(a.offline_after_secs),
+            )
+        })
+    }
+
+    /// Flushes pending events and stops the background sink tasks
+    #[cfg_attr(
+        not(any(
+            feature = "webhook",
+            feature = "mqtt",
+            feature = "influxdb",
+            feature = "ndjson"
+        )),
+        expect(clippy::unused_async, reason = "awaits the enabled sinks")
+    )]
+    pub(crate) async fn shutdown(self) {
+        #[cfg(feature = "webhook")]
+        if let Some(webhook) = self.webhook {
+            webhook.shutdown().await;
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = self.mqtt {
+            mqtt.shutdown().await;
+        }
+        #[cfg(feature = "influxdb")]
+        if let Some(influxdb) = self.influxdb {
+            influxdb.shutdown().await;
+        }
+        #[cfg(feature = "ndjson")]
+        if let Some(ndjson) = self.ndjson {
+            ndjson.shutdown().await;
+        }
+    }
+}
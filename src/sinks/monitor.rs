@@ -0,0 +1,58 @@
+use crate::{
+    sinks::{Sinks, event::Event},
+    util::state::GatewayState,
+};
+use chrono::Utc;
+use sqlx::{Pool, Postgres, query};
+use std::{sync::Arc, time::Duration};
+use tokio::time::{MissedTickBehavior, interval};
+
+/// Periodically reports nodes that went quiet and an unreachable database to the sinks
+///
+/// Each node is reported once until it is heard from again, and the database once per outage.
+/// Without a database only nodes are watched.
+pub(crate) async fn watch(
+    state: Arc<GatewayState>,
+    pool: Option<Pool<Postgres>>,
+    sinks: Arc<Sinks>,
+    every: Duration,
+    offline_after: Duration,
+) {
+    let offline_after = i64::try_from(offline_after.as_secs()).unwrap_or(i64::MAX);
+    let mut ticker = interval(every);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut db_down = false;
+
+    loop {
+        ticker.tick().await;
+
+        let cutoff = Utc::now().timestamp().saturating_sub(offline_after);
+        for (node_id, long_name, last_heard) in state.newly_offline(cutoff) {
+            sinks.notify(Event::NodeOffline {
+                node_id,
+                long_name,
+                last_heard,
+            });
+        }
+
+        let Some(pool) = &pool else {
+            continue;
+        };
+        match query!("SELECT 1 AS alive").fetch_one(pool).await {
+            Ok(_) => {
+                if db_down {
+                    tracing::info!("Database reachable again");
+                }
+                db_down = false;
+            }
+            Err(e) => {
+                if !db_down {
+                    sinks.notify(Event::DatabaseUnavailable {
+                        error: e.to_string(),
+                    });
+                }
+                db_down = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use crate::{
+    dto::telemetry::Reading,
+    util::{config::MqttSettings, mqtt::options},
+};
+use anyhow::{Context as _, Result};
+use rumqttc::{AsyncClient, ConnectionError, Event, EventLoop, Outgoing, Packet, QoS, qos};
+use std::time::Duration;
+use tokio::{
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+
+/// Number of publishes that may wait for the connection before new ones are dropped
+const REQUEST_QUEUE: usize = 64;
+
+/// Delay before reconnecting after the broker connection failed
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Time allowed to send queued publishes and disconnect on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// MQTT sink publishing every decoded telemetry packet as JSON
+#[derive(Debug)]
+pub(crate) struct Mqtt {
+    /// Handle queueing publishes on the event loop
+    client: AsyncClient,
+    /// The event loop task driving the broker connection
+    task: JoinHandle<()>,
+    /// `<prefix>/<location>`, the topic levels shared by every publish
+    base: String,
+    /// Quality of service of every publish
+    qos: QoS,
+    /// Whether the broker retains the last reading of every topic
+    retain: bool,
+}
+
+/// Replaces characters that are not allowed inside a single topic level
+fn topic_level(s: &str) -> String {
+    s.replace(['/', '+', '#'], "_")
+}
+
+impl Mqtt {
+    /// Sets up the broker connection and spawns its event loop task
+    pub(crate) fn new(cfg: &MqttSettings, location: &str) -> Result<Self> {
+        let qos = qos(cfg.qos).context("Invalid MQTT qos")?;
+        let (client, eventloop) = AsyncClient::new(options(&cfg.broker)?, REQUEST_QUEUE);
+
+        Ok(Self {
+            client,
+            task: tokio::spawn(run(eventloop)),
+            base: format!(
+                "{}/{}",
+                cfg.topic_prefix.trim_end_matches('/'),
+                topic_level(location)
+            ),
+            qos,
+            retain: cfg.retain,
+        })
+    }
+
+    /// Queues a reading for publishing to `<prefix>/<location>/<node_id>/<type>`
+    pub(crate) fn publish(&self, reading: &Reading) {
+        let topic = format!("{}/!{:08x}/{}", self.base, reading.node_id, reading.kind);
+        let payload = match serde_json::to_vec(reading) {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::error!(%e, topic, "Failed to serialize MQTT payload");
+                return;
+            }
+        };
+        if let Err(e) = self
+            .client
+            .try_publish(&topic, self.qos, self.retain, payload)
+        {
+            tracing::warn!(%e, topic, "MQTT queue unavailable, dropping reading");
+        }
+    }
+
+    /// Sends queued publishes, disconnects and stops the event loop task
+    pub(crate) async fn shutdown(mut self) {
+        if let Err(e) = self.client.disconnect().await {
+            tracing::warn!(%e, "Failed to queue MQTT disconnect");
+        }
+        if timeout(SHUTDOWN_TIMEOUT, &mut self.task).await.is_err() {
+            tracing::warn!("MQTT broker did not take the disconnect in time");
+            self.task.abort();
+        }
+    }
+}
+
+/// Drives the broker connection, reconnecting after failures until disconnected
+async fn run(mut eventloop: EventLoop) {
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => tracing::info!("Connected to MQTT broker"),
+            Ok(Event::Outgoing(Outgoing::Disconnect)) | Err(ConnectionError::RequestsDone) => {
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::warn!(%e, "MQTT connection failed, reconnecting");
+                sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dto::telemetry::Metric, util::config::MqttBroker};
+    use serde_json::Value;
+    use std::collections::BTreeMap;
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::{TcpListener, TcpStream},
+        sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    };
+
+    /// A publish as seen by the broker stand-in
+    #[derive(Debug)]
+    struct Received {
+        /// Topic name
+        topic: String,
+        /// Quality of service level
+        qos: u8,
+        /// Retain flag
+        retain: bool,
+        /// Payload bytes
+        payload: Vec<u8>,
+    }
+
+    /// Reads one MQTT control packet, returning its first header byte and its body
+    async fn read_packet(socket: &mut TcpStream) -> Result<Option<(u8, Vec<u8>)>> {
+        let mut header = [0_u8; 1];
+        if socket.read(&mut header).await? == 0 {
+            return Ok(None);
+        }
+        // Remaining length is a variable byte integer
+        let mut len = 0_usize;
+        let mut shift = 0;
+        loop {
+            let b = socket.read_u8().await?;
+            len |= usize::from(b & 0x7f) << shift;
+            if b & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+        let mut body = vec![0_u8; len];
+        socket.read_exact(&mut body).await?;
+        Ok(Some((header[0], body)))
+    }
+
+    /// Answers one client connection like a broker would, forwarding every publish
+    async fn serve(mut socket: TcpStream, tx: UnboundedSender<Received>) -> Result<()> {
+        while let Some((header, body)) = read_packet(&mut socket).await? {
+            match header >> 4 {
+                // CONNECT, accepted without a session
+                1 => socket.write_all(&[0x20, 2, 0, 0]).await?,
+                // PUBLISH, acknowledged at QoS 1
+                3 => {
+                    let qos = (header >> 1) & 0b11;
+                    let topic_len = usize::from(u16::from_be_bytes([body[0], body[1]]));
+                    let topic = String::from_utf8(body[2..2 + topic_len].to_vec())?;
+                    let mut rest = &body[2 + topic_len..];
+                    if qos > 0 {
+                        if qos == 1 {
+                            socket.write_all(&[0x40, 2, rest[0], rest[1]]).await?;
+                        }
+                        rest = &rest[2..];
+                    }
+                    tx.send(Received {
+                        topic,
+                        qos,
+                        retain: header & 1 == 1,
+                        payload: rest.to_vec(),
+                    })?;
+                }
+                // PINGREQ
+                12 => socket.write_all(&[0xd0, 0]).await?,
+                // DISCONNECT
+                14 => break,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Local MQTT broker stand-in accepting connections and forwarding every publish
+    async fn stand_in() -> Result<(u16, UnboundedReceiver<Received>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let (tx, rx) = unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                if let Err(e) = serve(socket, tx.clone()).await {
+                    tracing::warn!(%e, "stand-in connection failed");
+                }
+            }
+        });
+
+        Ok((port, rx))
+    }
+
+    /// Waits for the stand-in to forward the next publish
+    async fn next(rx: &mut UnboundedReceiver<Received>) -> Result<Received> {
+        timeout(Duration::from_secs(5), rx.recv())
+            .await?
+            .context("stand-in stopped")
+    }
+
+    fn settings(port: u16, qos: u8, retain: bool) -> MqttSettings {
+        MqttSettings {
+            broker: MqttBroker {
+                host: String::from("127.0.0.1"),
+                port,
+                client_id: String::from("test"),
+                tls: false,
+                ca_file: None,
+                username: None,
+                password: None,
+            },
+            topic_prefix: String::from("meshtastic/"),
+            qos,
+            retain,
+        }
+    }
+
+    fn environment(node_id: u32, temperature: f32) -> Reading {
+        Reading {
+            node_id,
+            msg_id: 7,
+            time: 1_700_000_000,
+            kind: "environment",
+            fields: BTreeMap::from([("temperature", Metric::Float(temperature))]),
+        }
+    }
+
+    #[test]
+    fn topic_levels_are_sanitized() {
+        assert_eq!(topic_level("Portland/North #2+"), "Portland_North _2_");
+    }
+
+    #[tokio::test]
+    async fn publishes_readings_as_json() -> Result<()> {
+        let (port, mut rx) = stand_in().await?;
+        let mqtt = Mqtt::new(&settings(port, 1, true), "Field/Site")?;
+        mqtt.publish(&environment(0x1234_abcd, 21.5));
+        mqtt.shutdown().await;
+
+        let publish = next(&mut rx).await?;
+        assert_eq!(publish.topic, "meshtastic/Field_Site/!1234abcd/environment");
+        assert_eq!(publish.qos, 1);
+        assert!(publish.retain);
+        let body: Value = serde_json::from_slice(&publish.payload)?;
+        assert_eq!(body["node_id"], 0x1234_abcd);
+        assert_eq!(body["type"], "environment");
+        assert_eq!(body["temperature"], 21.5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn publishes_without_retain_at_qos_0() -> Result<()> {
+        let (port, mut rx) = stand_in().await?;
+        let mqtt = Mqtt::new(&settings(port, 0, false), "site")?;
+        mqtt.publish(&environment(1, 20.0));
+        mqtt.publish(&environment(2, 20.0));
+        mqtt.shutdown().await;
+
+        let first = next(&mut rx).await?;
+        assert_eq!(first.qos, 0);
+        assert!(!first.retain);
+        assert_eq!(
+            next(&mut rx).await?.topic,
+            "meshtastic/site/!00000002/environment"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_qos_is_rejected() -> Result<()> {
+        anyhow::ensure!(
+            Mqtt::new(&settings(1883, 3, false), "site").is_err(),
+            "qos 3 should be rejected"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+use crate::{
+    dto::packet::{Decoded, Payload},
+    util::config::NdjsonSettings,
+};
+use anyhow::{Context as _, Result};
+use chrono::{NaiveDate, Utc};
+use flate2::{Compression, write::GzEncoder};
+use meshtastic::protobufs::{MeshPacket, PortNum};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, ErrorKind, Write},
+    path::PathBuf,
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    task::{JoinHandle, spawn_blocking},
+    time::timeout,
+};
+
+/// Number of lines that may wait for the writer before new ones are dropped
+const LINE_QUEUE: usize = 1024;
+
+/// Time allowed to write pending lines on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Sink appending every decoded packet as one JSON line to rotated files
+#[derive(Debug)]
+pub(crate) struct Ndjson {
+    /// Queue feeding the writer
+    tx: Sender<String>,
+    /// The blocking writer task
+    task: JoinHandle<()>,
+}
+
+impl Ndjson {
+    /// Creates the output directory and starts the writer
+    pub(crate) fn new(cfg: &NdjsonSettings) -> Result<Self> {
+        fs::create_dir_all(&cfg.dir)
+            .with_context(|| format!("Failed to create NDJSON directory {}", cfg.dir))?;
+        let archive = Archive {
+            dir: PathBuf::from(&cfg.dir),
+            rotate_bytes: cfg.rotate_bytes.filter(|b| *b > 0),
+            rotate_daily: cfg.rotate_daily,
+            gzip: cfg.gzip,
+            current: None,
+        };
+        let (tx, rx) = mpsc::channel(LINE_QUEUE);
+        Ok(Self {
+            tx,
+            task: spawn_blocking(move || archive.run(rx)),
+        })
+    }
+
+    /// Queues a packet for the writer without waiting on the disk
+    pub(crate) fn write(&self, pkt: &MeshPacket, port: PortNum, payload: Payload<'_>) {
+        let line = match serde_json::to_string(&Decoded::new(pkt, port, payload)) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::warn!(%e, node_id = pkt.from, "packet serialization failed");
+                return;
+            }
+        };
+        if let Err(e) = self.tx.try_send(line) {
+            tracing::warn!(%e, "NDJSON queue unavailable, dropping packet");
+        }
+    }
+
+    /// Closes the queue, writes the pending lines and finishes the open file
+    pub(crate) async fn shutdown(self) {
+        drop(self.tx);
+        match timeout(SHUTDOWN_TIMEOUT, self.task).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!(%e, "NDJSON writer failed"),
+            // A blocking task cannot be aborted, the open file may miss its gzip trailer
+            Err(_) => tracing::warn!("NDJSON writer did not finish in time"),
+        }
+    }
+}
+
+/// An open output file, compressed or not
+enum Output {
+    /// Plain text
+    Plain(BufWriter<File>),
+    /// Gzip stream, flushed in sync blocks so written lines survive a crash
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl Output {
+    /// The writer lines go to
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Self::Plain(w) => w,
+            Self::Gzip(w) => w,
+        }
+    }
+
+    /// Writes any buffered data and the gzip trailer
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Plain(mut w) => w.flush(),
+            Self::Gzip(w) => w.finish()?.flush(),
+        }
+    }
+}
+
+/// The file currently written
+struct Current {
+    /// Path of the file, for logging
+    path: PathBuf,
+    /// Output stream
+    out: Output,
+    /// UTC day the file was opened on
+    day: NaiveDate,
+    /// Uncompressed bytes written
+    bytes: u64,
+}
+
+/// Directory of rotated NDJSON files, named `packets-<day>.<n>.ndjson[.gz]`
+struct Archive {
+    /// Directory the files are written to
+    dir: PathBuf,
+    /// Uncompressed size after which the next line starts a new file
+    rotate_bytes: Option<u64>,
+    /// Whether a new UTC day starts a new file
+    rotate_daily: bool,
+    /// Whether files are gzip compressed
+    gzip: bool,
+    /// The file currently written
+    current: Option<Current>,
+}
+
+impl Archive {
+    /// Writes lines until the queue closes, flushing whenever it runs empty
+    fn run(mut self, mut rx: Receiver<String>) {
+        while let Some(line) = rx.blocking_recv() {
+            self.write(&line, Utc::now().date_naive());
+            while let Ok(line) = rx.try_recv() {
+                self.write(&line, Utc::now().date_naive());
+            }
+            if let Some(current) = &mut self.current
+                && let Err(e) = current.out.writer().flush()
+            {
+                tracing::error!(%e, path = %current.path.display(), "NDJSON flush failed");
+                self.close();
+            }
+        }
+        self.close();
+    }
+
+    /// Appends a line, rotating first if the current file is due
+    fn write(&mut self, line: &str, today: NaiveDate) {
+        if self.current.as_ref().is_some_and(|c| self.due(c, today)) {
+            self.close();
+        }
+        if let Err(e) = self.append(line, today) {
+            tracing::error!(%e, dir = %self.dir.display(), "NDJSON write failed, dropping packet");
+            // Start over in a new file with the next line
+            self.close();
+        }
+    }
+
+    /// Whether the current file is full or from an earlier day
+    fn due(&self, current: &Current, today: NaiveDate) -> bool {
+        (self.rotate_daily && current.day != today)
+            || self.rotate_bytes.is_some_and(|max| current.bytes >= max)
+    }
+
+    /// Writes a line to the current file, opening one if needed
+    fn append(&mut self, line: &str, today: NaiveDate) -> io::Result<()> {
+        if self.current.is_none() {
+            self.current = Some(self.open(today)?);
+        }
+        if let Some(current) = &mut self.current {
+            let out = current.out.writer();
+            out.write_all(line.as_bytes())?;
+            out.write_all(b"\n")?;
+            current.bytes += u64::try_from(line.len()).unwrap_or(u64::MAX) + 1;
+        }
+        Ok(())
+    }
+
+    /// Creates the next unused file of the day
+    fn open(&self, day: NaiveDate) -> io::Result<Current> {
+        let ext = if self.gzip { "ndjson.gz" } else { "ndjson" };
+        for n in 0_u32.. {
+            let path = self.dir.join(format!("packets-{day}.{n}.{ext}"));
+            let file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => BufWriter::new(file),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            };
+            tracing::info!(path = %path.display(), "writing packets to new NDJSON file");
+            let out = if self.gzip {
+                Output::Gzip(GzEncoder::new(file, Compression::default()))
+            } else {
+                Output::Plain(file)
+            };
+            return Ok(Current {
+                path,
+                out,
+                day,
+                bytes: 0,
+            });
+        }
+        Err(io::Error::other("no unused NDJSON file name left"))
+    }
+
+    /// Finishes the current file, if any
+    fn close(&mut self) {
+        if let Some(current) = self.current.take()
+            && let Err(e) = current.out.finish()
+        {
+            tracing::error!(%e, path = %current.path.display(), "NDJSON file not finished cleanly");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::{env::temp_dir, io::Read as _, path::Path, process};
+
+    /// Fresh directory for one test
+    fn scratch(name: &str) -> Result<PathBuf> {
+        let dir = temp_dir().join(format!("ndjson-{}-{name}", process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    fn archive(dir: &Path, rotate_bytes: Option<u64>, gzip: bool) -> Archive {
+        Archive {
+            dir: dir.to_path_buf(),
+            rotate_bytes,
+            rotate_daily: true,
+            gzip,
+            current: None,
+        }
+    }
+
+    fn day(d: u32) -> Result<NaiveDate> {
+        NaiveDate::from_ymd_opt(2026, 10, d).context("invalid date")
+    }
+
+    #[test]
+    fn rotates_by_size_and_day() -> Result<()> {
+        let dir = scratch("rotate")?;
+        let mut archive = archive(&dir, Some(10), false);
+        archive.write("{\"a\":1}", day(1)?);
+        archive.write("{\"a\":2}", day(1)?);
+        // The first file reached 16 bytes, the next line starts a new one
+        archive.write("{\"a\":3}", day(1)?);
+        archive.write("{\"a\":4}", day(2)?);
+        archive.close();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("packets-2026-10-01.0.ndjson"))?,
+            "{\"a\":1}\n{\"a\":2}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("packets-2026-10-01.1.ndjson"))?,
+            "{\"a\":3}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("packets-2026-10-02.0.ndjson"))?,
+            "{\"a\":4}\n"
+        );
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn gzip_files_decompress_and_existing_files_are_kept() -> Result<()> {
+        let dir = scratch("gzip")?;
+        fs::write(dir.join("packets-2026-10-01.0.ndjson.gz"), b"older run")?;
+        let mut archive = archive(&dir, None, true);
+        archive.write("{\"a\":1}", day(1)?);
+        archive.write("{\"a\":2}", day(1)?);
+        archive.close();
+
+        assert_eq!(
+            fs::read(dir.join("packets-2026-10-01.0.ndjson.gz"))?,
+            b"older run"
+        );
+        let mut text = String::new();
+        GzDecoder::new(File::open(dir.join("packets-2026-10-01.1.ndjson.gz"))?)
+            .read_to_string(&mut text)?;
+        assert_eq!(text, "{\"a\":1}\n{\"a\":2}\n");
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
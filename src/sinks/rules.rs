@@ -0,0 +1,260 @@
+use crate::{
+    dto::telemetry::{empty, field, kind},
+    util::config::{Comparator, RuleSettings},
+};
+use anyhow::{Context as _, Result};
+use meshtastic::protobufs::telemetry::Variant;
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    sync::{Mutex, PoisonError},
+};
+
+/// A rule crossing its threshold, or returning past its hysteresis band
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Alert {
+    /// Name of the rule
+    pub(crate) rule: String,
+    /// Node number the reading came from
+    pub(crate) node_id: u32,
+    /// Telemetry field the rule watches, e.g. `environment.temperature`
+    pub(crate) field: String,
+    /// Reading that caused the transition
+    pub(crate) value: f64,
+    /// Threshold of the rule
+    pub(crate) threshold: f64,
+    /// Whether the rule returned to normal rather than triggered
+    pub(crate) cleared: bool,
+}
+
+/// A validated threshold rule
+#[derive(Debug)]
+struct Rule {
+    /// Settings as written in the config file
+    cfg: RuleSettings,
+    /// Telemetry type, the part of `field` before the dot
+    kind: String,
+    /// Field within the variant, the part of `field` after the dot
+    name: String,
+}
+
+/// Threshold rules evaluated against incoming telemetry
+#[derive(Debug, Default)]
+pub(crate) struct Rules {
+    /// Configured rules in config file order
+    rules: Vec<Rule>,
+    /// `(rule index, node)` pairs currently past their threshold
+    active: Mutex<HashSet<(usize, u32)>>,
+}
+
+impl Comparator {
+    /// Whether a reading is past the threshold
+    fn breached(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::Above => value > threshold,
+            Self::AtLeast => value >= threshold,
+            Self::Below => value < threshold,
+            Self::AtMost => value <= threshold,
+        }
+    }
+
+    /// Whether a reading is back past the threshold by at least the hysteresis
+    fn recovered(self, value: f64, threshold: f64, hysteresis: f64) -> bool {
+        match self {
+            Self::Above | Self::AtLeast => value <= threshold - hysteresis,
+            Self::Below | Self::AtMost => value >= threshold + hysteresis,
+        }
+    }
+}
+
+impl Rules {
+    /// Validates the configured rules, failing on unknown telemetry fields
+    pub(crate) fn new(settings: &[RuleSettings]) -> Result<Self> {
+        let rules = settings
+            .iter()
+            .map(|cfg| {
+                let (kind, name) = cfg.field.split_once('.').with_context(|| {
+                    format!(
+                        "Rule {} field {} is not of the form <telemetry>.<field>",
+                        cfg.name, cfg.field
+                    )
+                })?;
+                let v = empty(kind).with_context(|| {
+                    format!("Rule {} has unknown telemetry type {kind}", cfg.name)
+                })?;
+                field(&v, name).with_context(|| format!("Invalid rule {}", cfg.name))?;
+                Ok(Rule {
+                    cfg: cfg.clone(),
+                    kind: kind.to_owned(),
+                    name: name.to_owned(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            rules,
+            active: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Evaluates every rule against a telemetry reading, returning the rules that changed state
+    pub(crate) fn evaluate(&self, node_id: u32, v: &Variant) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        if self.rules.is_empty() {
+            return alerts;
+        }
+        let mut active = self.active.lock().unwrap_or_else(PoisonError::into_inner);
+
+        for (i, rule) in self.rules.iter().enumerate() {
+            if rule.kind != kind(v)
+                || (!rule.cfg.nodes.is_empty() && !rule.cfg.nodes.contains(&node_id))
+            {
+                continue;
+            }
+            let Ok(Some(value)) = field(v, &rule.name) else {
+                continue;
+            };
+            let value = value.as_f64();
+            let cfg = &rule.cfg;
+            let cleared = if active.contains(&(i, node_id)) {
+                if !cfg
+                    .comparator
+                    .recovered(value, cfg.threshold, cfg.hysteresis)
+                {
+                    continue;
+                }
+                active.remove(&(i, node_id));
+                true
+            } else {
+                if !cfg.comparator.breached(value, cfg.threshold) {
+                    continue;
+                }
+                active.insert((i, node_id));
+                false
+            };
+            alerts.push(Alert {
+                rule: cfg.name.clone(),
+                node_id,
+                field: cfg.field.clone(),
+                value,
+                threshold: cfg.threshold,
+                cleared,
+            });
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use meshtastic::protobufs::{DeviceMetrics, EnvironmentMetrics, LocalStats};
+
+    fn rule(field: &str, comparator: Comparator, threshold: f64, hysteresis: f64) -> RuleSettings {
+        RuleSettings {
+            name: String::from("test"),
+            field: String::from(field),
+            comparator,
+            threshold,
+            hysteresis,
+            nodes: Vec::new(),
+        }
+    }
+
+    fn temperature(t: f32) -> Variant {
+        Variant::EnvironmentMetrics(EnvironmentMetrics {
+            temperature: Some(t),
+            ..Default::default()
+        })
+    }
+
+    fn rejected(field: &str) -> bool {
+        Rules::new(&[rule(field, Comparator::Above, 1.0, 0.0)]).is_err()
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert!(rejected("environment.nope"));
+        assert!(rejected("nope.temperature"));
+        assert!(rejected("temperature"));
+    }
+
+    #[test]
+    fn triggers_once_and_clears_after_hysteresis() -> Result<()> {
+        let rules = Rules::new(&[rule(
+            "environment.temperature",
+            Comparator::Above,
+            40.0,
+            2.0,
+        )])?;
+
+        assert!(rules.evaluate(1, &temperature(39.0)).is_empty());
+
+        let alerts = rules.evaluate(1, &temperature(41.0));
+        assert_eq!(alerts.len(), 1);
+        assert!(!alerts[0].cleared);
+
+        // Still hot, or back under the threshold but inside the hysteresis band
+        assert!(rules.evaluate(1, &temperature(45.0)).is_empty());
+        assert!(rules.evaluate(1, &temperature(39.0)).is_empty());
+
+        let alerts = rules.evaluate(1, &temperature(37.5));
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].cleared);
+        Ok(())
+    }
+
+    #[test]
+    fn below_comparator_and_node_scope() -> Result<()> {
+        let mut low = rule("device.battery_level", Comparator::Below, 20.0, 5.0);
+        low.nodes = vec![7];
+        let rules = Rules::new(&[low])?;
+        let battery = |level| {
+            Variant::DeviceMetrics(DeviceMetrics {
+                battery_level: Some(level),
+                ..Default::default()
+            })
+        };
+
+        // Out of scope node
+        assert!(rules.evaluate(8, &battery(5)).is_empty());
+
+        assert_eq!(rules.evaluate(7, &battery(15)).len(), 1);
+        assert!(rules.evaluate(7, &battery(22)).is_empty());
+        let alerts = rules.evaluate(7, &battery(25));
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].cleared);
+        Ok(())
+    }
+
+    #[test]
+    fn rules_only_match_their_telemetry_type() -> Result<()> {
+        let rules = Rules::new(&[rule(
+            "local_stats.channel_utilization",
+            Comparator::Above,
+            25.0,
+            0.0,
+        )])?;
+        assert!(rules.evaluate(1, &temperature(90.0)).is_empty());
+        let busy = Variant::LocalStats(LocalStats {
+            channel_utilization: 30.0,
+            ..Default::default()
+        });
+        assert_eq!(rules.evaluate(1, &busy).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn nodes_are_tracked_independently() -> Result<()> {
+        let rules = Rules::new(&[rule(
+            "environment.temperature",
+            Comparator::AtLeast,
+            40.0,
+            0.0,
+        )])?;
+        assert_eq!(rules.evaluate(1, &temperature(40.0)).len(), 1);
+        assert_eq!(rules.evaluate(2, &temperature(40.0)).len(), 1);
+        assert!(rules.evaluate(1, &temperature(41.0)).is_empty());
+        Ok(())
+    }
+}
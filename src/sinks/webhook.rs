@@ -0,0 +1,269 @@
+use crate::{
+    sinks::event::Event,
+    util::config::{DEPLOYMENT_LOCATION, WebhookSettings},
+};
+use anyhow::{Context as _, Result};
+use chrono::Utc;
+use reqwest::{Client, Response, header::CONTENT_TYPE};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    task::{JoinHandle, JoinSet},
+    time::sleep,
+};
+
+/// Number of events that may wait for delivery before new ones are dropped
+const EVENT_QUEUE: usize = 64;
+
+/// Timeout of a single webhook request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// JSON body posted to every webhook endpoint
+#[derive(Serialize)]
+struct Notification<'a> {
+    /// The event, flattened so its `event` tag sits at the top level
+    #[serde(flatten)]
+    event: &'a Event,
+    /// Deployment the daemon is running in
+    deployment_location: Option<&'a str>,
+    /// RFC 3339 time the event was dispatched
+    time: String,
+}
+
+/// Delivery settings shared by the dispatcher and its delivery tasks
+#[derive(Debug, Clone)]
+struct Delivery {
+    /// Endpoints receiving every event
+    endpoints: Vec<String>,
+    /// Retries per endpoint after a failed delivery
+    retries: u32,
+    /// Delay before the first retry
+    backoff: Duration,
+    /// Minimum time between two events with the same type, node and rule
+    rate_limit: Duration,
+}
+
+/// Webhook sink pushing alert events to HTTP endpoints from a background task
+#[derive(Debug)]
+pub(crate) struct Webhook {
+    /// Queue feeding the dispatcher task
+    tx: Sender<Event>,
+    /// The dispatcher task
+    task: JoinHandle<()>,
+}
+
+impl Webhook {
+    /// Creates the HTTP client and spawns the dispatcher task
+    pub(crate) fn new(cfg: &WebhookSettings) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to build webhook HTTP client")?;
+        let delivery = Delivery {
+            endpoints: cfg.endpoints.clone(),
+            retries: cfg.retries,
+            backoff: Duration::from_millis(cfg.backoff_ms),
+            rate_limit: Duration::from_secs(cfg.rate_limit_secs),
+        };
+        let (tx, rx) = mpsc::channel(EVENT_QUEUE);
+
+        Ok(Self {
+            tx,
+            task: tokio::spawn(dispatch(rx, client, delivery)),
+        })
+    }
+
+    /// Queues an event for delivery without waiting on the network
+    pub(crate) fn send(&self, event: Event) {
+        if let Err(e) = self.tx.try_send(event) {
+            tracing::warn!(%e, "Webhook queue unavailable, dropping event");
+        }
+    }
+
+    /// Closes the queue and waits for queued events to be delivered
+    pub(crate) async fn shutdown(self) {
+        drop(self.tx);
+        if let Err(e) = self.task.await {
+            tracing::error!(%e, "Webhook dispatcher failed");
+        }
+    }
+}
+
+/// Rate limits events by type, node and rule, then delivers them to every endpoint
+async fn dispatch(mut rx: Receiver<Event>, client: Client, delivery: Delivery) {
+    let mut last_sent: HashMap<(&'static str, Option<u32>, Option<String>), Instant> =
+        HashMap::new();
+    let mut deliveries = JoinSet::new();
+
+    while let Some(event) = rx.recv().await {
+        let key = (
+            event.kind(),
+            event.node_id(),
+            event.rule().map(str::to_owned),
+        );
+        if last_sent
+            .get(&key)
+            .is_some_and(|t| t.elapsed() < delivery.rate_limit)
+        {
+            tracing::debug!(event = key.0, node_id = key.1, "Webhook event rate limited");
+            continue;
+        }
+        last_sent.insert(key, Instant::now());
+
+        let body = match serde_json::to_vec(&Notification {
+            event: &event,
+            deployment_location: DEPLOYMENT_LOCATION.get().map(String::as_str),
+            time: Utc::now().to_rfc3339(),
+        }) {
+            Ok(b) => b,
+            Err(e) => {
+                tracing::error!(%e, event = event.kind(), "Failed to serialize webhook event");
+                continue;
+            }
+        };
+
+        for url in &delivery.endpoints {
+            deliveries.spawn(deliver(
+                client.clone(),
+                url.clone(),
+                body.clone(),
+                delivery.retries,
+                delivery.backoff,
+            ));
+        }
+        // Reap finished deliveries so the set does not grow unbounded
+        while deliveries.try_join_next().is_some() {}
+    }
+
+    while deliveries.join_next().await.is_some() {}
+}
+
+/// Posts a body to one endpoint, retrying with exponential backoff
+async fn deliver(client: Client, url: String, body: Vec<u8>, retries: u32, backoff: Duration) {
+    let mut delay = backoff;
+    for attempt in 0..=retries {
+        match client
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(Response::error_for_status)
+        {
+            Ok(_) => {
+                tracing::debug!(url, attempt, "Webhook delivered");
+                return;
+            }
+            Err(e) => tracing::warn!(%e, url, attempt, "Webhook delivery failed"),
+        }
+        if attempt < retries {
+            sleep(delay).await;
+            delay = delay.saturating_mul(2);
+        }
+    }
+    tracing::error!(
+        url,
+        "Giving up on webhook delivery after {} attempts",
+        retries + 1
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::http_stand_in::{Request, stand_in};
+    use serde_json::Value;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    fn settings(url: String, retries: u32, rate_limit_secs: u64) -> WebhookSettings {
+        WebhookSettings {
+            endpoints: vec![url],
+            retries,
+            backoff_ms: 1,
+            rate_limit_secs,
+        }
+    }
+
+    fn low_battery(node_id: u32) -> Event {
+        Event::LowBattery {
+            node_id,
+            battery_level: 5,
+            threshold: 20,
+        }
+    }
+
+    fn drain(rx: &mut UnboundedReceiver<Request>) -> Result<Vec<Value>> {
+        let mut bodies = Vec::new();
+        while let Ok(request) = rx.try_recv() {
+            bodies.push(serde_json::from_str(&request.body)?);
+        }
+        Ok(bodies)
+    }
+
+    #[tokio::test]
+    async fn delivers_event_as_json() -> Result<()> {
+        let (url, mut rx) = stand_in(vec![]).await?;
+        let webhook = Webhook::new(&settings(url, 0, 0))?;
+        webhook.send(low_battery(0x1234));
+        webhook.shutdown().await;
+
+        let bodies = drain(&mut rx)?;
+        assert_eq!(bodies.len(), 1);
+        assert_eq!(bodies[0]["event"], "low_battery");
+        assert_eq!(bodies[0]["node_id"], 0x1234);
+        assert_eq!(bodies[0]["battery_level"], 5);
+        assert!(bodies[0]["time"].is_string());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retries_failed_delivery() -> Result<()> {
+        let (url, mut rx) = stand_in(vec![500, 503]).await?;
+        let webhook = Webhook::new(&settings(url, 2, 0))?;
+        webhook.send(Event::SerialDown);
+        webhook.shutdown().await;
+
+        // Two failures and the successful third attempt all reach the stand-in
+        let bodies = drain(&mut rx)?;
+        assert_eq!(bodies.len(), 3);
+        assert!(bodies.iter().all(|b| b["event"] == "serial_down"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_retries() -> Result<()> {
+        let (url, mut rx) = stand_in(vec![500, 500, 500, 500]).await?;
+        let webhook = Webhook::new(&settings(url, 1, 0))?;
+        webhook.send(Event::SerialDown);
+        webhook.shutdown().await;
+
+        assert_eq!(drain(&mut rx)?.len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rate_limits_per_event_type_and_node() -> Result<()> {
+        let (url, mut rx) = stand_in(vec![]).await?;
+        let webhook = Webhook::new(&settings(url, 0, 3600))?;
+        webhook.send(low_battery(1));
+        webhook.send(low_battery(1));
+        webhook.send(low_battery(2));
+        webhook.send(Event::SerialDown);
+        webhook.shutdown().await;
+
+        let bodies = drain(&mut rx)?;
+        assert_eq!(bodies.len(), 3);
+        assert_eq!(
+            bodies
+                .iter()
+                .filter(|b| b["event"] == "low_battery" && b["node_id"] == 1)
+                .count(),
+            1
+        );
+        Ok(())
+    }
+}
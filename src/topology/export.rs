@@ -0,0 +1,418 @@
+use crate::{
+    topology::Graph,
+    util::{config::Settings, parse::time},
+};
+use anyhow::{Context as _, Error, Result, bail};
+use chrono::{NaiveDateTime, Utc};
+use serde_json::{Value, from_value, json, to_string_pretty};
+use sqlx::{Pool, Postgres, postgres::types::Oid, query_as};
+use std::{
+    collections::HashMap,
+    env,
+    fmt::{self, Write as _},
+};
+
+/// Usage of the subcommand, shown with argument errors
+const USAGE: &str = "usage: meshtastic-telemetry-daemon-rs topology [--at <time>] \
+[--format graphml|dot|json] [--location <deployment>]
+prints the latest snapshot taken at or before --at, times are Unix seconds, RFC 3339 or \
+YYYY-MM-DD in UTC";
+
+/// Graph file formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// `GraphML`, for Gephi, yEd or `NetworkX`
+    GraphMl,
+    /// Graphviz DOT
+    Dot,
+    /// Nodes and links as JSON, for web front ends
+    Json,
+}
+
+impl Format {
+    /// Parses a format name
+    fn parse(s: &str) -> Result<Self> {
+        match s {
+            "graphml" => Ok(Self::GraphMl),
+            "dot" => Ok(Self::Dot),
+            "json" => Ok(Self::Json),
+            other => bail!("unknown format {other}, expected graphml, dot or json"),
+        }
+    }
+}
+
+/// Arguments of the `topology` subcommand
+#[derive(Debug)]
+pub(crate) struct Args {
+    /// Time the snapshot is taken at or before
+    at: NaiveDateTime,
+    /// Output format
+    format: Format,
+    /// Deployment to export, the configured one if unset
+    location: Option<String>,
+}
+
+impl Args {
+    /// Parses the command line if the daemon was started as `topology`
+    pub(crate) fn from_env() -> Result<Option<Self>> {
+        let mut argv = env::args().skip(1);
+        if argv.next().as_deref() != Some("topology") {
+            return Ok(None);
+        }
+        Self::parse(argv)
+            .map(Some)
+            .with_context(|| format!("Invalid topology arguments\n{USAGE}"))
+    }
+
+    /// Parses the arguments following `topology`
+    fn parse(mut argv: impl Iterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self {
+            at: Utc::now().naive_utc(),
+            format: Format::GraphMl,
+            location: None,
+        };
+        while let Some(flag) = argv.next() {
+            let value = argv
+                .next()
+                .with_context(|| format!("{flag} needs a value"))?;
+            match flag.as_str() {
+                "--at" => parsed.at = time(&value)?,
+                "--format" => parsed.format = Format::parse(&value)?,
+                "--location" => parsed.location = Some(value),
+                other => bail!("unknown argument {other}"),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// A row of the `TopologySnapshots` table
+#[derive(Debug)]
+struct Snapshot {
+    /// Time the snapshot was taken
+    time: NaiveDateTime,
+    /// The stored [`Graph`]
+    graph: Value,
+}
+
+/// Names of a node from the `NodeInfo` table
+#[derive(Debug)]
+struct Names {
+    /// Node number
+    node_id: Oid,
+    /// Long name of the node
+    longname: String,
+    /// Short name of the node
+    shortname: String,
+}
+
+/// A snapshot with the names of its nodes
+#[derive(Debug)]
+struct Mesh {
+    /// Deployment the snapshot was taken in
+    location: String,
+    /// Time the snapshot was taken
+    time: NaiveDateTime,
+    /// Links of the snapshot
+    graph: Graph,
+    /// Long and short names by node number, for the nodes with a `NodeInfo` row
+    names: HashMap<u32, (String, String)>,
+}
+
+/// Node id in its `!` prefixed hex form
+fn hex_id(node_id: u32) -> String {
+    format!("!{node_id:08x}")
+}
+
+impl Mesh {
+    /// Long name of a node, its id if it has none
+    fn label(&self, node_id: u32) -> String {
+        self.names
+            .get(&node_id)
+            .map(|(long, _)| long.as_str())
+            .filter(|long| !long.is_empty())
+            .map_or_else(|| hex_id(node_id), String::from)
+    }
+
+    /// Writes the graph in `format`
+    fn render(&self, format: Format, out: &mut String) -> Result<()> {
+        match format {
+            Format::GraphMl => self.graphml(out)?,
+            Format::Dot => self.dot(out)?,
+            Format::Json => self.json(out)?,
+        }
+        Ok(())
+    }
+
+    /// Writes a directed `GraphML` graph, names as node data and SNR and time as edge data
+    fn graphml(&self, out: &mut String) -> fmt::Result {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            out,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        for (id, on, kind) in [
+            ("long_name", "node", "string"),
+            ("short_name", "node", "string"),
+            ("snr", "edge", "float"),
+            ("time", "edge", "long"),
+        ] {
+            writeln!(
+                out,
+                r#"  <key id="{id}" for="{on}" attr.name="{id}" attr.type="{kind}"/>"#
+            )?;
+        }
+        writeln!(
+            out,
+            r#"  <graph id="{}" edgedefault="directed">"#,
+            xml_escape(&format!("{} {}", self.location, self.time))
+        )?;
+        for node_id in self.graph.nodes() {
+            write!(out, r#"    <node id="{}">"#, hex_id(node_id))?;
+            if let Some((long, short)) = self.names.get(&node_id) {
+                write!(
+                    out,
+                    r#"<data key="long_name">{}</data><data key="short_name">{}</data>"#,
+                    xml_escape(long),
+                    xml_escape(short)
+                )?;
+            }
+            writeln!(out, "</node>")?;
+        }
+        for l in &self.graph.links {
+            writeln!(
+                out,
+                r#"    <edge source="{}" target="{}"><data key="snr">{}</data><data key="time">{}</data></edge>"#,
+                hex_id(l.reporter),
+                hex_id(l.neighbor),
+                l.snr,
+                l.time
+            )?;
+        }
+        writeln!(out, "  </graph>")?;
+        writeln!(out, "</graphml>")
+    }
+
+    /// Writes a Graphviz digraph labelled with the long names and the SNR of the links
+    fn dot(&self, out: &mut String) -> fmt::Result {
+        writeln!(out, "digraph mesh {{")?;
+        writeln!(
+            out,
+            "  label=\"{}\";",
+            dot_escape(&format!("{} {}", self.location, self.time))
+        )?;
+        for node_id in self.graph.nodes() {
+            writeln!(
+                out,
+                "  \"{}\" [label=\"{}\"];",
+                hex_id(node_id),
+                dot_escape(&self.label(node_id))
+            )?;
+        }
+        for l in &self.graph.links {
+            writeln!(
+                out,
+                "  \"{}\" -> \"{}\" [label=\"{} dB\"];",
+                hex_id(l.reporter),
+                hex_id(l.neighbor),
+                l.snr
+            )?;
+        }
+        writeln!(out, "}}")
+    }
+
+    /// Writes the nodes and links as a JSON object
+    fn json(&self, out: &mut String) -> Result<()> {
+        let nodes: Vec<Value> = self
+            .graph
+            .nodes()
+            .into_iter()
+            .map(|node_id| {
+                let names = self.names.get(&node_id);
+                json!({
+                    "id": hex_id(node_id),
+                    "num": node_id,
+                    "long_name": names.map(|(long, _)| long),
+                    "short_name": names.map(|(_, short)| short),
+                })
+            })
+            .collect();
+        let links: Vec<Value> = self
+            .graph
+            .links
+            .iter()
+            .map(|l| {
+                json!({
+                    "source": hex_id(l.reporter),
+                    "target": hex_id(l.neighbor),
+                    "snr": l.snr,
+                    "time": l.time,
+                })
+            })
+            .collect();
+        let doc = json!({
+            "location": self.location,
+            "time": self.time.and_utc().timestamp(),
+            "nodes": nodes,
+            "links": links,
+        });
+        writeln!(out, "{}", to_string_pretty(&doc)?)?;
+        Ok(())
+    }
+}
+
+/// Escapes text for XML attributes and content
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Escapes text for a quoted DOT string
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Latest snapshot of a deployment at or before `at`
+async fn snapshot(
+    location: &str,
+    at: NaiveDateTime,
+    pool: &Pool<Postgres>,
+) -> Result<Option<Snapshot>, Error> {
+    query_as!(
+        Snapshot,
+        "
+SELECT time, graph
+FROM TopologySnapshots
+WHERE deployment_location = $1 AND time <= $2
+ORDER BY time DESC
+LIMIT 1
+        ",
+        location,
+        at,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select row from TopologySnapshots table")
+}
+
+/// Names of the nodes of a graph
+async fn names(graph: &Graph, pool: &Pool<Postgres>) -> Result<Vec<Names>, Error> {
+    let nodes = graph.nodes().into_iter().map(Oid).collect::<Vec<_>>();
+    query_as!(
+        Names,
+        "
+SELECT node_id, longname, shortname
+FROM NodeInfo
+WHERE node_id = ANY($1)
+        ",
+        &nodes,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(Error::from)
+    .context("Failed to select rows from NodeInfo table")
+}
+
+/// Prints the latest topology snapshot of the deployment at or before the given time
+pub(crate) async fn run(settings: &Settings, args: Args) -> Result<()> {
+    let pool = settings
+        .setup_postgres()
+        .await
+        .context("Failed to connect to postgresql database")?;
+    let location = args
+        .location
+        .unwrap_or_else(|| settings.deployment.location.clone());
+    let Some(row) = snapshot(&location, args.at, &pool).await? else {
+        pool.close().await;
+        bail!(
+            "No topology snapshot for {location} at or before {}",
+            args.at
+        );
+    };
+    let graph: Graph = from_value(row.graph).context("Invalid graph in TopologySnapshots row")?;
+    let names = names(&graph, &pool).await?;
+    pool.close().await;
+
+    let mesh = Mesh {
+        location,
+        time: row.time,
+        graph,
+        names: names
+            .into_iter()
+            .map(|n| (n.node_id.0, (n.longname, n.shortname)))
+            .collect(),
+    };
+    let mut out = String::new();
+    mesh.render(args.format, &mut out)?;
+    print!("{out}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::Link;
+    use chrono::DateTime;
+
+    fn rejected(args: &[&str]) -> bool {
+        Args::parse(args.iter().map(|a| String::from(*a))).is_err()
+    }
+
+    fn mesh() -> Result<Mesh> {
+        let link = |reporter, neighbor| Link {
+            reporter,
+            neighbor,
+            snr: 6.25,
+            time: 1_760_000_000,
+        };
+        Ok(Mesh {
+            location: String::from("Lab"),
+            time: DateTime::from_timestamp(1_760_000_060, 0)
+                .context("out of range")?
+                .naive_utc(),
+            graph: Graph {
+                links: vec![link(1, 2), link(2, 1)],
+            },
+            names: HashMap::from([(1, (String::from("Roof \"A\" <1>"), String::from("R&A")))]),
+        })
+    }
+
+    #[test]
+    fn arguments_are_validated() -> Result<()> {
+        assert!(rejected(&["--format", "svg"]));
+        assert!(rejected(&["--at"]));
+        assert!(rejected(&["--nodes", "1"]));
+        let args = Args::parse(["--format", "dot"].into_iter().map(String::from))?;
+        assert_eq!(args.format, Format::Dot);
+        Ok(())
+    }
+
+    #[test]
+    fn names_are_escaped() -> Result<()> {
+        let mesh = mesh()?;
+        let mut out = String::new();
+        mesh.render(Format::GraphMl, &mut out)?;
+        assert!(out.contains(
+            r#"<node id="!00000001"><data key="long_name">Roof &quot;A&quot; &lt;1&gt;</data><data key="short_name">R&amp;A</data></node>"#
+        ));
+        assert!(out.contains(r#"<node id="!00000002"></node>"#));
+        assert!(out.contains(r#"<edge source="!00000002" target="!00000001">"#));
+
+        out.clear();
+        mesh.render(Format::Dot, &mut out)?;
+        assert!(out.contains(r#"  "!00000001" [label="Roof \"A\" <1>"];"#));
+        assert!(out.contains(r#"  "!00000002" [label="!00000002"];"#));
+        assert!(out.contains(r#"  "!00000001" -> "!00000002" [label="6.25 dB"];"#));
+
+        out.clear();
+        mesh.render(Format::Json, &mut out)?;
+        let doc: Value = serde_json::from_str(&out)?;
+        assert_eq!(doc["nodes"][1]["long_name"], Value::Null);
+        assert_eq!(doc["links"][0]["source"], "!00000001");
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{
+    dto::dbops::{neighborinfo, topologysnapshots},
+    util::state::GatewayState,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time::{MissedTickBehavior, interval};
+
+/// The `topology` subcommand writing a stored snapshot as a graph file
+pub(crate) mod export;
+
+/// A neighbor a node reported hearing
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Link {
+    /// Node that reported the neighbor
+    pub(crate) reporter: u32,
+    /// Node it heard
+    pub(crate) neighbor: u32,
+    /// SNR the reporter heard the neighbor with
+    pub(crate) snr: f32,
+    /// Unix time of the report
+    pub(crate) time: i64,
+}
+
+/// Links of the mesh at one point in time, stored as the graph of a `TopologySnapshots` row
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Graph {
+    /// Links ordered by reporter and neighbor
+    pub(crate) links: Vec<Link>,
+}
+
+impl Graph {
+    /// Nodes at either end of a link, ascending
+    pub(crate) fn nodes(&self) -> BTreeSet<u32> {
+        self.links
+            .iter()
+            .flat_map(|l| [l.reporter, l.neighbor])
+            .collect()
+    }
+}
+
+/// The latest `NeighborInfo` report of every node
+#[derive(Debug, Default)]
+pub(crate) struct Topology {
+    /// Unix time and neighbors of the latest report, by reporter
+    reports: HashMap<u32, (i64, Vec<Link>)>,
+}
+
+impl Topology {
+    /// Keeps the links of a report unless a newer one of the same node is already kept
+    pub(crate) fn report(&mut self, reporter: u32, time: i64, links: Vec<Link>) {
+        if self
+            .reports
+            .get(&reporter)
+            .is_none_or(|(kept, _)| *kept <= time)
+        {
+            self.reports.insert(reporter, (time, links));
+        }
+    }
+
+    /// Links of the reports since `cutoff`, forgetting older ones
+    pub(crate) fn graph(&mut self, cutoff: i64) -> Graph {
+        self.reports.retain(|_, (time, _)| *time >= cutoff);
+        let mut links: Vec<Link> = self
+            .reports
+            .values()
+            .flat_map(|(_, links)| links.iter().copied())
+            .collect();
+        links.sort_unstable_by_key(|l| (l.reporter, l.neighbor));
+        Graph { links }
+    }
+}
+
+/// Periodically records the links of recent neighbor reports as a `TopologySnapshots` row
+///
+/// The reports of the last `max_age` stored in `NeighborReports` are loaded first, nodes only
+/// report their neighbors every few hours.
+pub(crate) async fn snapshot(
+    state: Arc<GatewayState>,
+    pool: Pool<Postgres>,
+    every: Duration,
+    max_age: Duration,
+) {
+    let max_age = i64::try_from(max_age.as_secs()).unwrap_or(i64::MAX);
+    let cutoff = |now: DateTime<Utc>| now.timestamp().saturating_sub(max_age);
+    let since = DateTime::from_timestamp(cutoff(Utc::now()), 0).unwrap_or_default();
+    match neighborinfo::latest_reports(since.naive_utc(), &pool).await {
+        Ok(reports) => {
+            tracing::info!(table = "NeighborReports", rows = reports.len(), "loaded");
+            for (reporter, time, links) in reports {
+                state.neighbor_report(reporter, time, links);
+            }
+        }
+        Err(e) => {
+            state.db_error();
+            tracing::error!(%e, table = "NeighborReports", "select failed");
+        }
+    }
+
+    let mut ticker = interval(every);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let now = Utc::now();
+        let graph = state.topology(cutoff(now));
+        if graph.links.is_empty() {
+            continue;
+        }
+        match topologysnapshots::insert(now.naive_utc(), &graph, &pool).await {
+            Ok(_) => tracing::info!(
+                table = "TopologySnapshots",
+                links = graph.links.len(),
+                "inserted 1 row"
+            ),
+            Err(e) => {
+                state.db_error();
+                tracing::error!(%e, table = "TopologySnapshots", "insert failed");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(reporter: u32, neighbor: u32, time: i64) -> Link {
+        Link {
+            reporter,
+            neighbor,
+            snr: 6.25,
+            time,
+        }
+    }
+
+    #[test]
+    fn newest_report_of_a_node_is_kept() {
+        let mut topology = Topology::default();
+        topology.report(1, 100, vec![link(1, 2, 100), link(1, 3, 100)]);
+        topology.report(1, 50, vec![link(1, 4, 50)]);
+        assert_eq!(topology.graph(0).links, [link(1, 2, 100), link(1, 3, 100)]);
+        topology.report(1, 200, vec![link(1, 3, 200)]);
+        assert_eq!(topology.graph(0).links, [link(1, 3, 200)]);
+    }
+
+    #[test]
+    fn stale_reports_are_dropped() {
+        let mut topology = Topology::default();
+        topology.report(2, 100, vec![link(2, 1, 100)]);
+        topology.report(1, 300, vec![link(1, 2, 300)]);
+        let graph = topology.graph(200);
+        assert_eq!(graph.links, [link(1, 2, 300)]);
+        assert_eq!(graph.nodes().into_iter().collect::<Vec<_>>(), [1, 2]);
+        topology.report(2, 150, vec![link(2, 1, 150)]);
+        assert_eq!(topology.graph(200).links, [link(1, 2, 300)]);
+    }
+}
@@ -1,12 +1,15 @@
+use crate::util::MAX_INFLIGHT_TASKS;
 use anyhow::{Context as _, Result, anyhow};
 use config::Config;
 use meshtastic::utils::stream::available_serial_ports;
 use microxdg::XdgApp;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, de};
 use sqlx::{
     PgPool,
     postgres::{PgConnectOptions, PgPoolOptions},
 };
+#[cfg(feature = "mqtt-ingest")]
+use std::collections::HashMap;
 use std::{
     fs,
     io::{self, BufRead as _},
@@ -17,6 +20,9 @@ use std::{
 /// Deployment location constant to initialize with config value
 pub(crate) static DEPLOYMENT_LOCATION: OnceLock<String> = OnceLock::new();
 
+/// Range-test mode config, set if range-test messages are to be recorded
+pub(crate) static RANGE_TEST: OnceLock<RangeTestSettings> = OnceLock::new();
+
 /// Example config file to write in case one cannot be found
 static EXAMPLE_CONFIG: &[u8] = include_bytes!("example_config.toml");
 
@@ -77,15 +83,337 @@ pub(crate) struct DeploymentSettings {
     pub location: String,
 }
 
+/// Struct representing webhook notification settings
+#[cfg(feature = "webhook")]
+#[derive(Debug, Deserialize)]
+pub(crate) struct WebhookSettings {
+    /// URLs that receive a JSON `POST` for every alert event
+    pub(crate) endpoints: Vec<String>,
+    /// Number of retries per endpoint after a failed delivery
+    pub(crate) retries: u32,
+    /// Delay before the first retry in milliseconds, doubled after every further failure
+    pub(crate) backoff_ms: u64,
+    /// Minimum number of seconds between two events of the same type about the same node
+    pub(crate) rate_limit_secs: u64,
+}
+
+/// Struct representing the connection to an MQTT broker
+#[cfg(feature = "mqtt")]
+#[derive(Debug, Deserialize)]
+pub(crate) struct MqttBroker {
+    /// Hostname or IP address of the broker
+    pub(crate) host: String,
+    /// Port of the broker, usually 1883 or 8883 with TLS
+    pub(crate) port: u16,
+    /// Client id presented to the broker
+    pub(crate) client_id: String,
+    /// Whether to connect over TLS
+    #[serde(default)]
+    pub(crate) tls: bool,
+    /// PEM file with the CA certificate of the broker, the system roots if left out
+    pub(crate) ca_file: Option<String>,
+    /// Optional username for the broker
+    pub(crate) username: Option<String>,
+    /// Optional password for the broker
+    pub(crate) password: Option<String>,
+}
+
+/// Struct representing MQTT publishing settings
+#[cfg(feature = "mqtt")]
+#[derive(Debug, Deserialize)]
+pub(crate) struct MqttSettings {
+    /// The broker connection
+    #[serde(flatten)]
+    pub(crate) broker: MqttBroker,
+    /// First topic level, readings go to `<prefix>/<location>/<node_id>/<type>`
+    pub(crate) topic_prefix: String,
+    /// Quality of service of every publish, 0, 1 or 2
+    pub(crate) qos: u8,
+    /// Whether the broker keeps the last reading of every topic for new subscribers
+    pub(crate) retain: bool,
+}
+
+/// Struct representing settings for ingesting Meshtastic MQTT uplink traffic
+#[cfg(feature = "mqtt-ingest")]
+#[derive(Debug, Deserialize)]
+pub(crate) struct MqttIngestSettings {
+    /// The broker connection
+    #[serde(flatten)]
+    pub(crate) broker: MqttBroker,
+    /// Topic filters to subscribe to
+    #[serde(default = "default_ingest_topics")]
+    pub(crate) topics: Vec<String>,
+    /// Names of the channels to ingest, mapped to their base64 encoded PSK
+    pub(crate) channels: HashMap<String, String>,
+}
+
+/// Topic filter covering every Meshtastic uplink on a broker
+#[cfg(feature = "mqtt-ingest")]
+fn default_ingest_topics() -> Vec<String> {
+    vec![String::from("msh/#")]
+}
+
+/// Struct representing `InfluxDB` line protocol output settings
+#[cfg(feature = "influxdb")]
+#[derive(Debug, Deserialize)]
+pub(crate) struct InfluxSettings {
+    /// Base URL of the server, writes go to `<url>/api/v2/write`
+    pub(crate) url: String,
+    /// Bucket receiving the points, `<database>/<retention policy>` on `InfluxDB` 1.x
+    pub(crate) bucket: String,
+    /// Organization owning the bucket, left out for `InfluxDB` 1.x and `VictoriaMetrics`
+    pub(crate) org: Option<String>,
+    /// API token, `<user>:<password>` on `InfluxDB` 1.x
+    pub(crate) token: Option<String>,
+    /// Number of points written per request
+    pub(crate) batch_size: usize,
+    /// Seconds after which a partial batch is written anyway
+    pub(crate) flush_interval_secs: u64,
+    /// Whether `InfluxDB` replaces `PostgreSQL`, the daemon then runs without a database
+    #[serde(default)]
+    pub(crate) replace_postgres: bool,
+}
+
+/// Struct representing the newline-delimited JSON archive settings
+#[cfg(feature = "ndjson")]
+#[derive(Debug, Deserialize)]
+pub(crate) struct NdjsonSettings {
+    /// Directory the files are written to, created if missing
+    pub(crate) dir: String,
+    /// Uncompressed size in bytes after which a new file is started
+    pub(crate) rotate_bytes: Option<u64>,
+    /// Whether a new file is started every UTC day
+    #[serde(default = "default_true")]
+    pub(crate) rotate_daily: bool,
+    /// Whether files are gzip compressed
+    #[serde(default)]
+    pub(crate) gzip: bool,
+    /// Whether the files replace `PostgreSQL`, the daemon then runs without a database
+    #[serde(default)]
+    pub(crate) replace_postgres: bool,
+}
+
+/// Serde default for settings that are on unless turned off
+#[cfg(feature = "ndjson")]
+const fn default_true() -> bool {
+    true
+}
+
+/// Struct representing the embedded HTTP API settings
+#[cfg(feature = "api")]
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApiSettings {
+    /// Address and port to listen on, e.g. `0.0.0.0:8080`
+    pub(crate) bind: String,
+}
+
+/// Struct representing the thresholds that raise alert events
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub(crate) struct AlertSettings {
+    /// Battery percentage below which a `DeviceMetrics` packet raises an event
+    pub(crate) battery_threshold: Option<u32>,
+    /// Seconds without a packet after which a known node is reported offline
+    pub(crate) offline_after_secs: u64,
+    /// Seconds between node liveness and database health checks
+    pub(crate) check_interval_secs: u64,
+    /// Whether detection sensor and alert messages raise events
+    #[serde(default)]
+    pub(crate) sensor_events: bool,
+}
+
+/// Struct representing the gateway's periodic report on itself
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct GatewayStatsSettings {
+    /// Seconds between two `GatewayStats` rows
+    pub(crate) interval_secs: u64,
+    /// Path on the filesystem whose free space is reported
+    #[serde(default = "default_disk_path")]
+    pub(crate) disk_path: String,
+}
+
+/// The root filesystem, usually the SD card or eMMC of the gateway
+fn default_disk_path() -> String {
+    String::from("/")
+}
+
+/// Struct representing the periodic snapshots of the mesh topology
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub(crate) struct TopologySettings {
+    /// Seconds between two `TopologySnapshots` rows
+    pub(crate) interval_secs: u64,
+    /// Seconds after which a node's neighbor report is left out of the snapshots
+    #[serde(default = "default_topology_max_age_secs")]
+    pub(crate) max_age_secs: u64,
+}
+
+/// Twice the shortest neighbor report interval the firmware allows
+const fn default_topology_max_age_secs() -> u64 {
+    2 * 4 * 60 * 60
+}
+
+/// Struct representing the range-test mode
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub(crate) struct RangeTestSettings {
+    /// Latitude of the gateway in degrees, the serial node's last position if left out
+    pub(crate) latitude: Option<f64>,
+    /// Longitude of the gateway in degrees, the serial node's last position if left out
+    pub(crate) longitude: Option<f64>,
+}
+
+/// Comparison a threshold rule applies between a reading and its threshold
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub(crate) enum Comparator {
+    /// Triggers when the reading is greater than the threshold
+    #[serde(rename = ">")]
+    Above,
+    /// Triggers when the reading is greater than or equal to the threshold
+    #[serde(rename = ">=")]
+    AtLeast,
+    /// Triggers when the reading is less than the threshold
+    #[serde(rename = "<")]
+    Below,
+    /// Triggers when the reading is less than or equal to the threshold
+    #[serde(rename = "<=")]
+    AtMost,
+}
+
+/// Struct representing a threshold rule over incoming telemetry
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RuleSettings {
+    /// Name identifying the rule in events and the `Alerts` table
+    pub(crate) name: String,
+    /// Telemetry field as `<telemetry>.<field>`, e.g. `environment.temperature`
+    pub(crate) field: String,
+    /// Comparison between the reading and the threshold
+    pub(crate) comparator: Comparator,
+    /// Value the reading is compared against
+    pub(crate) threshold: f64,
+    /// Distance back past the threshold a reading must reach before the rule clears
+    #[serde(default)]
+    pub(crate) hysteresis: f64,
+    /// Node numbers the rule applies to, every node if empty
+    #[serde(default)]
+    pub(crate) nodes: Vec<u32>,
+}
+
+/// What a scheduled request asks a node to send back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RequestKind {
+    /// `DeviceMetrics` telemetry
+    Device,
+    /// `EnvironmentMetrics` telemetry
+    Environment,
+    /// `LocalStats` telemetry
+    LocalStats,
+    /// The node's position
+    Position,
+    /// The route to the node and back, only sent by `[requests.traceroute]`
+    #[serde(skip_deserializing)]
+    Traceroute,
+}
+
+/// Struct representing requests sent to a set of nodes on a fixed interval
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ScheduleSettings {
+    /// Node numbers the requests are sent to
+    pub(crate) nodes: Vec<u32>,
+    /// What each node is asked for
+    pub(crate) requests: Vec<RequestKind>,
+    /// Seconds between two rounds, rounds start on multiples of this since the Unix epoch
+    pub(crate) every_secs: u64,
+    /// Seconds the rounds are shifted by, e.g. 300 with hourly rounds sends at five past
+    #[serde(default)]
+    pub(crate) offset_secs: u64,
+}
+
+/// Struct representing traceroutes sent to one node after the other
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct TracerouteSettings {
+    /// Seconds between two traceroutes, aligned like the schedule rounds
+    pub(crate) every_secs: u64,
+    /// Node numbers probed in turn, every known node if empty
+    #[serde(default)]
+    pub(crate) nodes: Vec<u32>,
+}
+
+/// Struct representing the requests the gateway sends to nodes and its airtime limits
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RequestSettings {
+    /// Channel index the requests are sent on, must be 0 as replies are only decoded there
+    #[serde(default)]
+    pub(crate) channel: u32,
+    /// Minimum number of seconds between two requests
+    pub(crate) min_gap_secs: u64,
+    /// Most requests sent within any hour
+    pub(crate) max_per_hour: u32,
+    /// Requests and the nodes they go to
+    #[serde(default)]
+    pub(crate) schedule: Vec<ScheduleSettings>,
+    /// Traceroutes probing the mesh, sharing the airtime limits
+    pub(crate) traceroute: Option<TracerouteSettings>,
+}
+
 /// Settings struct that parses a config and sets up
 #[derive(Debug, Deserialize)]
+#[serde(remote = "Self")]
 pub(crate) struct Settings {
-    /// The Postgres connection config
-    postgres: PostgresConnection,
+    /// The Postgres connection config, left out when another output replaces the database
+    postgres: Option<PostgresConnection>,
     /// The serial connection to a Meshtastic node config
     serial: SerialConnection,
     /// The deployment config
     pub(crate) deployment: DeploymentSettings,
+    /// The optional alert thresholds config
+    pub(crate) alerts: Option<AlertSettings>,
+    /// Threshold rules evaluated against incoming telemetry
+    #[serde(default)]
+    pub(crate) rules: Vec<RuleSettings>,
+    /// The optional scheduled requests config
+    pub(crate) requests: Option<RequestSettings>,
+    /// The optional gateway self-report config
+    pub(crate) gateway_stats: Option<GatewayStatsSettings>,
+    /// The optional range-test mode config
+    pub(crate) range_test: Option<RangeTestSettings>,
+    /// The optional topology snapshot config
+    pub(crate) topology: Option<TopologySettings>,
+    /// The optional webhook notification config
+    #[cfg(feature = "webhook")]
+    pub(crate) webhook: Option<WebhookSettings>,
+    /// The optional MQTT publishing config
+    #[cfg(feature = "mqtt")]
+    pub(crate) mqtt: Option<MqttSettings>,
+    /// The optional MQTT uplink ingest config
+    #[cfg(feature = "mqtt-ingest")]
+    pub(crate) mqtt_ingest: Option<MqttIngestSettings>,
+    /// The optional `InfluxDB` output config
+    #[cfg(feature = "influxdb")]
+    pub(crate) influxdb: Option<InfluxSettings>,
+    /// The optional HTTP API config
+    #[cfg(feature = "api")]
+    pub(crate) api: Option<ApiSettings>,
+    /// The optional NDJSON archive config
+    #[cfg(feature = "ndjson")]
+    pub(crate) ndjson: Option<NdjsonSettings>,
+}
+
+impl<'de> Deserialize<'de> for Settings {
+    /// Parses the settings, requiring `[postgres]` unless another output replaces the database,
+    /// and requests on channel 0, the only channel whose replies are decoded
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let settings = Self::deserialize(deserializer)?;
+        if settings.postgres.is_none() && !settings.replaces_postgres() {
+            return Err(de::Error::missing_field("postgres"));
+        }
+        if let Some(channel) = settings.requests.as_ref().map(|r| r.channel)
+            && channel != 0
+        {
+            return Err(de::Error::custom(format!(
+                "requests.channel is {channel}, replies are only decoded on channel 0"
+            )));
+        }
+        Ok(settings)
+    }
 }
 
 impl Settings {
@@ -173,12 +501,51 @@ impl Settings {
 
     /// Sets up a Postgres connection
     pub(crate) async fn setup_postgres(&self) -> Result<PgPool> {
-        self.postgres.setup().await
+        self.postgres
+            .as_ref()
+            .context("The config has no [postgres] section")?
+            .setup()
+            .await
+    }
+
+    /// Whether `InfluxDB` or the NDJSON archive replaces `PostgreSQL`
+    #[cfg_attr(
+        not(any(feature = "influxdb", feature = "ndjson")),
+        expect(
+            clippy::unused_self,
+            clippy::missing_const_for_fn,
+            reason = "InfluxDB or the NDJSON files may replace the database when enabled"
+        )
+    )]
+    pub(crate) fn replaces_postgres(&self) -> bool {
+        #[cfg(feature = "influxdb")]
+        if self.influxdb.as_ref().is_some_and(|i| i.replace_postgres) {
+            return true;
+        }
+        #[cfg(feature = "ndjson")]
+        if self.ndjson.as_ref().is_some_and(|n| n.replace_postgres) {
+            return true;
+        }
+        false
+    }
+
+    /// Sets up the daemon's Postgres connection, unless another output replaces the database
+    pub(crate) async fn setup_daemon_postgres(&self) -> Result<Option<PgPool>> {
+        if self.replaces_postgres() {
+            tracing::warn!("Not connecting to PostgreSQL, another output replaces it");
+            return Ok(None);
+        }
+        self.setup_postgres().await.map(Some)
     }
 
     /// Get the maximum connections value to bound in-flight tasks for received packets
+    ///
+    /// Without a database the tasks only share the in-flight bound itself.
     pub(crate) const fn get_max_connections(&self) -> usize {
-        self.postgres.max_connections as usize
+        match &self.postgres {
+            Some(postgres) => postgres.max_connections as usize,
+            None => MAX_INFLIGHT_TASKS,
+        }
     }
 }
 
@@ -213,13 +580,17 @@ mod tests {
         let settings: Settings = config.try_deserialize()?; // Using `?` here too
 
         // Assert Postgres configurations
-        assert_eq!(settings.postgres.user, "test_user");
-        assert_eq!(settings.postgres.password, "test_password");
-        assert_eq!(settings.postgres.port, 5432);
-        assert_eq!(settings.postgres.host, "127.0.0.1");
-        assert_eq!(settings.postgres.dbname, "test_db");
-        assert_eq!(settings.postgres.max_connections, 20);
-        assert_eq!(settings.postgres.min_connections, 2);
+        let postgres = settings
+            .postgres
+            .as_ref()
+            .context("postgres section should be parsed")?;
+        assert_eq!(postgres.user, "test_user");
+        assert_eq!(postgres.password, "test_password");
+        assert_eq!(postgres.port, 5432);
+        assert_eq!(postgres.host, "127.0.0.1");
+        assert_eq!(postgres.dbname, "test_db");
+        assert_eq!(postgres.max_connections, 20);
+        assert_eq!(postgres.min_connections, 2);
 
         // Assert Serial configurations
         assert_eq!(settings.serial.port, "/dev/ttyUSB0");
@@ -313,4 +684,468 @@ mod tests {
         assert!(settings.is_err(), "Should fail when port is not an integer");
         Ok(())
     }
+
+    /// Valid `[postgres]`, `[serial]` and `[deployment]` sections followed by `sections`
+    fn base_toml(sections: &str) -> String {
+        format!(
+            r#"
+            [postgres]
+            user = "test_user"
+            password = "test_password"
+            port = 5432
+            host = "127.0.0.1"
+            dbname = "test_db"
+            max_connections = 20
+            min_connections = 2
+
+            [serial]
+            port = "/dev/ttyUSB0"
+
+            [deployment]
+            location = "Portland Gateway"
+{sections}"#
+        )
+    }
+
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn test_deserialize_settings_webhook() -> Result<()> {
+        let toml_content = base_toml(
+            r#"
+            [alerts]
+            battery_threshold = 20
+            offline_after_secs = 3600
+            check_interval_secs = 60
+            sensor_events = true
+
+            [webhook]
+            endpoints = ["http://127.0.0.1:8080/hook", "https://example.org/alerts"]
+            retries = 3
+            backoff_ms = 500
+            rate_limit_secs = 600
+        "#,
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        let webhook = settings
+            .webhook
+            .context("webhook section should be parsed")?;
+        assert_eq!(webhook.endpoints.len(), 2);
+        assert_eq!(webhook.retries, 3);
+        let alerts = settings.alerts.context("alerts section should be parsed")?;
+        assert_eq!(alerts.battery_threshold, Some(20));
+        assert_eq!(alerts.offline_after_secs, 3600);
+        assert!(alerts.sensor_events);
+        Ok(())
+    }
+
+    #[cfg(feature = "mqtt")]
+    #[test]
+    fn test_deserialize_settings_mqtt() -> Result<()> {
+        let toml_content = base_toml(
+            r#"
+            [mqtt]
+            host = "broker.local"
+            port = 8883
+            client_id = "gateway-1"
+            topic_prefix = "meshtastic"
+            qos = 1
+            retain = true
+            tls = true
+            username = "gateway"
+            password = "secret"
+        "#,
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        let mqtt = settings.mqtt.context("mqtt section should be parsed")?;
+        assert_eq!(mqtt.broker.port, 8883);
+        assert_eq!(mqtt.qos, 1);
+        assert!(mqtt.retain && mqtt.broker.tls);
+        assert!(mqtt.broker.ca_file.is_none());
+        assert_eq!(mqtt.broker.username.as_deref(), Some("gateway"));
+        Ok(())
+    }
+
+    #[cfg(feature = "mqtt-ingest")]
+    #[test]
+    fn test_deserialize_settings_mqtt_ingest() -> Result<()> {
+        let toml_content = base_toml(
+            r#"
+            [mqtt_ingest]
+            host = "mqtt.meshtastic.org"
+            port = 1883
+            client_id = "gateway-ingest"
+
+            [mqtt_ingest.channels]
+            LongFast = "AQ=="
+        "#,
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        let ingest = settings
+            .mqtt_ingest
+            .context("mqtt_ingest section should be parsed")?;
+        assert_eq!(ingest.broker.port, 1883);
+        assert!(!ingest.broker.tls);
+        assert_eq!(ingest.topics, vec!["msh/#"]);
+        assert_eq!(
+            ingest.channels.get("LongFast").map(String::as_str),
+            Some("AQ==")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_settings_rules() -> Result<()> {
+        let toml_content = base_toml(
+            r#"
+            [[rules]]
+            name = "greenhouse_hot"
+            field = "environment.temperature"
+            comparator = ">"
+            threshold = 35.0
+            hysteresis = 2.5
+            nodes = [305419896]
+
+            [[rules]]
+            name = "busy_channel"
+            field = "local_stats.channel_utilization"
+            comparator = ">="
+            threshold = 40
+        "#,
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        assert_eq!(settings.rules.len(), 2);
+        assert_eq!(settings.rules[0].comparator, Comparator::Above);
+        assert_eq!(settings.rules[0].nodes, vec![0x1234_5678]);
+        assert_eq!(settings.rules[1].comparator, Comparator::AtLeast);
+        assert!(settings.rules[1].nodes.is_empty());
+        Ok(())
+    }
+
+    #[cfg(feature = "influxdb")]
+    #[test]
+    fn test_deserialize_settings_influxdb() -> Result<()> {
+        let toml_content = base_toml(
+            r#"
+            [influxdb]
+            url = "http://127.0.0.1:8086"
+            bucket = "meshtastic"
+            org = "lab"
+            token = "secret"
+            batch_size = 500
+            flush_interval_secs = 10
+        "#,
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        let influxdb = settings
+            .influxdb
+            .context("influxdb section should be parsed")?;
+        assert_eq!(influxdb.bucket, "meshtastic");
+        assert_eq!(influxdb.org.as_deref(), Some("lab"));
+        assert_eq!(influxdb.batch_size, 500);
+        assert!(!influxdb.replace_postgres);
+        Ok(())
+    }
+
+    #[cfg(feature = "api")]
+    #[test]
+    fn test_deserialize_settings_api() -> Result<()> {
+        let toml_content = base_toml(
+            r#"
+            [api]
+            bind = "0.0.0.0:8080"
+        "#,
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        let api = settings.api.context("api section should be parsed")?;
+        assert_eq!(api.bind, "0.0.0.0:8080");
+        Ok(())
+    }
+
+    #[cfg(feature = "ndjson")]
+    #[test]
+    fn test_deserialize_settings_ndjson() -> Result<()> {
+        let toml_content = base_toml(
+            r#"
+            [ndjson]
+            dir = "/var/lib/meshtastic/packets"
+            rotate_bytes = 104857600
+            gzip = true
+        "#,
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        let ndjson = settings.ndjson.context("ndjson section should be parsed")?;
+        assert_eq!(ndjson.rotate_bytes, Some(104_857_600));
+        assert!(ndjson.rotate_daily);
+        assert!(ndjson.gzip);
+        assert!(!ndjson.replace_postgres);
+        Ok(())
+    }
+
+    #[cfg(feature = "ndjson")]
+    #[tokio::test]
+    async fn ndjson_replacing_postgres_skips_the_connection() -> Result<()> {
+        let toml_content = base_toml(
+            r#"
+            [ndjson]
+            dir = "/var/lib/meshtastic/packets"
+            replace_postgres = true
+        "#,
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        assert!(settings.setup_daemon_postgres().await?.is_none());
+        Ok(())
+    }
+
+    #[cfg(feature = "influxdb")]
+    #[test]
+    fn influxdb_can_replace_postgres() -> Result<()> {
+        let toml_content = base_toml(
+            r#"
+            [influxdb]
+            url = "http://localhost:8086"
+            bucket = "meshtastic"
+            batch_size = 500
+            flush_interval_secs = 10
+            replace_postgres = true
+        "#,
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        assert!(settings.replaces_postgres());
+        Ok(())
+    }
+
+    #[cfg(feature = "ndjson")]
+    #[test]
+    fn test_deserialize_settings_replaced_postgres() -> Result<()> {
+        let toml_content = r#"
+            [serial]
+            port = "/dev/ttyUSB0"
+
+            [deployment]
+            location = "Portland Gateway"
+
+            [ndjson]
+            dir = "/var/lib/meshtastic/packets"
+            replace_postgres = true
+        "#;
+
+        let config = Config::builder()
+            .add_source(File::from_str(toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        assert!(settings.postgres.is_none());
+        assert_eq!(settings.get_max_connections(), MAX_INFLIGHT_TASKS);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_settings_without_alerts() -> Result<()> {
+        let toml_content = base_toml("");
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        assert!(settings.alerts.is_none());
+        assert!(settings.requests.is_none());
+        assert!(settings.gateway_stats.is_none());
+        assert!(settings.range_test.is_none());
+        assert!(settings.topology.is_none());
+        assert!(settings.rules.is_empty());
+        #[cfg(feature = "webhook")]
+        assert!(settings.webhook.is_none());
+        #[cfg(feature = "mqtt")]
+        assert!(settings.mqtt.is_none());
+        #[cfg(feature = "mqtt-ingest")]
+        assert!(settings.mqtt_ingest.is_none());
+        #[cfg(feature = "influxdb")]
+        assert!(settings.influxdb.is_none());
+        #[cfg(feature = "api")]
+        assert!(settings.api.is_none());
+        #[cfg(feature = "ndjson")]
+        assert!(settings.ndjson.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn requests_on_a_secondary_channel_are_rejected() -> Result<()> {
+        let toml_content = base_toml(
+            r"
+            [requests]
+            channel = 1
+            min_gap_secs = 30
+            max_per_hour = 20
+        ",
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Result<Settings, _> = config.try_deserialize();
+        assert!(
+            settings.is_err(),
+            "Should fail when requests are on another channel"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_settings_requests() -> Result<()> {
+        let toml_content = base_toml(
+            r#"
+            [requests]
+            min_gap_secs = 30
+            max_per_hour = 20
+
+            [[requests.schedule]]
+            nodes = [305419896]
+            requests = ["environment", "local_stats", "position"]
+            every_secs = 3600
+            offset_secs = 300
+
+            [requests.traceroute]
+            every_secs = 900
+        "#,
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        let requests = settings
+            .requests
+            .context("requests section should be parsed")?;
+        assert_eq!(requests.channel, 0);
+        assert_eq!(requests.max_per_hour, 20);
+        let schedule = requests.schedule.first().context("schedule entry")?;
+        assert_eq!(
+            schedule.requests,
+            [
+                RequestKind::Environment,
+                RequestKind::LocalStats,
+                RequestKind::Position
+            ]
+        );
+        assert_eq!(schedule.offset_secs, 300);
+        let traceroute = requests.traceroute.context("traceroute section")?;
+        assert_eq!(traceroute.every_secs, 900);
+        assert!(traceroute.nodes.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_settings_gateway_stats() -> Result<()> {
+        let toml_content = base_toml(
+            "
+            [gateway_stats]
+            interval_secs = 300
+        ",
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        let stats = settings
+            .gateway_stats
+            .context("gateway_stats section should be parsed")?;
+        assert_eq!(stats.interval_secs, 300);
+        assert_eq!(stats.disk_path, "/");
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_settings_topology() -> Result<()> {
+        let toml_content = base_toml(
+            "
+            [topology]
+            interval_secs = 3600
+        ",
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        let topology = settings
+            .topology
+            .context("topology section should be parsed")?;
+        assert_eq!(topology.interval_secs, 3600);
+        assert_eq!(topology.max_age_secs, 28_800);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_settings_range_test() -> Result<()> {
+        let toml_content = base_toml(
+            "
+            [range_test]
+            latitude = 45.5152
+            longitude = -122.6784
+        ",
+        );
+
+        let config = Config::builder()
+            .add_source(File::from_str(&toml_content, FileFormat::Toml))
+            .build()?;
+
+        let settings: Settings = config.try_deserialize()?;
+        let range_test = settings
+            .range_test
+            .context("range_test section should be parsed")?;
+        assert_eq!(range_test.latitude, Some(45.5152));
+        assert_eq!(range_test.longitude, Some(-122.6784));
+        Ok(())
+    }
 }
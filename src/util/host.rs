@@ -0,0 +1,150 @@
+use crate::{dto::dbops::gatewaystats, util::state::GatewayState};
+use rustix::fs::statvfs;
+use sqlx::{Pool, Postgres};
+use std::{fs, sync::Arc, time::Duration};
+use tokio::time::{Instant, MissedTickBehavior, interval};
+
+/// Seconds since boot, the first field of the file
+const UPTIME: &str = "/proc/uptime";
+/// Load averages over 1, 5 and 15 minutes, the first three fields of the file
+const LOADAVG: &str = "/proc/loadavg";
+/// Memory totals in kB, one `Key: value kB` line each
+const MEMINFO: &str = "/proc/meminfo";
+/// Temperature of the first thermal zone in millidegrees Celsius, usually the CPU die
+const CPU_TEMP: &str = "/sys/class/thermal/thermal_zone0/temp";
+
+/// Readings of the host the daemon runs on, `None` where the host does not expose one
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct HostStats {
+    /// Seconds since the host booted
+    pub(crate) uptime_secs: Option<i64>,
+    /// CPU temperature in °C
+    pub(crate) cpu_temp: Option<f32>,
+    /// Load averages over 1, 5 and 15 minutes
+    pub(crate) load: Option<[f32; 3]>,
+    /// Total memory in kB
+    pub(crate) mem_total_kb: Option<i64>,
+    /// Memory available to new processes in kB
+    pub(crate) mem_available_kb: Option<i64>,
+    /// Bytes free to unprivileged users on the reported filesystem
+    pub(crate) disk_free_bytes: Option<i64>,
+    /// Size of the reported filesystem in bytes
+    pub(crate) disk_total_bytes: Option<i64>,
+}
+
+impl HostStats {
+    /// Reads the host readings, `disk_path` names the filesystem whose space is reported
+    pub(crate) fn read(disk_path: &str) -> Self {
+        let file = |path| fs::read_to_string(path).ok();
+        let meminfo = file(MEMINFO);
+        let (disk_free_bytes, disk_total_bytes) = statvfs(disk_path).map_or_else(
+            |e| {
+                tracing::debug!(%e, disk_path, "statvfs failed");
+                (None, None)
+            },
+            |s| {
+                let bytes = |blocks: u64| i64::try_from(blocks.saturating_mul(s.f_frsize)).ok();
+                (bytes(s.f_bavail), bytes(s.f_blocks))
+            },
+        );
+        Self {
+            uptime_secs: file(UPTIME).as_deref().and_then(uptime),
+            cpu_temp: file(CPU_TEMP).as_deref().and_then(cpu_temp),
+            load: file(LOADAVG).as_deref().and_then(load),
+            mem_total_kb: meminfo.as_deref().and_then(|m| mem_kb(m, "MemTotal")),
+            mem_available_kb: meminfo.as_deref().and_then(|m| mem_kb(m, "MemAvailable")),
+            disk_free_bytes,
+            disk_total_bytes,
+        }
+    }
+}
+
+/// Whole seconds of `/proc/uptime`
+fn uptime(content: &str) -> Option<i64> {
+    let secs = content.split_whitespace().next()?;
+    secs.split_once('.')
+        .map_or(secs, |(whole, _)| whole)
+        .parse()
+        .ok()
+}
+
+/// Degrees Celsius from the millidegrees of a thermal zone
+#[expect(
+    clippy::cast_precision_loss,
+    reason = "Millidegrees of a CPU stay far below 2^24"
+)]
+fn cpu_temp(content: &str) -> Option<f32> {
+    let milli: i32 = content.trim().parse().ok()?;
+    Some(milli as f32 / 1000.0)
+}
+
+/// The three load averages of `/proc/loadavg`
+fn load(content: &str) -> Option<[f32; 3]> {
+    let mut fields = content.split_whitespace().map(str::parse);
+    Some([
+        fields.next()?.ok()?,
+        fields.next()?.ok()?,
+        fields.next()?.ok()?,
+    ])
+}
+
+/// Value in kB of the `key` line of `/proc/meminfo`
+fn mem_kb(content: &str, key: &str) -> Option<i64> {
+    content.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name != key {
+            return None;
+        }
+        value.trim().trim_end_matches("kB").trim_end().parse().ok()
+    })
+}
+
+/// Periodically records host and daemon stats as a `GatewayStats` row
+pub(crate) async fn report(
+    state: Arc<GatewayState>,
+    pool: Pool<Postgres>,
+    every: Duration,
+    disk_path: String,
+) {
+    let started = Instant::now();
+    let mut ticker = interval(every);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+        let host = HostStats::read(&disk_path);
+        let daemon_uptime = started.elapsed();
+        if let Err(e) = gatewaystats::insert(&state, &host, daemon_uptime, &pool).await {
+            state.db_error();
+            tracing::error!(%e, table = "GatewayStats", "insert failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proc_files_are_parsed() {
+        assert_eq!(uptime("350735.47 234388.90\n"), Some(350_735));
+        assert_eq!(uptime("12 3\n"), Some(12));
+        assert_eq!(cpu_temp("48312\n"), Some(48.312));
+        assert_eq!(
+            load("0.52 0.58 0.59 1/467 12345\n"),
+            Some([0.52, 0.58, 0.59])
+        );
+        let meminfo = "MemTotal:        1014624 kB\nMemFree:          160228 kB\n\
+                       MemAvailable:     641576 kB\n";
+        assert_eq!(mem_kb(meminfo, "MemTotal"), Some(1_014_624));
+        assert_eq!(mem_kb(meminfo, "MemAvailable"), Some(641_576));
+    }
+
+    #[test]
+    fn missing_readings_are_none() {
+        assert_eq!(uptime(""), None);
+        assert_eq!(cpu_temp("N/A"), None);
+        assert_eq!(load("0.52 0.58"), None);
+        assert_eq!(mem_kb("MemTotal: 1014624 kB\n", "MemAvailable"), None);
+    }
+}
@@ -1,9 +1,17 @@
 use chrono::{DateTime, NaiveDateTime, Utc};
+use std::time::Duration;
 
 /// Config file interaction module
 pub(crate) mod config;
+/// Host readings and the periodic gateway self-report
+pub(crate) mod host;
 /// Set logger for CLI module
 pub(crate) mod log;
+/// Broker connection options shared by the MQTT sink and ingest
+#[cfg(feature = "mqtt")]
+pub(crate) mod mqtt;
+/// Parsing of node ids, times and lists given on the command line or in requests
+pub(crate) mod parse;
 /// Local state of the program (necessary evil due to requests for features)
 pub(crate) mod state;
 
@@ -33,6 +41,34 @@ pub(crate) fn timestamp(epoch: u32) -> NaiveDateTime {
     }
 }
 
+/// Create a timestamp from the first valid of several epochs, e.g. a payload's own time before
+/// the packet's receive time
+///
+/// Replayed packets are received long after they were sent, so the time a payload carries is
+/// preferred over the time it arrived.
+pub(crate) fn first_timestamp(epochs: &[u32]) -> NaiveDateTime {
+    epochs
+        .iter()
+        .find(|e| **e > MIN_VALID_EPOCH)
+        .map_or_else(|| Utc::now().naive_utc(), |e| timestamp(*e))
+}
+
+/// Period of a configured interval timer, at least a second
+///
+/// `tokio::time::interval` panics on a zero period, so a `0` in the config ticks every second
+/// instead of taking the daemon down.
+pub(crate) const fn tick_interval(secs: u64) -> Duration {
+    Duration::from_secs(if secs == 0 { 1 } else { secs })
+}
+
+/// Name of a protobuf enum value, values newer than the protobufs are kept as their number
+pub(crate) fn enum_name<E: TryFrom<i32>>(
+    value: i32,
+    as_str_name: impl Fn(&E) -> &'static str,
+) -> String {
+    E::try_from(value).map_or_else(|_| value.to_string(), |e| as_str_name(&e).to_owned())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -81,4 +117,40 @@ mod tests {
         let dt = timestamp(u32::MAX);
         assert_eq!(dt.and_utc().timestamp(), i64::from(u32::MAX));
     }
+
+    #[test]
+    fn tick_interval_is_at_least_a_second() {
+        for (secs, expected) in [(0, 1), (1, 1), (300, 300)] {
+            assert_eq!(
+                tick_interval(secs),
+                Duration::from_secs(expected),
+                "{secs} seconds"
+            );
+        }
+    }
+
+    #[test]
+    fn first_valid_epoch_wins() -> Result<()> {
+        let dt = first_timestamp(&[0, 1_735_689_700, 1_735_689_800]);
+        assert_eq!(
+            dt,
+            DateTime::from_timestamp(1_735_689_700, 0)
+                .ok_or(anyhow!("Error creating DateTime"))?
+                .naive_utc()
+        );
+        let before = Utc::now().naive_utc();
+        assert!(first_timestamp(&[0, 12]) >= before);
+        Ok(())
+    }
+
+    #[test]
+    fn enum_values_are_named() {
+        use meshtastic::protobufs::config::{
+            device_config::Role,
+            lo_ra_config::{ModemPreset, RegionCode},
+        };
+        assert_eq!(enum_name(2, Role::as_str_name), "ROUTER");
+        assert_eq!(enum_name(1, RegionCode::as_str_name), "US");
+        assert_eq!(enum_name(99, ModemPreset::as_str_name), "99");
+    }
 }
@@ -0,0 +1,61 @@
+use crate::util::config::MqttBroker;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use anyhow::Context as _;
+use anyhow::Result;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use rumqttc::TlsConfiguration;
+use rumqttc::{MqttOptions, Transport};
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use std::fs;
+use std::time::Duration;
+
+/// Keep alive interval of broker connections
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// TLS transport using the CA certificate from the config, or the system roots
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+fn transport(broker: &MqttBroker) -> Result<Transport> {
+    let ca = broker
+        .ca_file
+        .as_ref()
+        .map(fs::read)
+        .transpose()
+        .context("Failed to read MQTT CA certificate")?;
+    #[cfg(feature = "native-tls")]
+    let tls = match ca {
+        Some(ca) => TlsConfiguration::SimpleNative {
+            ca,
+            client_auth: None,
+        },
+        None => TlsConfiguration::Native,
+    };
+    #[cfg(not(feature = "native-tls"))]
+    let tls = match ca {
+        Some(ca) => TlsConfiguration::Simple {
+            ca,
+            alpn: None,
+            client_auth: None,
+        },
+        None => TlsConfiguration::default(),
+    };
+    Ok(Transport::tls_with_config(tls))
+}
+
+/// TLS transport, unavailable without a TLS feature
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+fn transport(_broker: &MqttBroker) -> Result<Transport> {
+    anyhow::bail!("MQTT over TLS needs the native-tls or rustls feature")
+}
+
+/// Client options for a configured broker connection
+pub(crate) fn options(broker: &MqttBroker) -> Result<MqttOptions> {
+    let mut options = MqttOptions::new(&broker.client_id, &broker.host, broker.port);
+    options.set_keep_alive(KEEP_ALIVE);
+    if let Some(username) = &broker.username {
+        options.set_credentials(username, broker.password.as_deref().unwrap_or_default());
+    }
+    if broker.tls {
+        options.set_transport(transport(broker)?);
+    }
+    Ok(options)
+}
@@ -0,0 +1,68 @@
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+
+/// Parses a node number given as decimal or as a `!` prefixed hex node id
+pub(crate) fn node_id(s: &str) -> Result<u32> {
+    match s.strip_prefix('!') {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .with_context(|| format!("invalid node id {s}"))
+}
+
+/// Parses a time given as Unix seconds, RFC 3339 or a UTC date
+pub(crate) fn time(s: &str) -> Result<NaiveDateTime> {
+    if let Ok(secs) = s.parse::<i64>() {
+        return DateTime::from_timestamp(secs, 0)
+            .map(|dt| dt.naive_utc())
+            .with_context(|| format!("time {s} out of range"));
+    }
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.naive_utc());
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map(|d| d.and_time(NaiveTime::MIN))
+        .with_context(|| format!("invalid time {s}, expected Unix seconds, RFC 3339 or YYYY-MM-DD"))
+}
+
+/// Entries of a comma separated list
+pub(crate) fn list(param: Option<&str>) -> impl Iterator<Item = &str> {
+    param
+        .into_iter()
+        .flat_map(|p| p.split(','))
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_ids_are_decimal_or_hex() -> Result<()> {
+        assert_eq!(node_id("305419896")?, 0x1234_5678);
+        assert_eq!(node_id("!12345678")?, 0x1234_5678);
+        assert_eq!(node_id("!xyz").ok(), None);
+        assert_eq!(node_id("-1").ok(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn times_accept_epochs_rfc3339_and_dates() -> Result<()> {
+        let expected = DateTime::from_timestamp(1_760_000_000, 0)
+            .context("out of range")?
+            .naive_utc();
+        assert_eq!(time("1760000000")?, expected);
+        assert_eq!(time("2025-10-09T10:53:20+02:00")?, expected);
+        assert_eq!(time("2025-10-09T08:53:20Z")?, expected);
+        assert_eq!(time("2025-10-09")?.to_string(), "2025-10-09 00:00:00");
+        assert_eq!(time("yesterday").ok(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn lists_skip_blank_entries() {
+        assert_eq!(list(Some(" a, ,b,")).collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(list(None).count(), 0);
+    }
+}
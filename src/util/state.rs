@@ -1,16 +1,22 @@
-use crate::util::config::DEPLOYMENT_LOCATION;
+use crate::{
+    dto::radio::RadioSnapshot,
+    topology::{Graph, Link, Topology},
+    util::config::DEPLOYMENT_LOCATION,
+};
 use anyhow::{Error, Result};
-use meshtastic::protobufs::User;
+use chrono::Utc;
+use meshtastic::protobufs::{User, from_radio::PayloadVariant};
 use sqlx::{Pool, Postgres};
 use std::{
     collections::{
-        HashMap,
+        HashMap, HashSet, VecDeque,
         hash_map::Entry::{Occupied, Vacant},
     },
     fmt::{self, Display, Formatter},
+    mem,
     sync::{
-        PoisonError, RwLock,
-        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering::Relaxed},
+        Mutex, PoisonError, RwLock,
+        atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, AtomicUsize, Ordering::Relaxed},
     },
 };
 
@@ -27,6 +33,61 @@ pub(crate) struct NodeMeta {
     id: String,
     /// Number of received packets
     rx_count: AtomicUsize,
+    /// Unix time of the last received packet, or of the node joining the state
+    last_heard: AtomicI64,
+    /// Whether the node was already reported offline since it was last heard
+    offline: AtomicBool,
+}
+
+/// Number of packets remembered to drop copies, enough to cover a store-and-forward history
+const RECENT_PACKETS: usize = 16_384;
+
+/// Packets seen recently, by sender and packet id
+#[derive(Debug)]
+pub(crate) struct RecentPackets {
+    /// Lookup of the remembered packets
+    seen: HashSet<(u32, u32)>,
+    /// Remembered packets, oldest first
+    order: VecDeque<(u32, u32)>,
+    /// Most packets remembered
+    capacity: usize,
+}
+
+impl RecentPackets {
+    /// Remembers up to `capacity` packets
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Remembers a packet, returning whether it was new
+    pub(crate) fn insert(&mut self, from: u32, id: u32) -> bool {
+        if !self.seen.insert((from, id)) {
+            return false;
+        }
+        self.order.push_back((from, id));
+        if self.order.len() > self.capacity
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.seen.remove(&oldest);
+        }
+        true
+    }
+}
+
+/// Milliseconds after which an unanswered traceroute is forgotten
+const PROBE_TIMEOUT_MS: i64 = 10 * 60 * 1000;
+
+/// A traceroute sent by the gateway and waiting for its reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Probe {
+    /// Node the traceroute was sent to
+    pub(crate) node_id: u32,
+    /// Unix time in milliseconds the traceroute was sent at
+    pub(crate) sent_ms: i64,
 }
 
 /// We need some state information for the serial vs mesh packet resolution of conflicts
@@ -39,6 +100,18 @@ pub(crate) struct GatewayState {
     serial_node: AtomicU32,
     /// Any packets received yet?
     any_recv: AtomicBool,
+    /// Mesh packets handled since the daemon started, from known and unknown nodes
+    processed: AtomicU64,
+    /// Failed database queries since the daemon started
+    db_errors: AtomicU64,
+    /// Traceroutes awaiting their reply, by the id of the request packet
+    probes: Mutex<HashMap<u32, Probe>>,
+    /// Mesh packets handled recently, replays of them are dropped
+    recent: Mutex<RecentPackets>,
+    /// Configuration frames of the serial node since the last complete configuration
+    radio: Mutex<RadioSnapshot>,
+    /// Latest neighbor report of every node
+    topology: Mutex<Topology>,
 }
 
 impl Default for GatewayState {
@@ -48,6 +121,12 @@ impl Default for GatewayState {
             nodes: RwLock::new(HashMap::new()),
             serial_node: AtomicU32::new(0),
             any_recv: AtomicBool::new(false),
+            processed: AtomicU64::new(0),
+            db_errors: AtomicU64::new(0),
+            probes: Mutex::new(HashMap::new()),
+            recent: Mutex::new(RecentPackets::new(RECENT_PACKETS)),
+            radio: Mutex::new(RadioSnapshot::default()),
+            topology: Mutex::new(Topology::default()),
         }
     }
 }
@@ -89,9 +168,10 @@ impl GatewayState {
         Self::default()
     }
 
-    /// Increment the `rx_count` of a given node
+    /// Increment the `rx_count` of a given node and mark it as heard from now
     pub(crate) fn increment_count(&self, node_id: u32) -> bool {
-        // Lock is only held for an atomic instruction, so it is short
+        self.processed.fetch_add(1, Relaxed);
+        // Lock is only held for a few atomic instructions, so it is short
         if let Some(n) = self
             .nodes
             .read()
@@ -99,12 +179,153 @@ impl GatewayState {
             .get(&node_id)
         {
             n.rx_count.fetch_add(1, Relaxed);
+            n.last_heard.store(Utc::now().timestamp(), Relaxed);
+            n.offline.store(false, Relaxed);
             self.any_recv.store(true, Relaxed);
             return true;
         }
         false
     }
 
+    /// Returns `(node_id, long_name, last_heard)` for every node not heard from since `cutoff`
+    /// that has not been reported yet, and marks those nodes as reported until heard again
+    pub(crate) fn newly_offline(&self, cutoff: i64) -> Vec<(u32, String, i64)> {
+        self.nodes
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter_map(|(id, n)| {
+                let last_heard = n.last_heard.load(Relaxed);
+                (last_heard < cutoff && !n.offline.swap(true, Relaxed))
+                    .then(|| (*id, n.long_name.clone(), last_heard))
+            })
+            .collect()
+    }
+
+    /// Returns the short name of a known node
+    #[cfg_attr(
+        not(feature = "influxdb"),
+        expect(dead_code, reason = "only used to tag InfluxDB points")
+    )]
+    pub(crate) fn short_name(&self, node_id: u32) -> Option<String> {
+        self.nodes
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&node_id)
+            .map(|n| n.short_name.clone())
+    }
+
+    /// Returns the received packet count and the last heard Unix time of a known node
+    #[cfg_attr(
+        not(feature = "api"),
+        expect(dead_code, reason = "only served by the API")
+    )]
+    pub(crate) fn activity(&self, node_id: u32) -> Option<(usize, i64)> {
+        self.nodes
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&node_id)
+            .map(|n| (n.rx_count.load(Relaxed), n.last_heard.load(Relaxed)))
+    }
+
+    /// Returns the number of mesh packets handled since the daemon started
+    pub(crate) fn packets_processed(&self) -> u64 {
+        self.processed.load(Relaxed)
+    }
+
+    /// Counts a failed database query
+    pub(crate) fn db_error(&self) {
+        self.db_errors.fetch_add(1, Relaxed);
+    }
+
+    /// Returns the number of failed database queries since the daemon started
+    pub(crate) fn db_errors(&self) -> u64 {
+        self.db_errors.load(Relaxed)
+    }
+
+    /// Returns the numbers of every known node besides the serial node, in ascending order
+    pub(crate) fn node_ids(&self) -> Vec<u32> {
+        let serial = self.serial_node.load(Relaxed);
+        let mut ids = self
+            .nodes
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .copied()
+            .filter(|id| *id != serial)
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Remembers a traceroute sent with packet id `id`, forgetting the ones that timed out
+    pub(crate) fn probe_sent(&self, id: u32, probe: Probe) {
+        let mut probes = self.probes.lock().unwrap_or_else(PoisonError::into_inner);
+        probes.retain(|id, p| {
+            let live = p.sent_ms > probe.sent_ms - PROBE_TIMEOUT_MS;
+            if !live {
+                tracing::debug!(
+                    request_id = id,
+                    node_id = p.node_id,
+                    "traceroute unanswered"
+                );
+            }
+            live
+        });
+        probes.insert(id, probe);
+    }
+
+    /// Takes the traceroute a reply from `node_id` to request `request_id` answers
+    pub(crate) fn probe_answered(&self, request_id: u32, node_id: u32) -> Option<Probe> {
+        let mut probes = self.probes.lock().unwrap_or_else(PoisonError::into_inner);
+        match probes.entry(request_id) {
+            Occupied(e) if e.get().node_id == node_id => Some(e.remove()),
+            _ => None,
+        }
+    }
+
+    /// Returns whether a mesh packet is seen for the first time
+    ///
+    /// Store-and-forward routers replay packets with their original sender and id, the copy of
+    /// a packet already handled is dropped. Packets without an id are always new.
+    pub(crate) fn first_sighting(&self, from: u32, id: u32) -> bool {
+        id == 0
+            || self
+                .recent
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(from, id)
+    }
+
+    /// Keeps a configuration frame the serial node sent while being configured
+    pub(crate) fn radio_frame(&self, frame: &PayloadVariant) {
+        self.radio
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .add(frame);
+    }
+
+    /// Takes the configuration frames kept since the last call
+    pub(crate) fn take_radio(&self) -> RadioSnapshot {
+        mem::take(&mut *self.radio.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Keeps the neighbors of a `NeighborInfo` report, replacing an older report of the node
+    pub(crate) fn neighbor_report(&self, reporter: u32, time: i64, links: Vec<Link>) {
+        self.topology
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .report(reporter, time, links);
+    }
+
+    /// Links of the neighbor reports since the Unix time `cutoff`
+    pub(crate) fn topology(&self, cutoff: i64) -> Graph {
+        self.topology
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .graph(cutoff)
+    }
+
     /// Returns whether any packets were received since the last call, then resets the flag.
     #[inline]
     pub(crate) fn any_recvd(&self) -> bool {
@@ -117,8 +338,23 @@ impl GatewayState {
         self.serial_node.store(num, Relaxed);
     }
 
+    /// Node number of the locally-connected serial device, 0 until it reported itself.
+    #[inline]
+    pub(crate) fn serial_number(&self) -> u32 {
+        self.serial_node.load(Relaxed)
+    }
+
     /// Insert a new node into the state
     pub(crate) fn insert(&self, node_id: u32, user: &User) -> Result<()> {
+        if self.update(node_id, user) {
+            Ok(())
+        } else {
+            Err(Error::msg("Node already in state"))
+        }
+    }
+
+    /// Insert a new node into the state or update a known one, returning whether it changed
+    pub(crate) fn update(&self, node_id: u32, user: &User) -> bool {
         match self
             .nodes
             .write()
@@ -132,8 +368,10 @@ impl GatewayState {
                     hw_model: user.hw_model,
                     id: user.id.clone(),
                     rx_count: AtomicUsize::new(0),
+                    last_heard: AtomicI64::new(Utc::now().timestamp()),
+                    offline: AtomicBool::new(false),
                 });
-                Ok(())
+                true
             }
             Occupied(mut e) => {
                 let n = e.get_mut();
@@ -141,12 +379,12 @@ impl GatewayState {
                     && n.short_name == user.short_name
                     && n.hw_model == user.hw_model
                 {
-                    return Err(Error::msg("Node already in state"));
+                    return false;
                 }
                 n.long_name.clone_from(&user.long_name);
                 n.short_name.clone_from(&user.short_name);
                 n.hw_model = user.hw_model;
-                Ok(())
+                true
             }
         }
     }
@@ -223,6 +461,24 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn processed_counts_known_and_unknown_nodes() -> Result<()> {
+        let state = GatewayState::new();
+        state.insert(1, &test_user("TestNode", "TN"))?;
+        state.increment_count(1);
+        state.increment_count(0xDEAD_BEEF);
+        assert_eq!(state.packets_processed(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn db_errors_are_counted() {
+        let state = GatewayState::new();
+        state.db_error();
+        state.db_error();
+        assert_eq!(state.db_errors(), 2);
+    }
+
     #[test]
     fn any_recvd_false_when_no_packets() {
         let state = GatewayState::new();
@@ -265,6 +521,19 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn update_reports_whether_the_node_changed() {
+        let state = GatewayState::new();
+        assert!(state.update(1, &test_user("NodeA", "NA")));
+        assert!(!state.update(1, &test_user("NodeA", "NA"))); // no change
+        assert!(state.update(1, &test_user("NodeB", "NA")));
+        let user = User {
+            hw_model: 9,
+            ..test_user("NodeB", "NA")
+        };
+        assert!(state.update(1, &user));
+    }
+
     #[test]
     fn serial_number_roundtrip() -> Result<()> {
         let state = GatewayState::new();
@@ -276,6 +545,14 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn serial_number_defaults_to_zero_until_set() {
+        let state = GatewayState::new();
+        assert_eq!(state.serial_number(), 0);
+        state.set_serial_number(42);
+        assert_eq!(state.serial_number(), 42);
+    }
+
     #[test]
     fn increment_does_not_set_flag_for_unknown_node() {
         let state = GatewayState::new();
@@ -307,6 +584,35 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn newly_offline_reports_quiet_nodes_once() -> Result<()> {
+        let state = GatewayState::new();
+        state.insert(1, &test_user("Quiet", "QT"))?;
+        let future = Utc::now().timestamp() + 60;
+
+        let offline = state.newly_offline(future);
+        assert_eq!(offline.len(), 1);
+        assert_eq!(offline[0].0, 1);
+        assert_eq!(offline[0].1, "Quiet");
+
+        // Already reported, so it is not reported a second time
+        assert!(state.newly_offline(future).is_empty());
+
+        // Hearing from the node again re-arms the report
+        state.increment_count(1);
+        assert_eq!(state.newly_offline(future + 60).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn newly_offline_ignores_recently_heard_nodes() -> Result<()> {
+        let state = GatewayState::new();
+        state.insert(1, &test_user("Chatty", "CH"))?;
+        state.increment_count(1);
+        assert!(state.newly_offline(Utc::now().timestamp() - 60).is_empty());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn concurrent_increments_are_thread_safe() -> Result<()> {
         use std::sync::Arc;
@@ -339,4 +645,57 @@ mod tests {
         assert!(state.any_recvd());
         Ok(())
     }
+
+    #[test]
+    fn node_ids_skip_the_serial_node() -> Result<()> {
+        let state = GatewayState::new();
+        for id in [3, 1, 2] {
+            state.insert(id, &test_user("Node", "N"))?;
+        }
+        state.set_serial_number(2);
+        assert_eq!(state.node_ids(), [1, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn probes_match_their_reply_once_and_expire() {
+        let state = GatewayState::new();
+        let probe = |node_id, sent_ms| Probe { node_id, sent_ms };
+        state.probe_sent(10, probe(1, 0));
+        state.probe_sent(11, probe(2, 1000));
+        // A reply from another node does not answer the probe
+        assert_eq!(state.probe_answered(11, 1), None);
+        assert_eq!(state.probe_answered(11, 2), Some(probe(2, 1000)));
+        assert_eq!(state.probe_answered(11, 2), None);
+        // Sending past the timeout forgets the unanswered probe
+        state.probe_sent(12, probe(3, PROBE_TIMEOUT_MS));
+        assert_eq!(state.probe_answered(10, 1), None);
+        assert_eq!(
+            state.probe_answered(12, 3),
+            Some(probe(3, PROBE_TIMEOUT_MS))
+        );
+    }
+
+    #[test]
+    fn recent_packets_are_bounded() {
+        let mut recent = RecentPackets::new(4);
+        assert!(recent.insert(1, 1));
+        assert!(!recent.insert(1, 1));
+        assert!(recent.insert(2, 1));
+        for id in 0..4 {
+            recent.insert(3, id);
+        }
+        // Pushed out by the newer packets
+        assert!(recent.insert(1, 1));
+    }
+
+    #[test]
+    fn replayed_packets_are_not_new() {
+        let state = GatewayState::new();
+        assert!(state.first_sighting(7, 42));
+        assert!(!state.first_sighting(7, 42));
+        assert!(state.first_sighting(8, 42));
+        assert!(state.first_sighting(7, 0));
+        assert!(state.first_sighting(7, 0));
+    }
 }